@@ -0,0 +1,72 @@
+use super::{read_map, write_map};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, EntityId, ObjectMut, ObjectRef, SchemaType, Update,
+};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Delegates authority over sets of components to partitions.
+pub struct AuthorityDelegation {
+    /// The partition entity ID that each component set is delegated to, keyed by component set ID.
+    pub delegations: BTreeMap<u32, EntityId>,
+}
+
+impl SchemaType for AuthorityDelegation {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            delegations: read_map(object, 1, |entry, field_id| entry.get_int64(field_id)),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_map(object, 1, &self.delegations, |entry, field_id, value| {
+            entry.add_int64(field_id, value)
+        });
+    }
+}
+
+impl Component for AuthorityDelegation {
+    const ID: ComponentId = 65;
+    type Update = AuthorityDelegationUpdate;
+
+    fn apply_update(&mut self, update: &AuthorityDelegationUpdate) {
+        if let Some(delegations) = &update.delegations {
+            self.delegations = delegations.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AuthorityDelegationUpdate {
+    pub delegations: Option<BTreeMap<u32, EntityId>>,
+}
+
+impl Update for AuthorityDelegationUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let cleared = update.is_field_cleared(1);
+        let fields = update.get_fields();
+        Self {
+            delegations: if cleared || fields.get_object_count(1) > 0 {
+                Some(read_map(&fields, 1, |entry, field_id| {
+                    entry.get_int64(field_id)
+                }))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(delegations) = &self.delegations {
+            write_map(&mut fields, 1, delegations, |entry, field_id, value| {
+                entry.add_int64(field_id, value)
+            });
+            if delegations.is_empty() {
+                update.add_cleared_field(1);
+            }
+        }
+        update
+    }
+}
@@ -0,0 +1,119 @@
+use super::{
+    read_map, read_object, read_object_list, read_optional_object, write_map, write_object,
+    write_object_list,
+};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A set of worker attributes. A worker satisfies the set if it has every attribute in it.
+pub struct WorkerAttributeSet {
+    pub attribute: Vec<String>,
+}
+
+impl SchemaType for WorkerAttributeSet {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            attribute: object.get_string_list(1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_string_list(1, &self.attribute);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A set of attribute sets. A worker satisfies the requirement if it satisfies any of the attribute
+/// sets.
+pub struct WorkerRequirementSet {
+    pub attribute_set: Vec<WorkerAttributeSet>,
+}
+
+impl SchemaType for WorkerRequirementSet {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            attribute_set: read_object_list(object, 1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object_list(object, 1, &self.attribute_set);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Controls which workers can read an entity and which workers can be authoritative over each of
+/// its components.
+pub struct EntityAcl {
+    /// The workers allowed to see the entity.
+    pub read_acl: WorkerRequirementSet,
+    /// The workers allowed to be authoritative over each component, keyed by component ID.
+    pub component_write_acl: BTreeMap<ComponentId, WorkerRequirementSet>,
+}
+
+impl SchemaType for EntityAcl {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            read_acl: read_object(object, 1),
+            component_write_acl: read_map(object, 2, read_object),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.read_acl);
+        write_map(object, 2, &self.component_write_acl, write_object);
+    }
+}
+
+impl Component for EntityAcl {
+    const ID: ComponentId = 50;
+    type Update = EntityAclUpdate;
+
+    fn apply_update(&mut self, update: &EntityAclUpdate) {
+        if let Some(read_acl) = &update.read_acl {
+            self.read_acl = read_acl.clone();
+        }
+        if let Some(component_write_acl) = &update.component_write_acl {
+            self.component_write_acl = component_write_acl.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EntityAclUpdate {
+    pub read_acl: Option<WorkerRequirementSet>,
+    pub component_write_acl: Option<BTreeMap<ComponentId, WorkerRequirementSet>>,
+}
+
+impl Update for EntityAclUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let cleared = update.is_field_cleared(2);
+        let fields = update.get_fields();
+        Self {
+            read_acl: read_optional_object(&fields, 1),
+            component_write_acl: if cleared || fields.get_object_count(2) > 0 {
+                Some(read_map(&fields, 2, read_object))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(read_acl) = &self.read_acl {
+            write_object(&mut fields, 1, read_acl);
+        }
+        if let Some(component_write_acl) = &self.component_write_acl {
+            write_map(&mut fields, 2, component_write_acl, write_object);
+            if component_write_acl.is_empty() {
+                update.add_cleared_field(2);
+            }
+        }
+        update
+    }
+}
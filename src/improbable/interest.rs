@@ -0,0 +1,363 @@
+use super::{
+    read_map, read_object, read_object_list, read_optional_object, write_map, write_object,
+    write_object_list, Coordinates, EdgeLength,
+};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, EntityId, ObjectMut, ObjectRef, SchemaType, Update,
+};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A sphere centered on an absolute position.
+pub struct SphereConstraint {
+    pub center: Coordinates,
+    pub radius: f64,
+}
+
+impl SchemaType for SphereConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            center: read_object(object, 1),
+            radius: object.get_double(2),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.center);
+        object.add_double(2, self.radius);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A vertical cylinder centered on an absolute position.
+pub struct CylinderConstraint {
+    pub center: Coordinates,
+    pub radius: f64,
+}
+
+impl SchemaType for CylinderConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            center: read_object(object, 1),
+            radius: object.get_double(2),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.center);
+        object.add_double(2, self.radius);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// An axis-aligned box centered on an absolute position.
+pub struct BoxConstraint {
+    pub center: Coordinates,
+    pub edge_length: EdgeLength,
+}
+
+impl SchemaType for BoxConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            center: read_object(object, 1),
+            edge_length: read_object(object, 2),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.center);
+        write_object(object, 2, &self.edge_length);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A sphere centered on the position of the entity holding the interest.
+pub struct RelativeSphereConstraint {
+    pub radius: f64,
+}
+
+impl SchemaType for RelativeSphereConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            radius: object.get_double(1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_double(1, self.radius);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A vertical cylinder centered on the position of the entity holding the interest.
+pub struct RelativeCylinderConstraint {
+    pub radius: f64,
+}
+
+impl SchemaType for RelativeCylinderConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            radius: object.get_double(1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_double(1, self.radius);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// An axis-aligned box centered on the position of the entity holding the interest.
+pub struct RelativeBoxConstraint {
+    pub edge_length: EdgeLength,
+}
+
+impl SchemaType for RelativeBoxConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            edge_length: read_object(object, 1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.edge_length);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Matches the entity holding the interest.
+pub struct SelfConstraint {}
+
+impl SchemaType for SelfConstraint {
+    fn from_object(_object: &ObjectRef) -> Self {
+        Self {}
+    }
+
+    fn write_object(&self, _object: &mut ObjectMut) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Matches the entities which have every component of a component set.
+pub struct ComponentSetConstraint {
+    pub component_set_id: u32,
+}
+
+impl SchemaType for ComponentSetConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            component_set_id: object.get_uint32(1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_uint32(1, self.component_set_id);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A query constraint. Exactly one of the fields is expected to be set.
+pub struct QueryConstraint {
+    pub sphere_constraint: Option<SphereConstraint>,
+    pub cylinder_constraint: Option<CylinderConstraint>,
+    pub box_constraint: Option<BoxConstraint>,
+    pub relative_sphere_constraint: Option<RelativeSphereConstraint>,
+    pub relative_cylinder_constraint: Option<RelativeCylinderConstraint>,
+    pub relative_box_constraint: Option<RelativeBoxConstraint>,
+    pub entity_id_constraint: Option<EntityId>,
+    pub component_constraint: Option<ComponentId>,
+    pub and_constraint: Vec<QueryConstraint>,
+    pub or_constraint: Vec<QueryConstraint>,
+    pub self_constraint: Option<SelfConstraint>,
+    pub component_set_constraint: Option<ComponentSetConstraint>,
+}
+
+impl SchemaType for QueryConstraint {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            sphere_constraint: read_optional_object(object, 1),
+            cylinder_constraint: read_optional_object(object, 2),
+            box_constraint: read_optional_object(object, 3),
+            relative_sphere_constraint: read_optional_object(object, 4),
+            relative_cylinder_constraint: read_optional_object(object, 5),
+            relative_box_constraint: read_optional_object(object, 6),
+            entity_id_constraint: if object.get_entity_id_count(7) > 0 {
+                Some(object.get_entity_id(7))
+            } else {
+                None
+            },
+            component_constraint: if object.get_uint32_count(8) > 0 {
+                Some(object.get_uint32(8))
+            } else {
+                None
+            },
+            and_constraint: read_object_list(object, 9),
+            or_constraint: read_object_list(object, 10),
+            self_constraint: read_optional_object(object, 11),
+            component_set_constraint: read_optional_object(object, 12),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        if let Some(constraint) = &self.sphere_constraint {
+            write_object(object, 1, constraint);
+        }
+        if let Some(constraint) = &self.cylinder_constraint {
+            write_object(object, 2, constraint);
+        }
+        if let Some(constraint) = &self.box_constraint {
+            write_object(object, 3, constraint);
+        }
+        if let Some(constraint) = &self.relative_sphere_constraint {
+            write_object(object, 4, constraint);
+        }
+        if let Some(constraint) = &self.relative_cylinder_constraint {
+            write_object(object, 5, constraint);
+        }
+        if let Some(constraint) = &self.relative_box_constraint {
+            write_object(object, 6, constraint);
+        }
+        if let Some(entity_id) = self.entity_id_constraint {
+            object.add_entity_id(7, entity_id);
+        }
+        if let Some(component_id) = self.component_constraint {
+            object.add_uint32(8, component_id);
+        }
+        write_object_list(object, 9, &self.and_constraint);
+        write_object_list(object, 10, &self.or_constraint);
+        if let Some(constraint) = &self.self_constraint {
+            write_object(object, 11, constraint);
+        }
+        if let Some(constraint) = &self.component_set_constraint {
+            write_object(object, 12, constraint);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A query defining a set of entities and the components of those entities to receive.
+pub struct Query {
+    pub constraint: QueryConstraint,
+    /// Whether every component of the matching entities should be received. Either this,
+    /// result_component_id or result_component_set_id should be set.
+    pub full_snapshot_result: Option<bool>,
+    pub result_component_id: Vec<ComponentId>,
+    /// The maximum frequency, in Hz, at which updates are received for this query.
+    pub frequency: Option<f32>,
+    pub result_component_set_id: Vec<u32>,
+}
+
+impl SchemaType for Query {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            constraint: read_object(object, 1),
+            full_snapshot_result: if object.get_bool_count(2) > 0 {
+                Some(object.get_bool(2))
+            } else {
+                None
+            },
+            result_component_id: (0..object.get_uint32_count(3))
+                .map(|index| object.index_uint32(3, index))
+                .collect(),
+            frequency: if object.get_float_count(4) > 0 {
+                Some(object.get_float(4))
+            } else {
+                None
+            },
+            result_component_set_id: (0..object.get_uint32_count(5))
+                .map(|index| object.index_uint32(5, index))
+                .collect(),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.constraint);
+        if let Some(full_snapshot_result) = self.full_snapshot_result {
+            object.add_bool(2, full_snapshot_result);
+        }
+        object.add_uint32_list(3, &self.result_component_id);
+        if let Some(frequency) = self.frequency {
+            object.add_float(4, frequency);
+        }
+        object.add_uint32_list(5, &self.result_component_set_id);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The queries run on behalf of the workers authoritative over a component.
+pub struct ComponentInterest {
+    pub queries: Vec<Query>,
+}
+
+impl SchemaType for ComponentInterest {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            queries: read_object_list(object, 1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object_list(object, 1, &self.queries);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Defines the entities that the workers authoritative over this entity's components are
+/// interested in.
+pub struct Interest {
+    /// The interest of the workers authoritative over each component, keyed by component ID.
+    pub component_interest: BTreeMap<ComponentId, ComponentInterest>,
+}
+
+impl SchemaType for Interest {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            component_interest: read_map(object, 1, read_object),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_map(object, 1, &self.component_interest, write_object);
+    }
+}
+
+impl Component for Interest {
+    const ID: ComponentId = 58;
+    type Update = InterestUpdate;
+
+    fn apply_update(&mut self, update: &InterestUpdate) {
+        if let Some(component_interest) = &update.component_interest {
+            self.component_interest = component_interest.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InterestUpdate {
+    pub component_interest: Option<BTreeMap<ComponentId, ComponentInterest>>,
+}
+
+impl Update for InterestUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let cleared = update.is_field_cleared(1);
+        let fields = update.get_fields();
+        Self {
+            component_interest: if cleared || fields.get_object_count(1) > 0 {
+                Some(read_map(&fields, 1, read_object))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(component_interest) = &self.component_interest {
+            write_map(&mut fields, 1, component_interest, write_object);
+            if component_interest.is_empty() {
+                update.add_cleared_field(1);
+            }
+        }
+        update
+    }
+}
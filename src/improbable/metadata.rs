@@ -0,0 +1,60 @@
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Metadata describing an entity.
+pub struct Metadata {
+    /// The type of the entity, as shown in the Inspector.
+    pub entity_type: String,
+}
+
+impl SchemaType for Metadata {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            entity_type: object.get_string(1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_string(1, &self.entity_type);
+    }
+}
+
+impl Component for Metadata {
+    const ID: ComponentId = 53;
+    type Update = MetadataUpdate;
+
+    fn apply_update(&mut self, update: &MetadataUpdate) {
+        if let Some(entity_type) = &update.entity_type {
+            self.entity_type = entity_type.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MetadataUpdate {
+    pub entity_type: Option<String>,
+}
+
+impl Update for MetadataUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let fields = update.get_fields();
+        Self {
+            entity_type: if fields.get_bytes_count(1) > 0 {
+                Some(fields.get_string(1))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(entity_type) = &self.entity_type {
+            fields.add_string(1, entity_type);
+        }
+        update
+    }
+}
@@ -0,0 +1,290 @@
+//! Rust types for the components of the SpatialOS standard schema library
+//! (`improbable/standard_library.schema`).
+
+use crate::schema::{
+    ComponentData, FieldId, ObjectMut, ObjectRef, SchemaType, MAP_KEY_FIELD_ID, MAP_VALUE_FIELD_ID,
+};
+use std::collections::BTreeMap;
+
+mod authority_delegation;
+mod entity_acl;
+mod interest;
+mod metadata;
+mod persistence;
+mod player_client;
+mod position;
+mod system;
+mod worker;
+
+pub use authority_delegation::{AuthorityDelegation, AuthorityDelegationUpdate};
+pub use entity_acl::{EntityAcl, EntityAclUpdate, WorkerAttributeSet, WorkerRequirementSet};
+pub use interest::{
+    BoxConstraint, ComponentInterest, ComponentSetConstraint, CylinderConstraint, Interest,
+    InterestUpdate, Query, QueryConstraint, RelativeBoxConstraint, RelativeCylinderConstraint,
+    RelativeSphereConstraint, SelfConstraint, SphereConstraint,
+};
+pub use metadata::{Metadata, MetadataUpdate};
+pub use persistence::{Persistence, PersistenceUpdate};
+pub use player_client::{PlayerClient, PlayerClientUpdate, PlayerIdentity};
+pub use position::{Position, PositionUpdate};
+pub use system::{System, SystemUpdate};
+pub use worker::{Connection, ConnectionStatus, Worker, WorkerUpdate};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// A point in the world, in world units.
+pub struct Coordinates {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl SchemaType for Coordinates {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            x: object.get_double(1),
+            y: object.get_double(2),
+            z: object.get_double(3),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_double(1, self.x);
+        object.add_double(2, self.y);
+        object.add_double(3, self.z);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// The dimensions of a box, in world units.
+pub struct EdgeLength {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl SchemaType for EdgeLength {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            x: object.get_double(1),
+            y: object.get_double(2),
+            z: object.get_double(3),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_double(1, self.x);
+        object.add_double(2, self.y);
+        object.add_double(3, self.z);
+    }
+}
+
+/// Reads the object of the field, or an empty object if the field is missing.
+fn read_object<T: SchemaType>(object: &ObjectRef, field_id: FieldId) -> T {
+    match object.get_object(field_id) {
+        Some(object) => T::from_object(&object),
+        None => T::from_object(&ComponentData::new().get_fields()),
+    }
+}
+
+fn read_optional_object<T: SchemaType>(object: &ObjectRef, field_id: FieldId) -> Option<T> {
+    if object.get_object_count(field_id) > 0 {
+        Some(read_object(object, field_id))
+    } else {
+        None
+    }
+}
+
+fn read_object_list<T: SchemaType>(object: &ObjectRef, field_id: FieldId) -> Vec<T> {
+    (0..object.get_object_count(field_id))
+        .map(|index| T::from_object(&object.index_object(field_id, index)))
+        .collect()
+}
+
+fn write_object<T: SchemaType>(object: &mut ObjectMut, field_id: FieldId, value: &T) {
+    value.write_object(&mut object.add_object(field_id))
+}
+
+fn write_object_list<T: SchemaType>(object: &mut ObjectMut, field_id: FieldId, values: &[T]) {
+    for value in values {
+        write_object(object, field_id, value)
+    }
+}
+
+fn read_map<V, F>(object: &ObjectRef, field_id: FieldId, read_value: F) -> BTreeMap<u32, V>
+where
+    F: Fn(&ObjectRef, FieldId) -> V,
+{
+    (0..object.get_object_count(field_id))
+        .map(|index| {
+            let entry = object.index_object(field_id, index);
+            let key = entry.get_uint32(MAP_KEY_FIELD_ID);
+            (key, read_value(&entry, MAP_VALUE_FIELD_ID))
+        })
+        .collect()
+}
+
+fn write_map<V, F>(
+    object: &mut ObjectMut,
+    field_id: FieldId,
+    map: &BTreeMap<u32, V>,
+    write_value: F,
+) where
+    F: Fn(&mut ObjectMut, FieldId, &V),
+{
+    for (key, value) in map {
+        let mut entry = object.add_object(field_id);
+        entry.add_uint32(MAP_KEY_FIELD_ID, key);
+        write_value(&mut entry, MAP_VALUE_FIELD_ID, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Component, Update};
+    use std::fmt::Debug;
+
+    fn round_trip<C: Component + PartialEq + Debug>(component: &C) {
+        assert_eq!(&C::from_data(&component.to_data()), component);
+    }
+
+    fn round_trip_update<U: Update + PartialEq + Debug>(update: &U) {
+        assert_eq!(&U::from_update(&update.to_update()), update);
+    }
+
+    fn requirement(attribute_sets: &[&[&str]]) -> WorkerRequirementSet {
+        WorkerRequirementSet {
+            attribute_set: attribute_sets
+                .iter()
+                .map(|attributes| WorkerAttributeSet {
+                    attribute: attributes.iter().map(|&a| a.to_owned()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn position_round_trips() {
+        let coords = Coordinates {
+            x: 1.5,
+            y: -2.0,
+            z: 3.25,
+        };
+        round_trip(&Position { coords });
+        round_trip_update(&PositionUpdate {
+            coords: Some(coords),
+        });
+        round_trip_update(&PositionUpdate::default());
+    }
+
+    #[test]
+    fn entity_acl_round_trips() {
+        let mut component_write_acl = BTreeMap::new();
+        component_write_acl.insert(Position::ID, requirement(&[&["server"]]));
+        component_write_acl.insert(
+            Metadata::ID,
+            requirement(&[&["server"], &["client", "admin"]]),
+        );
+        let acl = EntityAcl {
+            read_acl: requirement(&[&["server"], &["client"]]),
+            component_write_acl,
+        };
+        round_trip(&acl);
+        round_trip_update(&EntityAclUpdate {
+            read_acl: Some(acl.read_acl.clone()),
+            component_write_acl: Some(acl.component_write_acl.clone()),
+        });
+        round_trip_update(&EntityAclUpdate {
+            read_acl: None,
+            component_write_acl: Some(BTreeMap::new()),
+        });
+    }
+
+    #[test]
+    fn interest_round_trips() {
+        let nearby = Query {
+            constraint: QueryConstraint {
+                and_constraint: vec![
+                    QueryConstraint {
+                        sphere_constraint: Some(SphereConstraint {
+                            center: Coordinates {
+                                x: 1.0,
+                                y: 2.0,
+                                z: 3.0,
+                            },
+                            radius: 50.0,
+                        }),
+                        ..QueryConstraint::default()
+                    },
+                    QueryConstraint {
+                        component_constraint: Some(Metadata::ID),
+                        ..QueryConstraint::default()
+                    },
+                ],
+                ..QueryConstraint::default()
+            },
+            full_snapshot_result: Some(false),
+            result_component_id: vec![Position::ID, Metadata::ID],
+            frequency: Some(2.5),
+            result_component_set_id: Vec::new(),
+        };
+        let own = Query {
+            constraint: QueryConstraint {
+                or_constraint: vec![
+                    QueryConstraint {
+                        self_constraint: Some(SelfConstraint {}),
+                        ..QueryConstraint::default()
+                    },
+                    QueryConstraint {
+                        entity_id_constraint: Some(7),
+                        relative_box_constraint: Some(RelativeBoxConstraint {
+                            edge_length: EdgeLength {
+                                x: 1.0,
+                                y: 2.0,
+                                z: 4.0,
+                            },
+                        }),
+                        ..QueryConstraint::default()
+                    },
+                ],
+                component_set_constraint: Some(ComponentSetConstraint {
+                    component_set_id: 9,
+                }),
+                ..QueryConstraint::default()
+            },
+            result_component_set_id: vec![9],
+            ..Query::default()
+        };
+        let mut component_interest = BTreeMap::new();
+        component_interest.insert(
+            Position::ID,
+            ComponentInterest {
+                queries: vec![nearby, own],
+            },
+        );
+        component_interest.insert(Metadata::ID, ComponentInterest::default());
+        let interest = Interest { component_interest };
+        round_trip(&interest);
+        round_trip_update(&InterestUpdate {
+            component_interest: Some(interest.component_interest),
+        });
+        round_trip_update(&InterestUpdate::default());
+    }
+
+    #[test]
+    fn authority_delegation_round_trips() {
+        let mut delegations = BTreeMap::new();
+        delegations.insert(1, 100);
+        delegations.insert(2, -1);
+        round_trip(&AuthorityDelegation {
+            delegations: delegations.clone(),
+        });
+        round_trip_update(&AuthorityDelegationUpdate {
+            delegations: Some(delegations),
+        });
+        round_trip_update(&AuthorityDelegationUpdate {
+            delegations: Some(BTreeMap::new()),
+        });
+        round_trip_update(&AuthorityDelegationUpdate::default());
+    }
+}
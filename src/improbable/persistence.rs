@@ -0,0 +1,35 @@
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// Marks an entity as persistent, meaning it is saved into snapshots.
+pub struct Persistence;
+
+impl SchemaType for Persistence {
+    fn from_object(_object: &ObjectRef) -> Self {
+        Self
+    }
+
+    fn write_object(&self, _object: &mut ObjectMut) {}
+}
+
+impl Component for Persistence {
+    const ID: ComponentId = 55;
+    type Update = PersistenceUpdate;
+
+    fn apply_update(&mut self, _update: &PersistenceUpdate) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PersistenceUpdate;
+
+impl Update for PersistenceUpdate {
+    fn from_update(_update: &ComponentUpdate) -> Self {
+        Self
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        ComponentUpdate::new()
+    }
+}
@@ -0,0 +1,81 @@
+use super::{read_object, read_optional_object, write_object};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The identity of a player, as provided by the authentication service the player logged in with.
+pub struct PlayerIdentity {
+    pub player_identifier: String,
+    /// The name of the authentication provider which issued the identifier.
+    pub provider: String,
+    pub metadata: Vec<u8>,
+}
+
+impl SchemaType for PlayerIdentity {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            player_identifier: object.get_string(1),
+            provider: object.get_string(2),
+            metadata: object.get_bytes(3),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_string(1, &self.player_identifier);
+        object.add_string(2, &self.provider);
+        object.add_bytes(3, &self.metadata);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Marks an entity as owned by a player client.
+pub struct PlayerClient {
+    /// The identity of the player owning the entity.
+    pub player_identity: PlayerIdentity,
+}
+
+impl SchemaType for PlayerClient {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            player_identity: read_object(object, 1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.player_identity);
+    }
+}
+
+impl Component for PlayerClient {
+    const ID: ComponentId = 61;
+    type Update = PlayerClientUpdate;
+
+    fn apply_update(&mut self, update: &PlayerClientUpdate) {
+        if let Some(player_identity) = &update.player_identity {
+            self.player_identity = player_identity.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PlayerClientUpdate {
+    pub player_identity: Option<PlayerIdentity>,
+}
+
+impl Update for PlayerClientUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        Self {
+            player_identity: read_optional_object(&update.get_fields(), 1),
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(player_identity) = &self.player_identity {
+            write_object(&mut fields, 1, player_identity);
+        }
+        update
+    }
+}
@@ -0,0 +1,56 @@
+use super::{read_object, read_optional_object, write_object, Coordinates};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The position of an entity in the world, used by SpatialOS for load balancing and queries.
+pub struct Position {
+    pub coords: Coordinates,
+}
+
+impl SchemaType for Position {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            coords: read_object(object, 1),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        write_object(object, 1, &self.coords);
+    }
+}
+
+impl Component for Position {
+    const ID: ComponentId = 54;
+    type Update = PositionUpdate;
+
+    fn apply_update(&mut self, update: &PositionUpdate) {
+        if let Some(coords) = update.coords {
+            self.coords = coords;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PositionUpdate {
+    pub coords: Option<Coordinates>,
+}
+
+impl Update for PositionUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let fields = update.get_fields();
+        Self {
+            coords: read_optional_object(&fields, 1),
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(coords) = &self.coords {
+            write_object(&mut fields, 1, coords);
+        }
+        update
+    }
+}
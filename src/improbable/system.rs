@@ -0,0 +1,35 @@
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// Marks an entity as created and managed by SpatialOS itself.
+pub struct System;
+
+impl SchemaType for System {
+    fn from_object(_object: &ObjectRef) -> Self {
+        Self
+    }
+
+    fn write_object(&self, _object: &mut ObjectMut) {}
+}
+
+impl Component for System {
+    const ID: ComponentId = 59;
+    type Update = SystemUpdate;
+
+    fn apply_update(&mut self, _update: &SystemUpdate) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SystemUpdate;
+
+impl Update for SystemUpdate {
+    fn from_update(_update: &ComponentUpdate) -> Self {
+        Self
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        ComponentUpdate::new()
+    }
+}
@@ -0,0 +1,144 @@
+use super::{read_object, read_optional_object, write_object};
+use crate::schema::{
+    Component, ComponentId, ComponentUpdate, ObjectMut, ObjectRef, SchemaType, Update,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// The status of a worker's connection to SpatialOS.
+pub enum ConnectionStatus {
+    #[default]
+    Unknown,
+    AwaitingWorkerConnection,
+    Connected,
+    Disconnected,
+}
+
+impl From<u32> for ConnectionStatus {
+    fn from(status: u32) -> Self {
+        match status {
+            1 => Self::AwaitingWorkerConnection,
+            2 => Self::Connected,
+            3 => Self::Disconnected,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<&ConnectionStatus> for u32 {
+    fn from(status: &ConnectionStatus) -> Self {
+        match status {
+            ConnectionStatus::Unknown => 0,
+            ConnectionStatus::AwaitingWorkerConnection => 1,
+            ConnectionStatus::Connected => 2,
+            ConnectionStatus::Disconnected => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Information about a worker's connection to SpatialOS.
+pub struct Connection {
+    pub status: ConnectionStatus,
+    /// The latency of the data sent to the worker, in milliseconds.
+    pub data_latency_ms: u32,
+    /// The time at which the worker connected, in milliseconds since the Unix epoch.
+    pub connected_since_utc: u64,
+}
+
+impl SchemaType for Connection {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            status: object.get_enum(1),
+            data_latency_ms: object.get_uint32(2),
+            connected_since_utc: object.get_uint64(3),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_enum::<ConnectionStatus, ConnectionStatus>(1, self.status);
+        object.add_uint32(2, self.data_latency_ms);
+        object.add_uint64(3, self.connected_since_utc);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Present on the entity that SpatialOS creates to represent each connected worker.
+pub struct Worker {
+    pub worker_id: String,
+    pub worker_type: String,
+    pub connection: Connection,
+}
+
+impl SchemaType for Worker {
+    fn from_object(object: &ObjectRef) -> Self {
+        Self {
+            worker_id: object.get_string(1),
+            worker_type: object.get_string(2),
+            connection: read_object(object, 3),
+        }
+    }
+
+    fn write_object(&self, object: &mut ObjectMut) {
+        object.add_string(1, &self.worker_id);
+        object.add_string(2, &self.worker_type);
+        write_object(object, 3, &self.connection);
+    }
+}
+
+impl Component for Worker {
+    const ID: ComponentId = 60;
+    type Update = WorkerUpdate;
+
+    fn apply_update(&mut self, update: &WorkerUpdate) {
+        if let Some(worker_id) = &update.worker_id {
+            self.worker_id = worker_id.clone();
+        }
+        if let Some(worker_type) = &update.worker_type {
+            self.worker_type = worker_type.clone();
+        }
+        if let Some(connection) = &update.connection {
+            self.connection = connection.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorkerUpdate {
+    pub worker_id: Option<String>,
+    pub worker_type: Option<String>,
+    pub connection: Option<Connection>,
+}
+
+impl Update for WorkerUpdate {
+    fn from_update(update: &ComponentUpdate) -> Self {
+        let fields = update.get_fields();
+        Self {
+            worker_id: if fields.get_bytes_count(1) > 0 {
+                Some(fields.get_string(1))
+            } else {
+                None
+            },
+            worker_type: if fields.get_bytes_count(2) > 0 {
+                Some(fields.get_string(2))
+            } else {
+                None
+            },
+            connection: read_optional_object(&fields, 3),
+        }
+    }
+
+    fn to_update(&self) -> ComponentUpdate {
+        let mut update = ComponentUpdate::new();
+        let mut fields = update.get_fields_mut();
+        if let Some(worker_id) = &self.worker_id {
+            fields.add_string(1, worker_id);
+        }
+        if let Some(worker_type) = &self.worker_type {
+            fields.add_string(2, worker_type);
+        }
+        if let Some(connection) = &self.connection {
+            write_object(&mut fields, 3, connection);
+        }
+        update
+    }
+}
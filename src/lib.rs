@@ -1,3 +1,4 @@
+pub mod improbable;
 pub mod schema;
 pub mod worker;
 
@@ -5,7 +6,7 @@ pub mod private_exports {
     pub use spatialos_sys::*;
 }
 
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 
 pub use spatialos_sys::{
     Schema_AddComponentUpdateClearedField, Schema_GetComponentUpdateClearedFieldCount,
@@ -18,8 +19,7 @@ pub use spatialos_sys::{
     Ngrpc_GetStatus, Ngrpc_MakeCall, Ngrpc_Parameters, Ngrpc_Receive, Ngrpc_Send, Ngrpc_Status,
     Ngrpc_StatusCode, Ngrpc_StatusCodeToString, Ngrpc_TlsParameters, Schema_AllocateObject,
     Schema_ApplyComponentUpdateToData, Schema_Bundle, Schema_Bundle_Destroy,
-    Schema_Bundle_GetError, Schema_Bundle_Load,
-    Schema_ClearComponentUpdateClearedFields,
+    Schema_Bundle_GetError, Schema_Bundle_Load, Schema_ClearComponentUpdateClearedFields,
     Schema_ConvertComponentDataIntoUpdate, Schema_CopyCommandRequest, Schema_CopyCommandResponse,
     Schema_CopyComponentData, Schema_CopyComponentUpdate, Schema_CopyGenericData,
     Schema_CreateCommandRequest, Schema_CreateCommandResponse, Schema_CreateGenericData,
@@ -128,9 +128,15 @@ pub(crate) fn const_to_vector<T: Clone>(data: *const T, size: isize) -> Vec<T> {
     }
 }
 
+/// Converts a string for the SDK, removing the NUL bytes it contains instead of failing.
+pub(crate) fn to_c_string<S: Into<Vec<u8>>>(data: S) -> CString {
+    let mut bytes = data.into();
+    bytes.retain(|byte| *byte != 0);
+    CString::new(bytes).expect("NUL bytes were removed")
+}
+
 pub(crate) fn const_to_string(data: *const i8) -> String {
     unsafe { CStr::from_ptr(data) }
-        .to_str()
-        .map(|s| s.to_owned())
-        .unwrap()
+        .to_string_lossy()
+        .into_owned()
 }
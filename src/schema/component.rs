@@ -0,0 +1,50 @@
+use crate::schema::{ComponentData, ComponentId, ComponentUpdate, ObjectMut, ObjectRef};
+use crate::worker;
+
+/// A schema type that can be read from and written into a schema object.
+pub trait SchemaType: Sized {
+    /// Reads a value of this type from the fields of the given object.
+    fn from_object(object: &ObjectRef) -> Self;
+
+    /// Writes the fields of this value into the given object.
+    fn write_object(&self, object: &mut ObjectMut);
+}
+
+/// A schema component, identified by its component ID.
+pub trait Component: SchemaType {
+    /// The ID of the component, as declared in schema.
+    const ID: ComponentId;
+
+    /// The type of the updates that can be applied to this component.
+    type Update: Update;
+
+    /// Deserializes the component from schema component data.
+    fn from_data(data: &ComponentData) -> Self {
+        Self::from_object(&data.get_fields())
+    }
+
+    /// Serializes the component into new schema component data.
+    fn to_data(&self) -> ComponentData {
+        let mut data = ComponentData::new();
+        self.write_object(&mut data.get_fields_mut());
+        data
+    }
+
+    /// Serializes the component into component data that can be sent to SpatialOS.
+    fn to_component_data(&self) -> worker::ComponentData {
+        worker::ComponentData::new(Self::ID, self.to_data())
+    }
+
+    /// Applies every field set in the given update to the component.
+    fn apply_update(&mut self, update: &Self::Update);
+}
+
+/// An update to a schema component. Fields that are not set are left untouched when the update is
+/// applied.
+pub trait Update: Sized {
+    /// Deserializes the update from a schema component update.
+    fn from_update(update: &ComponentUpdate) -> Self;
+
+    /// Serializes the update into a new schema component update.
+    fn to_update(&self) -> ComponentUpdate;
+}
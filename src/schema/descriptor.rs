@@ -0,0 +1,498 @@
+//! Runtime descriptions of schema types, so that objects can be read without compiled Rust types.
+//!
+//! A SchemaDescriptor is either built by hand or, with the `serde` feature, loaded from the JSON
+//! bundle written by the schema compiler with `--bundle_json_out`. Only the parts of the bundle
+//! describing the fields of types and components, and the values of enums, are read.
+
+use crate::schema::{ComponentId, FieldId, SchemaError};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The primitive types of schema.
+pub enum PrimitiveType {
+    Bool,
+    Float,
+    Double,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    String,
+    Bytes,
+    EntityId,
+    Entity,
+}
+
+impl FromStr for PrimitiveType {
+    type Err = SchemaError;
+
+    /// Parses the name of a primitive type, as written in a schema bundle.
+    fn from_str(name: &str) -> Result<Self, SchemaError> {
+        match name {
+            "Bool" => Ok(PrimitiveType::Bool),
+            "Float" => Ok(PrimitiveType::Float),
+            "Double" => Ok(PrimitiveType::Double),
+            "Int32" => Ok(PrimitiveType::Int32),
+            "Int64" => Ok(PrimitiveType::Int64),
+            "Uint32" => Ok(PrimitiveType::Uint32),
+            "Uint64" => Ok(PrimitiveType::Uint64),
+            "Sint32" => Ok(PrimitiveType::Sint32),
+            "Sint64" => Ok(PrimitiveType::Sint64),
+            "Fixed32" => Ok(PrimitiveType::Fixed32),
+            "Fixed64" => Ok(PrimitiveType::Fixed64),
+            "Sfixed32" => Ok(PrimitiveType::Sfixed32),
+            "Sfixed64" => Ok(PrimitiveType::Sfixed64),
+            "String" => Ok(PrimitiveType::String),
+            "Bytes" => Ok(PrimitiveType::Bytes),
+            "EntityId" => Ok(PrimitiveType::EntityId),
+            "Entity" => Ok(PrimitiveType::Entity),
+            _ => Err(SchemaError::InvalidDescriptor(format!(
+                "unknown primitive type {}",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A reference to the type of a value, by qualified name for enums and user-defined types.
+pub enum TypeReference {
+    Primitive(PrimitiveType),
+    Enum(String),
+    Type(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The type of a field, along with how many values it holds.
+pub enum FieldType {
+    Singular(TypeReference),
+    Option(TypeReference),
+    List(TypeReference),
+    Map(TypeReference, TypeReference),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub field_id: FieldId,
+    pub field_type: FieldType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A user-defined type, identified by its qualified name.
+pub struct TypeDescriptor {
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An enum, identified by its qualified name, along with the name of each value.
+pub struct EnumDescriptor {
+    pub name: String,
+    pub values: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A component, along with the qualified name of the type describing its fields.
+pub struct ComponentDescriptor {
+    pub name: String,
+    pub component_id: ComponentId,
+    pub data_type: String,
+}
+
+#[derive(Debug, Clone, Default)]
+/// A set of schema types, enums and components, looked up by qualified name or component ID.
+pub struct SchemaDescriptor {
+    types: HashMap<String, TypeDescriptor>,
+    enums: HashMap<String, EnumDescriptor>,
+    components: HashMap<ComponentId, ComponentDescriptor>,
+}
+
+impl SchemaDescriptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_type(&mut self, descriptor: TypeDescriptor) {
+        self.types.insert(descriptor.name.clone(), descriptor);
+    }
+
+    pub fn add_enum(&mut self, descriptor: EnumDescriptor) {
+        self.enums.insert(descriptor.name.clone(), descriptor);
+    }
+
+    /// Adds a component. The type describing its fields must be added separately.
+    pub fn add_component(&mut self, descriptor: ComponentDescriptor) {
+        self.components.insert(descriptor.component_id, descriptor);
+    }
+
+    pub fn get_type(&self, name: &str) -> Option<&TypeDescriptor> {
+        self.types.get(name)
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDescriptor> {
+        self.enums.get(name)
+    }
+
+    pub fn get_component(&self, component_id: ComponentId) -> Option<&ComponentDescriptor> {
+        self.components.get(&component_id)
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = &TypeDescriptor> {
+        self.types.values()
+    }
+
+    pub fn enums(&self) -> impl Iterator<Item = &EnumDescriptor> {
+        self.enums.values()
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &ComponentDescriptor> {
+        self.components.values()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SchemaDescriptor {
+    /// Loads the types, enums and components of a JSON schema bundle. Components declaring
+    /// their fields inline are described by a type named after the component.
+    pub fn from_bundle_json(json: &str) -> Result<Self, SchemaError> {
+        let bundle: bundle::Bundle = serde_json::from_str(json)
+            .map_err(|error| SchemaError::InvalidDescriptor(error.to_string()))?;
+        let mut descriptor = Self::new();
+        for file in bundle.schema_files {
+            for schema_enum in file.enums {
+                descriptor.add_enum(EnumDescriptor {
+                    name: schema_enum.qualified_name,
+                    values: schema_enum
+                        .values
+                        .into_iter()
+                        .map(|value| (value.name, value.value))
+                        .collect(),
+                });
+            }
+            for schema_type in file.types {
+                descriptor.add_type(TypeDescriptor {
+                    name: schema_type.qualified_name,
+                    fields: bundle::to_fields(schema_type.fields)?,
+                });
+            }
+            for component in file.components {
+                let data_type = match component.data_definition {
+                    Some(data_type) => data_type,
+                    None => {
+                        descriptor.add_type(TypeDescriptor {
+                            name: component.qualified_name.clone(),
+                            fields: bundle::to_fields(component.fields)?,
+                        });
+                        component.qualified_name.clone()
+                    }
+                };
+                descriptor.add_component(ComponentDescriptor {
+                    name: component.qualified_name,
+                    component_id: component.component_id,
+                    data_type,
+                });
+            }
+        }
+        Ok(descriptor)
+    }
+
+    /// Loads a JSON schema bundle from the given file.
+    pub fn from_bundle_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, SchemaError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|error| SchemaError::InvalidDescriptor(error.to_string()))?;
+        Self::from_bundle_json(&json)
+    }
+}
+
+/// The subset of the JSON schema bundle format read by SchemaDescriptor::from_bundle_json.
+#[cfg(feature = "serde")]
+mod bundle {
+    use super::{FieldDescriptor, FieldType, PrimitiveType, TypeReference};
+    use crate::schema::{ComponentId, FieldId, SchemaError};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Bundle {
+        pub schema_files: Vec<File>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct File {
+        #[serde(default)]
+        pub enums: Vec<Enum>,
+        #[serde(default)]
+        pub types: Vec<Type>,
+        #[serde(default)]
+        pub components: Vec<Component>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Enum {
+        pub qualified_name: String,
+        pub values: Vec<EnumValue>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct EnumValue {
+        pub name: String,
+        pub value: u32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Type {
+        pub qualified_name: String,
+        #[serde(default)]
+        pub fields: Vec<Field>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Component {
+        pub qualified_name: String,
+        pub component_id: ComponentId,
+        #[serde(default)]
+        pub data_definition: Option<String>,
+        #[serde(default)]
+        pub fields: Vec<Field>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Field {
+        pub name: String,
+        pub field_id: FieldId,
+        pub singular_type: Option<Singular>,
+        pub option_type: Option<Inner>,
+        pub list_type: Option<Inner>,
+        pub map_type: Option<Map>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Singular {
+        #[serde(rename = "type")]
+        pub value_type: Reference,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Inner {
+        pub inner_type: Reference,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Map {
+        pub key_type: Reference,
+        pub value_type: Reference,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Reference {
+        pub primitive: Option<String>,
+        #[serde(rename = "enum")]
+        pub enum_name: Option<String>,
+        #[serde(rename = "type")]
+        pub type_name: Option<String>,
+    }
+
+    pub fn to_fields(fields: Vec<Field>) -> Result<Vec<FieldDescriptor>, SchemaError> {
+        fields.into_iter().map(to_field).collect()
+    }
+
+    fn to_field(field: Field) -> Result<FieldDescriptor, SchemaError> {
+        let field_type = if let Some(singular) = field.singular_type {
+            FieldType::Singular(to_reference(singular.value_type)?)
+        } else if let Some(option) = field.option_type {
+            FieldType::Option(to_reference(option.inner_type)?)
+        } else if let Some(list) = field.list_type {
+            FieldType::List(to_reference(list.inner_type)?)
+        } else if let Some(map) = field.map_type {
+            FieldType::Map(to_reference(map.key_type)?, to_reference(map.value_type)?)
+        } else {
+            return Err(SchemaError::InvalidDescriptor(format!(
+                "field {} has no type",
+                field.name
+            )));
+        };
+        Ok(FieldDescriptor {
+            name: field.name,
+            field_id: field.field_id,
+            field_type,
+        })
+    }
+
+    fn to_reference(reference: Reference) -> Result<TypeReference, SchemaError> {
+        match reference {
+            Reference {
+                primitive: Some(primitive),
+                ..
+            } => Ok(TypeReference::Primitive(
+                primitive.parse::<PrimitiveType>()?,
+            )),
+            Reference {
+                enum_name: Some(name),
+                ..
+            } => Ok(TypeReference::Enum(name)),
+            Reference {
+                type_name: Some(name),
+                ..
+            } => Ok(TypeReference::Type(name)),
+            _ => Err(SchemaError::InvalidDescriptor(
+                "type reference names no type".into(),
+            )),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"{
+        "schemaFiles": [{
+            "enums": [{
+                "qualifiedName": "test.Color",
+                "values": [{ "name": "RED", "value": 0 }, { "name": "BLUE", "value": 2 }]
+            }],
+            "types": [{
+                "qualifiedName": "test.Inventory",
+                "fields": [
+                    {
+                        "name": "owner",
+                        "fieldId": 1,
+                        "optionType": { "innerType": { "primitive": "EntityId" } }
+                    },
+                    {
+                        "name": "colors",
+                        "fieldId": 2,
+                        "listType": { "innerType": { "enum": "test.Color" } }
+                    },
+                    {
+                        "name": "counts",
+                        "fieldId": 3,
+                        "mapType": {
+                            "keyType": { "primitive": "String" },
+                            "valueType": { "primitive": "Uint32" }
+                        }
+                    }
+                ]
+            }],
+            "components": [
+                {
+                    "qualifiedName": "test.Health",
+                    "componentId": 1000,
+                    "fields": [{
+                        "name": "value",
+                        "fieldId": 1,
+                        "singularType": { "type": { "primitive": "Float" } }
+                    }]
+                },
+                {
+                    "qualifiedName": "test.Backpack",
+                    "componentId": 1001,
+                    "dataDefinition": "test.Inventory"
+                }
+            ]
+        }]
+    }"#;
+
+    #[test]
+    fn bundle_enums_are_loaded() {
+        let descriptor = SchemaDescriptor::from_bundle_json(BUNDLE).unwrap();
+        let color = descriptor.get_enum("test.Color").unwrap();
+        assert_eq!(
+            color.values,
+            vec![("RED".to_owned(), 0), ("BLUE".to_owned(), 2)]
+        );
+    }
+
+    #[test]
+    fn bundle_types_are_loaded_with_their_field_types() {
+        let descriptor = SchemaDescriptor::from_bundle_json(BUNDLE).unwrap();
+        let inventory = descriptor.get_type("test.Inventory").unwrap();
+        let fields: Vec<_> = inventory
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name.as_str(),
+                    field.field_id,
+                    field.field_type.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (
+                    "owner",
+                    1,
+                    FieldType::Option(TypeReference::Primitive(PrimitiveType::EntityId))
+                ),
+                (
+                    "colors",
+                    2,
+                    FieldType::List(TypeReference::Enum("test.Color".into()))
+                ),
+                (
+                    "counts",
+                    3,
+                    FieldType::Map(
+                        TypeReference::Primitive(PrimitiveType::String),
+                        TypeReference::Primitive(PrimitiveType::Uint32)
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_components_are_described_by_a_type_named_after_them() {
+        let descriptor = SchemaDescriptor::from_bundle_json(BUNDLE).unwrap();
+        let health = descriptor.get_component(1000).unwrap();
+        assert_eq!(health.name, "test.Health");
+        assert_eq!(health.data_type, "test.Health");
+        let data_type = descriptor.get_type(&health.data_type).unwrap();
+        assert_eq!(
+            data_type.fields,
+            vec![FieldDescriptor {
+                name: "value".into(),
+                field_id: 1,
+                field_type: FieldType::Singular(TypeReference::Primitive(PrimitiveType::Float)),
+            }]
+        );
+    }
+
+    #[test]
+    fn data_definition_components_refer_to_their_type() {
+        let descriptor = SchemaDescriptor::from_bundle_json(BUNDLE).unwrap();
+        let backpack = descriptor.get_component(1001).unwrap();
+        assert_eq!(backpack.data_type, "test.Inventory");
+        assert!(descriptor.get_type("test.Backpack").is_none());
+        assert_eq!(descriptor.types().count(), 2);
+    }
+
+    #[test]
+    fn fields_without_a_type_are_rejected() {
+        let json = r#"{ "schemaFiles": [{ "types": [{
+            "qualifiedName": "test.Broken",
+            "fields": [{ "name": "value", "fieldId": 1 }]
+        }] }] }"#;
+        assert!(matches!(
+            SchemaDescriptor::from_bundle_json(json),
+            Err(SchemaError::InvalidDescriptor(_))
+        ));
+    }
+}
@@ -1,11 +1,30 @@
 use spatialos_sys::{
-    Schema_ComponentId, Schema_CreateComponentData, Schema_CreateComponentUpdate, Schema_EntityId,
-    Schema_FieldId, Schema_GetComponentDataFields, Schema_GetComponentUpdateFields,
-    SCHEMA_MAP_KEY_FIELD_ID, SCHEMA_MAP_VALUE_FIELD_ID,
+    Schema_AddComponentUpdateClearedField, Schema_ApplyComponentUpdateToData, Schema_ComponentId,
+    Schema_ConvertComponentDataIntoUpdate, Schema_CopyCommandRequest, Schema_CopyCommandResponse,
+    Schema_CopyComponentData, Schema_CopyComponentUpdate, Schema_CreateCommandRequest,
+    Schema_CreateCommandResponse, Schema_CreateComponentData, Schema_CreateComponentUpdate,
+    Schema_DestroyCommandRequest, Schema_DestroyCommandResponse, Schema_DestroyComponentData,
+    Schema_DestroyComponentUpdate, Schema_EntityId, Schema_FieldId, Schema_GetCommandRequestObject,
+    Schema_GetCommandResponseObject, Schema_GetComponentDataFields,
+    Schema_GetComponentUpdateClearedFieldCount, Schema_GetComponentUpdateEvents,
+    Schema_GetComponentUpdateFields, Schema_GetError, Schema_IndexComponentUpdateClearedField,
+    Schema_MergeComponentUpdateIntoUpdate, Schema_ShallowCopyField, SCHEMA_MAP_KEY_FIELD_ID,
+    SCHEMA_MAP_VALUE_FIELD_ID,
 };
 
+pub mod component;
+pub mod descriptor;
 pub mod object;
-pub use object::Object;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod value;
+use crate::const_to_string;
+pub use component::{Component, SchemaType, Update};
+pub use descriptor::SchemaDescriptor;
+pub use object::{ObjectMut, ObjectRef, SchemaError};
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+pub use value::Value;
 
 pub type EntityId = Schema_EntityId;
 pub type FieldId = Schema_FieldId;
@@ -26,64 +45,523 @@ pub mod ffi {
 pub const MAP_KEY_FIELD_ID: u32 = SCHEMA_MAP_KEY_FIELD_ID;
 pub const MAP_VALUE_FIELD_ID: u32 = SCHEMA_MAP_VALUE_FIELD_ID;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ComponentData {
-    inner: Box<ffi::ComponentData>,
+    inner: NonNull<ffi::ComponentData>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ComponentUpdate {
-    inner: Box<ffi::ComponentUpdate>,
+    inner: NonNull<ffi::ComponentUpdate>,
+}
+
+#[derive(Debug)]
+pub struct CommandRequest {
+    inner: NonNull<ffi::CommandRequest>,
+}
+
+#[derive(Debug)]
+pub struct CommandResponse {
+    inner: NonNull<ffi::CommandResponse>,
 }
 
 impl ComponentData {
     pub fn new() -> Self {
-        let inner = unsafe { Box::from_raw(Schema_CreateComponentData()) };
-        Self { inner }
+        Self::from(unsafe { Schema_CreateComponentData() })
+    }
+
+    pub fn get_fields(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(Schema_GetComponentDataFields(self.inner.as_ptr())) }
+    }
+
+    pub fn get_fields_mut(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetComponentDataFields(self.inner.as_ptr())) }
     }
 
-    pub fn get_fields(&mut self) -> Object {
-        Object::from(unsafe {
-            Schema_GetComponentDataFields(&mut *self.inner as *mut ffi::ComponentData)
-        })
+    /// Serializes the fields of the component into the SpatialOS wire format.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.get_fields().serialize()
+    }
+
+    /// Creates component data from fields serialized with ComponentData::serialize. Returns None if
+    /// the buffer could not be parsed.
+    pub fn deserialize(buffer: &[u8]) -> Option<Self> {
+        let mut data = Self::new();
+        if data.get_fields_mut().merge_from_buffer(buffer).is_ok() {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    /// Applies an update to the data. Fields set by the update replace those of the data, and
+    /// fields cleared by the update are removed from it.
+    pub fn apply_update(&mut self, update: &ComponentUpdate) -> Result<(), SchemaError> {
+        let applied = unsafe {
+            Schema_ApplyComponentUpdateToData(update.inner.as_ptr(), self.inner.as_ptr()) != 0
+        };
+        if applied {
+            Ok(())
+        } else {
+            Err(update_error(&self.get_fields(), &update.get_fields()))
+        }
+    }
+
+    /// Converts the data into an update setting every field it holds.
+    pub fn into_update(self) -> ComponentUpdate {
+        let data = ManuallyDrop::new(self);
+        ComponentUpdate::from(unsafe { Schema_ConvertComponentDataIntoUpdate(data.inner.as_ptr()) })
+    }
+
+    /// Returns an update which, applied to the old data, yields the new data. Only the fields
+    /// whose values differ are set, and fields holding values in the old data but none in the new
+    /// data, such as emptied lists, maps and options, are cleared.
+    pub fn diff(old: &ComponentData, new: &ComponentData) -> Result<ComponentUpdate, SchemaError> {
+        let old_fields = old.get_fields();
+        let new_fields = new.get_fields();
+        let old_field_ids = old_fields.get_unique_field_ids();
+        let new_field_ids = new_fields.get_unique_field_ids();
+        let mut update = ComponentUpdate::new();
+        for &field_id in &new_field_ids {
+            let value = serialize_field(&new_fields, field_id);
+            if !old_field_ids.contains(&field_id) || serialize_field(&old_fields, field_id) != value
+            {
+                update.get_fields_mut().merge_from_buffer(&value)?;
+            }
+        }
+        for &field_id in &old_field_ids {
+            if !new_field_ids.contains(&field_id) {
+                update.add_cleared_field(field_id);
+            }
+        }
+        Ok(update)
     }
 }
 
 impl ComponentUpdate {
     pub fn new() -> Self {
-        let inner = unsafe { Box::from_raw(Schema_CreateComponentUpdate()) };
-        Self { inner }
+        Self::from(unsafe { Schema_CreateComponentUpdate() })
+    }
+
+    pub fn get_fields(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(Schema_GetComponentUpdateFields(self.inner.as_ptr())) }
+    }
+
+    pub fn get_fields_mut(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetComponentUpdateFields(self.inner.as_ptr())) }
+    }
+
+    pub fn get_events(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(Schema_GetComponentUpdateEvents(self.inner.as_ptr())) }
+    }
+
+    pub fn get_events_mut(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetComponentUpdateEvents(self.inner.as_ptr())) }
+    }
+
+    /// Serializes the fields, events and cleared fields of the update. The SDK has no wire format
+    /// for a whole update, so the result can only be read back with ComponentUpdate::deserialize.
+    pub fn serialize(&self) -> Vec<u8> {
+        let fields = self.get_fields().serialize();
+        let events = self.get_events().serialize();
+        let cleared_fields = self.get_cleared_fields();
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&fields);
+        buffer.extend_from_slice(&(events.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&events);
+        for field_id in cleared_fields {
+            buffer.extend_from_slice(&field_id.to_le_bytes());
+        }
+        buffer
+    }
+
+    /// Creates an update from a buffer written by ComponentUpdate::serialize. Returns None if the
+    /// buffer could not be parsed.
+    pub fn deserialize(buffer: &[u8]) -> Option<Self> {
+        fn split_u32(buffer: &[u8]) -> Option<(u32, &[u8])> {
+            if buffer.len() < 4 {
+                return None;
+            }
+            let (value, rest) = buffer.split_at(4);
+            Some((
+                u32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+                rest,
+            ))
+        }
+        fn split_object(buffer: &[u8]) -> Option<(&[u8], &[u8])> {
+            let (length, rest) = split_u32(buffer)?;
+            if rest.len() < length as usize {
+                return None;
+            }
+            Some(rest.split_at(length as usize))
+        }
+        let mut update = Self::new();
+        let (fields, rest) = split_object(buffer)?;
+        let (events, mut rest) = split_object(rest)?;
+        if update.get_fields_mut().merge_from_buffer(fields).is_err()
+            || update.get_events_mut().merge_from_buffer(events).is_err()
+        {
+            return None;
+        }
+        while !rest.is_empty() {
+            let (field_id, next) = split_u32(rest)?;
+            update.add_cleared_field(field_id);
+            rest = next;
+        }
+        Some(update)
+    }
+
+    /// Marks a list, map or option field as cleared by this update.
+    pub fn add_cleared_field(&mut self, field_id: FieldId) {
+        unsafe { Schema_AddComponentUpdateClearedField(self.inner.as_ptr(), field_id) }
+    }
+
+    /// Returns the IDs of the fields cleared by this update.
+    pub fn get_cleared_fields(&self) -> Vec<FieldId> {
+        let count = unsafe { Schema_GetComponentUpdateClearedFieldCount(self.inner.as_ptr()) };
+        (0..count)
+            .map(|index| unsafe {
+                Schema_IndexComponentUpdateClearedField(self.inner.as_ptr(), index)
+            })
+            .collect()
+    }
+
+    pub fn is_field_cleared(&self, field_id: FieldId) -> bool {
+        self.get_cleared_fields().contains(&field_id)
+    }
+
+    /// Merges another update into this one, as if both were applied in order. Fields set or
+    /// cleared by the other update replace those of this update, and events are appended.
+    pub fn merge(&mut self, other: &ComponentUpdate) -> Result<(), SchemaError> {
+        let merged = unsafe {
+            Schema_MergeComponentUpdateIntoUpdate(other.inner.as_ptr(), self.inner.as_ptr()) != 0
+        };
+        if merged {
+            Ok(())
+        } else {
+            Err(update_error(&self.get_fields(), &other.get_fields()))
+        }
+    }
+}
+
+/// Serializes the values of a single field of an object.
+fn serialize_field(object: &ObjectRef, field_id: FieldId) -> Vec<u8> {
+    let mut field = ComponentData::new();
+    let field_object = field.get_fields_mut();
+    unsafe { Schema_ShallowCopyField(object.as_ptr(), field_object.as_ptr(), field_id) };
+    field_object.serialize()
+}
+
+/// Returns the error the SDK reported while writing an update into an object, falling back to
+/// the error reported on the update it read from.
+fn update_error(written: &ObjectRef, read: &ObjectRef) -> SchemaError {
+    let mut error = unsafe { Schema_GetError(written.as_ptr()) };
+    if error.is_null() {
+        error = unsafe { Schema_GetError(read.as_ptr()) };
+    }
+    SchemaError::InvalidUpdate(if error.is_null() {
+        String::new()
+    } else {
+        const_to_string(error)
+    })
+}
+
+impl CommandRequest {
+    pub fn new() -> Self {
+        Self::from(unsafe { Schema_CreateCommandRequest() })
+    }
+
+    pub fn get_object(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(Schema_GetCommandRequestObject(self.inner.as_ptr())) }
+    }
+
+    pub fn get_object_mut(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetCommandRequestObject(self.inner.as_ptr())) }
+    }
+
+    /// Serializes the request object into the SpatialOS wire format.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.get_object().serialize()
     }
 
-    pub fn get_fields(&mut self) -> Object {
-        Object::from(unsafe {
-            Schema_GetComponentUpdateFields(&mut *self.inner as *mut ffi::ComponentUpdate)
-        })
+    /// Creates a request from an object serialized with CommandRequest::serialize. Returns None if
+    /// the buffer could not be parsed.
+    pub fn deserialize(buffer: &[u8]) -> Option<Self> {
+        let mut request = Self::new();
+        if request.get_object_mut().merge_from_buffer(buffer).is_ok() {
+            Some(request)
+        } else {
+            None
+        }
     }
 }
 
+impl CommandResponse {
+    pub fn new() -> Self {
+        Self::from(unsafe { Schema_CreateCommandResponse() })
+    }
+
+    pub fn get_object(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(Schema_GetCommandResponseObject(self.inner.as_ptr())) }
+    }
+
+    pub fn get_object_mut(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetCommandResponseObject(self.inner.as_ptr())) }
+    }
+
+    /// Serializes the response object into the SpatialOS wire format.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.get_object().serialize()
+    }
+
+    /// Creates a response from an object serialized with CommandResponse::serialize. Returns None
+    /// if the buffer could not be parsed.
+    pub fn deserialize(buffer: &[u8]) -> Option<Self> {
+        let mut response = Self::new();
+        if response.get_object_mut().merge_from_buffer(buffer).is_ok() {
+            Some(response)
+        } else {
+            None
+        }
+    }
+}
+
+/// Takes ownership of the data, which is destroyed when the ComponentData is dropped.
 impl From<*mut ffi::ComponentData> for ComponentData {
     fn from(inner: *mut ffi::ComponentData) -> Self {
-        let inner = unsafe { Box::from_raw(inner) };
+        let inner = NonNull::new(inner).expect("component data is null");
         Self { inner }
     }
 }
 
+/// Releases ownership of the data, which must then be destroyed by the SDK or the caller.
 impl From<ComponentData> for *mut ffi::ComponentData {
     fn from(data: ComponentData) -> Self {
-        Box::into_raw(data.inner)
+        ManuallyDrop::new(data).inner.as_ptr()
     }
 }
 
+/// Takes ownership of the update, which is destroyed when the ComponentUpdate is dropped.
 impl From<*mut ffi::ComponentUpdate> for ComponentUpdate {
     fn from(inner: *mut ffi::ComponentUpdate) -> Self {
-        let inner = unsafe { Box::from_raw(inner) };
+        let inner = NonNull::new(inner).expect("component update is null");
         Self { inner }
     }
 }
 
+/// Releases ownership of the update, which must then be destroyed by the SDK or the caller.
 impl Into<*mut ffi::ComponentUpdate> for ComponentUpdate {
     fn into(self) -> *mut ffi::ComponentUpdate {
-        Box::into_raw(self.inner)
+        ManuallyDrop::new(self).inner.as_ptr()
+    }
+}
+
+/// Takes ownership of the request, which is destroyed when the CommandRequest is dropped.
+impl From<*mut ffi::CommandRequest> for CommandRequest {
+    fn from(inner: *mut ffi::CommandRequest) -> Self {
+        let inner = NonNull::new(inner).expect("command request is null");
+        Self { inner }
+    }
+}
+
+/// Releases ownership of the request, which must then be destroyed by the SDK or the caller.
+impl From<CommandRequest> for *mut ffi::CommandRequest {
+    fn from(request: CommandRequest) -> Self {
+        ManuallyDrop::new(request).inner.as_ptr()
+    }
+}
+
+/// Takes ownership of the response, which is destroyed when the CommandResponse is dropped.
+impl From<*mut ffi::CommandResponse> for CommandResponse {
+    fn from(inner: *mut ffi::CommandResponse) -> Self {
+        let inner = NonNull::new(inner).expect("command response is null");
+        Self { inner }
+    }
+}
+
+/// Releases ownership of the response, which must then be destroyed by the SDK or the caller.
+impl From<CommandResponse> for *mut ffi::CommandResponse {
+    fn from(response: CommandResponse) -> Self {
+        ManuallyDrop::new(response).inner.as_ptr()
+    }
+}
+
+/// Each value exclusively owns its schema data, which is not tied to the thread it was created on,
+/// so it can be moved to another thread. The types are not Sync: the schema functions must not be
+/// called concurrently on the same data.
+unsafe impl Send for ComponentData {}
+unsafe impl Send for ComponentUpdate {}
+unsafe impl Send for CommandRequest {}
+unsafe impl Send for CommandResponse {}
+
+impl Default for ComponentData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for ComponentData {
+    fn clone(&self) -> Self {
+        Self::from(unsafe { Schema_CopyComponentData(self.inner.as_ptr()) })
+    }
+}
+
+impl Drop for ComponentData {
+    fn drop(&mut self) {
+        unsafe { Schema_DestroyComponentData(self.inner.as_ptr()) }
+    }
+}
+
+impl Default for ComponentUpdate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for ComponentUpdate {
+    fn clone(&self) -> Self {
+        Self::from(unsafe { Schema_CopyComponentUpdate(self.inner.as_ptr()) })
+    }
+}
+
+impl Drop for ComponentUpdate {
+    fn drop(&mut self) {
+        unsafe { Schema_DestroyComponentUpdate(self.inner.as_ptr()) }
+    }
+}
+
+impl Default for CommandRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for CommandRequest {
+    fn clone(&self) -> Self {
+        Self::from(unsafe { Schema_CopyCommandRequest(self.inner.as_ptr()) })
+    }
+}
+
+impl Drop for CommandRequest {
+    fn drop(&mut self) {
+        unsafe { Schema_DestroyCommandRequest(self.inner.as_ptr()) }
+    }
+}
+
+impl Default for CommandResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for CommandResponse {
+    fn clone(&self) -> Self {
+        Self::from(unsafe { Schema_CopyCommandResponse(self.inner.as_ptr()) })
+    }
+}
+
+impl Drop for CommandResponse {
+    fn drop(&mut self) {
+        unsafe { Schema_DestroyCommandResponse(self.inner.as_ptr()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(value: u32) -> ComponentData {
+        let mut data = ComponentData::new();
+        data.get_fields_mut().add_uint32(1, value);
+        data
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        let original = data(1);
+        let mut clone = original.clone();
+        clone.get_fields_mut().add_uint32(2, 2);
+        drop(original);
+        assert_eq!(clone.get_fields().get_uint32(1), 1);
+        assert_eq!(clone.get_fields().get_uint32_count(2), 1);
+
+        let mut request = CommandRequest::new();
+        request.get_object_mut().add_string(1, "request");
+        let copy = request.clone();
+        drop(request);
+        assert_eq!(copy.get_object().get_string(1), "request");
+    }
+
+    #[test]
+    fn raw_pointers_transfer_ownership() {
+        let raw: *mut ffi::ComponentData = data(3).into();
+        let data = ComponentData::from(raw);
+        assert_eq!(data.get_fields().get_uint32(1), 3);
+
+        let mut response = CommandResponse::new();
+        response.get_object_mut().add_bytes(1, &[1, 2, 3]);
+        let raw: *mut ffi::CommandResponse = response.into();
+        let response = CommandResponse::from(raw);
+        assert_eq!(response.get_object().get_bytes(1), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn data_converts_into_an_update() {
+        let update = data(4).into_update();
+        assert_eq!(update.get_fields().get_uint32(1), 4);
+    }
+
+    #[test]
+    fn updates_are_applied_and_merged() {
+        let mut first = ComponentUpdate::new();
+        first.get_fields_mut().add_uint32(1, 5);
+        let mut second = ComponentUpdate::new();
+        second.add_cleared_field(2);
+        first.merge(&second).unwrap();
+        assert!(first.is_field_cleared(2));
+
+        let mut target = data(1);
+        target.get_fields_mut().add_string(2, "cleared");
+        target.apply_update(&first).unwrap();
+        assert_eq!(target.get_fields().get_uint32(1), 5);
+        assert_eq!(target.get_fields().get_bytes_count(2), 0);
+    }
+
+    #[test]
+    fn diffs_turn_old_data_into_new_data() {
+        let mut old = data(1);
+        old.get_fields_mut().add_string(2, "unchanged");
+        old.get_fields_mut().add_uint32(3, 3);
+        let mut new = data(8);
+        new.get_fields_mut().add_string(2, "unchanged");
+
+        let update = ComponentData::diff(&old, &new).unwrap();
+        assert_eq!(update.get_fields().get_unique_field_ids(), vec![1]);
+        assert_eq!(update.get_cleared_fields(), vec![3]);
+
+        old.apply_update(&update).unwrap();
+        assert_eq!(old.serialize(), new.serialize());
+    }
+
+    #[test]
+    fn serialized_data_round_trips() {
+        let mut original = data(6);
+        original.get_fields_mut().add_string(2, "name");
+        let copy = ComponentData::deserialize(&original.serialize()).unwrap();
+        assert_eq!(copy.get_fields().get_uint32(1), 6);
+        assert_eq!(copy.get_fields().get_string(2), "name");
+    }
+
+    #[test]
+    fn reading_an_object_does_not_add_it() {
+        let mut data = ComponentData::new();
+        assert!(data.get_fields().get_object(1).is_none());
+        assert_eq!(data.get_fields().get_object_count(1), 0);
+
+        data.get_fields_mut().get_object_mut(1).add_uint32(1, 7);
+        let fields = data.get_fields();
+        assert_eq!(fields.get_object_count(1), 1);
+        assert_eq!(fields.get_object(1).unwrap().get_uint32(1), 7);
     }
 }
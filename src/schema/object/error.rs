@@ -0,0 +1,69 @@
+use crate::schema::FieldId;
+use std::fmt;
+use std::str::Utf8Error;
+
+#[derive(Debug, Clone, PartialEq)]
+/// An error raised while reading or parsing a schema object.
+pub enum SchemaError {
+    /// A field expected to hold a value is not set.
+    MissingField(FieldId),
+    /// A singular field holds more than one value.
+    WrongCount { field_id: FieldId, count: u32 },
+    /// A string field holds bytes which are not valid UTF-8.
+    InvalidUtf8 { field_id: FieldId, error: Utf8Error },
+    /// Several struct fields are mapped to the same field ID.
+    DuplicateField(FieldId),
+    /// A buffer could not be merged into an object. Holds the error reported by the SDK.
+    InvalidBuffer(String),
+    /// An update could not be applied to data or merged into another update. Holds the error
+    /// reported by the SDK.
+    InvalidUpdate(String),
+    /// A type is not declared in the schema descriptor it was looked up in.
+    UnknownType(String),
+    /// A schema descriptor could not be loaded.
+    InvalidDescriptor(String),
+    /// A value has no representation in schema.
+    Unsupported(&'static str),
+    /// An error raised by a Serialize or Deserialize implementation.
+    Custom(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::MissingField(field_id) => write!(f, "field {} is not set", field_id),
+            SchemaError::WrongCount { field_id, count } => write!(
+                f,
+                "field {} holds {} values but a single one was expected",
+                field_id, count
+            ),
+            SchemaError::InvalidUtf8 { field_id, error } => {
+                write!(f, "field {} is not a valid string: {}", field_id, error)
+            }
+            SchemaError::DuplicateField(field_id) => {
+                write!(f, "field {} is used by several struct fields", field_id)
+            }
+            SchemaError::InvalidBuffer(message) => {
+                write!(f, "could not parse schema buffer: {}", message)
+            }
+            SchemaError::InvalidUpdate(message) => {
+                write!(f, "could not apply component update: {}", message)
+            }
+            SchemaError::UnknownType(name) => write!(f, "unknown schema type {}", name),
+            SchemaError::InvalidDescriptor(message) => {
+                write!(f, "invalid schema descriptor: {}", message)
+            }
+            SchemaError::Unsupported(what) => write!(f, "{} cannot be represented in schema", what),
+            SchemaError::Custom(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemaError::InvalidUtf8 { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
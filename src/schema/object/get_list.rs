@@ -2,22 +2,19 @@ use spatialos_sys::{
     Schema_GetBoolList, Schema_GetDoubleList, Schema_GetEntityIdList, Schema_GetEnumList,
     Schema_GetFixed32List, Schema_GetFixed64List, Schema_GetFloatList, Schema_GetInt32List,
     Schema_GetInt64List, Schema_GetSfixed32List, Schema_GetSfixed64List, Schema_GetSint32List,
-    Schema_GetSint64List, Schema_GetUint32List, Schema_GetUint64List, Schema_Object,
+    Schema_GetSint64List, Schema_GetUint32List, Schema_GetUint64List,
 };
 
-use super::Object;
+use super::ObjectRef;
 use crate::schema::{EntityId, FieldId};
 
-impl Object {
+impl ObjectRef<'_> {
     pub fn get_sint64_list(&self, field_id: FieldId) -> Vec<i64> {
         let count = self.get_sint64_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetSint64List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetSint64List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -26,11 +23,8 @@ impl Object {
         let count = self.get_sint32_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetSint32List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetSint32List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -38,11 +32,8 @@ impl Object {
         let count = self.get_sfixed64_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetSfixed64List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetSfixed64List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -51,11 +42,8 @@ impl Object {
         let count = self.get_sfixed32_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetSfixed32List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetSfixed32List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -64,11 +52,8 @@ impl Object {
         let count = self.get_int64_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetInt64List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetInt64List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -77,11 +62,8 @@ impl Object {
         let count = self.get_int32_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetInt32List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetInt32List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -90,11 +72,8 @@ impl Object {
         let count = self.get_fixed64_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetFixed64List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetFixed64List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -103,11 +82,8 @@ impl Object {
         let count = self.get_fixed32_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetFixed32List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetFixed32List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -116,11 +92,8 @@ impl Object {
         let count = self.get_entity_id_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetEntityIdList(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetEntityIdList(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -129,11 +102,8 @@ impl Object {
         let count = self.get_bool_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetBoolList(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetBoolList(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list.into_iter().map(|b| b == 1).collect()
     }
@@ -142,11 +112,8 @@ impl Object {
         let count = self.get_float_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetFloatList(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetFloatList(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -155,11 +122,8 @@ impl Object {
         let count = self.get_double_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetDoubleList(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetDoubleList(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -168,11 +132,8 @@ impl Object {
         let count = self.get_uint32_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetUint32List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetUint32List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -181,11 +142,8 @@ impl Object {
         let count = self.get_uint64_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetUint64List(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetUint64List(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list
     }
@@ -218,7 +176,7 @@ impl Object {
     }
 
     pub fn get_optional_bytes_list(&self, field_id: FieldId) -> Option<Vec<Vec<u8>>> {
-        let count = self.get_double_count(field_id);
+        let count = self.get_bytes_count(field_id);
         if count > 0 {
             Some(self.get_bytes_list(field_id))
         } else {
@@ -238,12 +196,90 @@ impl Object {
         let count = self.get_enum_count(field_id);
         let mut list = Vec::with_capacity(count as usize);
         unsafe {
-            Schema_GetEnumList(
-                &*self.inner as *const Schema_Object,
-                field_id,
-                list.as_mut_ptr(),
-            )
+            Schema_GetEnumList(self.as_ptr(), field_id, list.as_mut_ptr());
+            list.set_len(count as usize);
         }
         list.into_iter().map(E::from).collect::<Vec<E>>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::improbable::ConnectionStatus;
+    use crate::schema::CommandRequest;
+
+    #[test]
+    fn lists_return_every_element_added() {
+        let mut request = CommandRequest::new();
+        let mut object = request.get_object_mut();
+        object.add_entity_id_list(1, &[1, -2, 3]);
+        object.add_bool_list(2, &[true, false, true]);
+        object.add_float_list(3, &[1.5, -2.0]);
+        object.add_double_list(4, &[0.25, 8.0, -1.0]);
+        object.add_int32_list(5, &[-1, 2]);
+        object.add_int64_list(6, &[i64::MIN, 0, i64::MAX]);
+        object.add_uint32_list(7, &[1, u32::MAX]);
+        object.add_uint64_list(8, &[u64::MAX, 2, 3]);
+        object.add_sint32_list(9, &[i32::MIN, -1]);
+        object.add_sint64_list(10, &[-5, 6, -7]);
+        object.add_fixed32_list(11, &[9, 10]);
+        object.add_fixed64_list(12, &[11, 12, 13]);
+        object.add_sfixed32_list(13, &[-14, 15]);
+        object.add_sfixed64_list(14, &[-16, 17, -18]);
+        object.add_bytes_list(15, &[b"ab", b"", b"c"]);
+        object.add_string_list(16, &["x".to_owned(), "yz".to_owned()]);
+        object.add_enum_list(
+            17,
+            &[ConnectionStatus::Connected, ConnectionStatus::Unknown],
+        );
+
+        let object = request.get_object();
+        assert_eq!(object.get_entity_id_list(1), vec![1, -2, 3]);
+        assert_eq!(object.get_bool_list(2), vec![true, false, true]);
+        assert_eq!(object.get_float_list(3), vec![1.5, -2.0]);
+        assert_eq!(object.get_double_list(4), vec![0.25, 8.0, -1.0]);
+        assert_eq!(object.get_int32_list(5), vec![-1, 2]);
+        assert_eq!(object.get_int64_list(6), vec![i64::MIN, 0, i64::MAX]);
+        assert_eq!(object.get_uint32_list(7), vec![1, u32::MAX]);
+        assert_eq!(object.get_uint64_list(8), vec![u64::MAX, 2, 3]);
+        assert_eq!(object.get_sint32_list(9), vec![i32::MIN, -1]);
+        assert_eq!(object.get_sint64_list(10), vec![-5, 6, -7]);
+        assert_eq!(object.get_fixed32_list(11), vec![9, 10]);
+        assert_eq!(object.get_fixed64_list(12), vec![11, 12, 13]);
+        assert_eq!(object.get_sfixed32_list(13), vec![-14, 15]);
+        assert_eq!(object.get_sfixed64_list(14), vec![-16, 17, -18]);
+        assert_eq!(
+            object.get_bytes_list(15),
+            vec![b"ab".to_vec(), Vec::new(), b"c".to_vec()]
+        );
+        assert_eq!(object.get_string_list(16), vec!["x", "yz"]);
+        assert_eq!(
+            object.get_enum_list::<ConnectionStatus>(17),
+            vec![ConnectionStatus::Connected, ConnectionStatus::Unknown]
+        );
+    }
+
+    #[test]
+    fn optional_lists_are_none_when_empty() {
+        let mut request = CommandRequest::new();
+        let mut object = request.get_object_mut();
+        object.add_double_list(1, &[1.0, 2.0]);
+        object.add_uint32_list(2, &[3]);
+        object.add_bytes_list(3, &[b"a"]);
+        object.add_string_list(4, &["b".to_owned()]);
+
+        let object = request.get_object();
+        assert_eq!(object.get_optional_double_list(1), Some(vec![1.0, 2.0]));
+        assert_eq!(object.get_optional_uint32_list(2), Some(vec![3]));
+        assert_eq!(object.get_optional_bytes_list(3), Some(vec![b"a".to_vec()]));
+        assert_eq!(
+            object.get_optional_string_list(4),
+            Some(vec!["b".to_owned()])
+        );
+        assert_eq!(object.get_optional_double_list(5), None);
+        assert_eq!(object.get_optional_uint32_list(5), None);
+        assert_eq!(object.get_optional_bytes_list(5), None);
+        assert_eq!(object.get_optional_string_list(5), None);
+        assert!(object.get_uint64_list(5).is_empty());
+    }
+}
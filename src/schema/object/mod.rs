@@ -1,16 +1,25 @@
 use spatialos_sys::{
-    Schema_AllocateBuffer, Schema_Clear, Schema_ClearField, Schema_GetBytesLength, Schema_Object,
+    Schema_AllocateBuffer, Schema_Clear, Schema_ClearField, Schema_GetBytesLength, Schema_GetError,
+    Schema_GetObject, Schema_GetUniqueFieldIdCount, Schema_GetUniqueFieldIds,
+    Schema_GetWriteBufferLength, Schema_MergeFromBuffer, Schema_Object, Schema_SerializeToBuffer,
 };
 
+use crate::const_to_string;
 use crate::schema::FieldId;
-use bytevec::ByteEncodable;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::NonNull;
 
 mod add;
 mod add_list;
 mod count;
+mod error;
 mod get;
 mod get_list;
 mod index;
+mod try_get;
+
+pub use error::SchemaError;
 
 pub struct AllocatedBuffer {
     pub inner: *mut u8,
@@ -22,38 +31,127 @@ impl From<*mut u8> for AllocatedBuffer {
     }
 }
 
-pub struct Object {
-    inner: Box<Schema_Object>,
+/// A read-only view of a schema object. It borrows from the data, update, command or parent
+/// object it was retrieved from, which keeps ownership of the underlying memory.
+pub struct ObjectRef<'a> {
+    inner: NonNull<Schema_Object>,
+    _marker: PhantomData<&'a Schema_Object>,
+}
+
+/// A mutable view of a schema object, borrowed from its parent like ObjectRef. Every getter of
+/// ObjectRef is available through Deref.
+pub struct ObjectMut<'a> {
+    object: ObjectRef<'a>,
+    _marker: PhantomData<&'a mut Schema_Object>,
 }
 
-impl From<*mut Schema_Object> for Object {
-    fn from(inner: *mut Schema_Object) -> Self {
-        let inner = unsafe { Box::from_raw(inner) };
-        Self { inner }
+impl<'a> ObjectRef<'a> {
+    /// Creates a view of the given object.
+    ///
+    /// # Safety
+    ///
+    /// The object must be valid and must not be modified or destroyed for the lifetime 'a.
+    pub unsafe fn from_raw(inner: *mut Schema_Object) -> Self {
+        Self {
+            inner: NonNull::new(inner).expect("schema object is null"),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reborrows the object, so that it can be handed to a function taking it by value.
+    pub fn reborrow(&self) -> ObjectRef<'_> {
+        unsafe { ObjectRef::from_raw(self.as_ptr()) }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut Schema_Object {
+        self.inner.as_ptr()
+    }
+
+    pub fn get_bytes_length(&self, field_id: FieldId) -> u32 {
+        unsafe { Schema_GetBytesLength(self.as_ptr(), field_id) }
+    }
+
+    /// Returns the IDs of the fields holding at least one value, in ascending order.
+    pub fn get_unique_field_ids(&self) -> Vec<FieldId> {
+        let count = unsafe { Schema_GetUniqueFieldIdCount(self.as_ptr()) };
+        let mut field_ids = vec![0; count as usize];
+        unsafe { Schema_GetUniqueFieldIds(self.as_ptr(), field_ids.as_mut_ptr()) };
+        field_ids
+    }
+
+    /// Serializes the object into the SpatialOS wire format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let length = unsafe { Schema_GetWriteBufferLength(self.as_ptr()) };
+        let mut buffer = vec![0; length as usize];
+        unsafe { Schema_SerializeToBuffer(self.as_ptr(), buffer.as_mut_ptr(), length) };
+        buffer
     }
 }
 
-impl Object {
-    pub fn allocate_buffer<T: ByteEncodable>(&mut self, data: &[T]) -> AllocatedBuffer {
-        let src = data.encode::<u8>().unwrap();
+impl<'a> ObjectMut<'a> {
+    /// Creates a mutable view of the given object.
+    ///
+    /// # Safety
+    ///
+    /// The object must be valid, and must not be accessed through any other view or destroyed
+    /// for the lifetime 'a.
+    pub unsafe fn from_raw(inner: *mut Schema_Object) -> Self {
+        Self {
+            object: ObjectRef::from_raw(inner),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reborrows the object, so that it can be handed to a function taking it by value.
+    pub fn reborrow(&mut self) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(self.as_ptr()) }
+    }
+
+    /// Copies the data into a buffer owned by the object, which lives as long as the object.
+    pub fn allocate_buffer(&mut self, data: &[u8]) -> AllocatedBuffer {
         let buffer = unsafe {
-            let buffer = Schema_AllocateBuffer(
-                &mut *self.inner as *mut Schema_Object,
-                std::mem::size_of::<T>() as u32 * data.len() as u32,
-            );
-            std::ptr::copy_nonoverlapping(src.as_ptr(), buffer, data.len());
+            let buffer = Schema_AllocateBuffer(self.as_ptr(), data.len() as u32);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
             buffer
         };
         AllocatedBuffer::from(buffer)
     }
-    pub fn get_bytes_length(&self, field_id: FieldId) -> u32 {
-        unsafe { Schema_GetBytesLength(&*self.inner as *const Schema_Object, field_id) }
+
+    /// Returns the last object of the field, adding an empty object to the field if it holds
+    /// none.
+    pub fn get_object_mut(&mut self, field_id: FieldId) -> ObjectMut<'_> {
+        unsafe { ObjectMut::from_raw(Schema_GetObject(self.as_ptr(), field_id)) }
     }
+
     pub fn clear(&mut self) {
-        unsafe { Schema_Clear(&mut *self.inner as *mut Schema_Object) }
+        unsafe { Schema_Clear(self.as_ptr()) }
     }
 
     pub fn clear_field(&mut self, field_id: FieldId) {
-        unsafe { Schema_ClearField(&mut *self.inner as *mut Schema_Object, field_id) }
+        unsafe { Schema_ClearField(self.as_ptr(), field_id) }
+    }
+
+    /// Merges fields serialized in the SpatialOS wire format into this object.
+    pub fn merge_from_buffer(&mut self, buffer: &[u8]) -> Result<(), SchemaError> {
+        let merged = unsafe {
+            Schema_MergeFromBuffer(self.as_ptr(), buffer.as_ptr(), buffer.len() as u32) != 0
+        };
+        if merged {
+            return Ok(());
+        }
+        let error = unsafe { Schema_GetError(self.as_ptr()) };
+        Err(SchemaError::InvalidBuffer(if error.is_null() {
+            String::new()
+        } else {
+            const_to_string(error)
+        }))
+    }
+}
+
+impl<'a> Deref for ObjectMut<'a> {
+    type Target = ObjectRef<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.object
     }
 }
@@ -0,0 +1,240 @@
+//! Getters which tell an absent field apart from one holding its default value, and report
+//! malformed fields instead of panicking. A field which is not set is returned as None.
+
+use super::{ObjectRef, SchemaError};
+use crate::schema::{EntityId, FieldId};
+
+impl ObjectRef<'_> {
+    pub fn try_get_bool(&self, field_id: FieldId) -> Result<Option<bool>, SchemaError> {
+        single(field_id, self.get_bool_count(field_id), || {
+            self.get_bool(field_id)
+        })
+    }
+
+    pub fn try_get_float(&self, field_id: FieldId) -> Result<Option<f32>, SchemaError> {
+        single(field_id, self.get_float_count(field_id), || {
+            self.get_float(field_id)
+        })
+    }
+
+    pub fn try_get_double(&self, field_id: FieldId) -> Result<Option<f64>, SchemaError> {
+        single(field_id, self.get_double_count(field_id), || {
+            self.get_double(field_id)
+        })
+    }
+
+    pub fn try_get_int32(&self, field_id: FieldId) -> Result<Option<i32>, SchemaError> {
+        single(field_id, self.get_int32_count(field_id), || {
+            self.get_int32(field_id)
+        })
+    }
+
+    pub fn try_get_int64(&self, field_id: FieldId) -> Result<Option<i64>, SchemaError> {
+        single(field_id, self.get_int64_count(field_id), || {
+            self.get_int64(field_id)
+        })
+    }
+
+    pub fn try_get_uint32(&self, field_id: FieldId) -> Result<Option<u32>, SchemaError> {
+        single(field_id, self.get_uint32_count(field_id), || {
+            self.get_uint32(field_id)
+        })
+    }
+
+    pub fn try_get_uint64(&self, field_id: FieldId) -> Result<Option<u64>, SchemaError> {
+        single(field_id, self.get_uint64_count(field_id), || {
+            self.get_uint64(field_id)
+        })
+    }
+
+    pub fn try_get_sint32(&self, field_id: FieldId) -> Result<Option<i32>, SchemaError> {
+        single(field_id, self.get_sint32_count(field_id), || {
+            self.get_sint32(field_id)
+        })
+    }
+
+    pub fn try_get_sint64(&self, field_id: FieldId) -> Result<Option<i64>, SchemaError> {
+        single(field_id, self.get_sint64_count(field_id), || {
+            self.get_sint64(field_id)
+        })
+    }
+
+    pub fn try_get_fixed32(&self, field_id: FieldId) -> Result<Option<u32>, SchemaError> {
+        single(field_id, self.get_fixed32_count(field_id), || {
+            self.get_fixed32(field_id)
+        })
+    }
+
+    pub fn try_get_fixed64(&self, field_id: FieldId) -> Result<Option<u64>, SchemaError> {
+        single(field_id, self.get_fixed64_count(field_id), || {
+            self.get_fixed64(field_id)
+        })
+    }
+
+    pub fn try_get_sfixed32(&self, field_id: FieldId) -> Result<Option<i32>, SchemaError> {
+        single(field_id, self.get_sfixed32_count(field_id), || {
+            self.get_sfixed32(field_id)
+        })
+    }
+
+    pub fn try_get_sfixed64(&self, field_id: FieldId) -> Result<Option<i64>, SchemaError> {
+        single(field_id, self.get_sfixed64_count(field_id), || {
+            self.get_sfixed64(field_id)
+        })
+    }
+
+    pub fn try_get_entity_id(&self, field_id: FieldId) -> Result<Option<EntityId>, SchemaError> {
+        single(field_id, self.get_entity_id_count(field_id), || {
+            self.get_entity_id(field_id)
+        })
+    }
+
+    pub fn try_get_enum<E: From<u32>>(&self, field_id: FieldId) -> Result<Option<E>, SchemaError> {
+        single(field_id, self.get_enum_count(field_id), || {
+            self.get_enum(field_id)
+        })
+    }
+
+    pub fn try_get_bytes(&self, field_id: FieldId) -> Result<Option<Vec<u8>>, SchemaError> {
+        single(field_id, self.get_bytes_count(field_id), || {
+            self.get_bytes(field_id)
+        })
+    }
+
+    pub fn try_get_string(&self, field_id: FieldId) -> Result<Option<String>, SchemaError> {
+        match self.try_get_bytes(field_id)? {
+            Some(bytes) => to_string(field_id, bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn try_get_object(&self, field_id: FieldId) -> Result<Option<ObjectRef<'_>>, SchemaError> {
+        single(field_id, self.get_object_count(field_id), || {
+            self.index_object(field_id, 0)
+        })
+    }
+
+    pub fn try_index_string(&self, field_id: FieldId, index: u32) -> Result<String, SchemaError> {
+        to_string(field_id, self.index_bytes(field_id, index))
+    }
+
+    pub fn try_get_string_list(&self, field_id: FieldId) -> Result<Vec<String>, SchemaError> {
+        (0..self.get_bytes_count(field_id))
+            .map(|index| self.try_index_string(field_id, index))
+            .collect()
+    }
+}
+
+fn single<T, F: FnOnce() -> T>(
+    field_id: FieldId,
+    count: u32,
+    get: F,
+) -> Result<Option<T>, SchemaError> {
+    match count {
+        0 => Ok(None),
+        1 => Ok(Some(get())),
+        count => Err(SchemaError::WrongCount { field_id, count }),
+    }
+}
+
+fn to_string(field_id: FieldId, bytes: Vec<u8>) -> Result<String, SchemaError> {
+    String::from_utf8(bytes).map_err(|error| SchemaError::InvalidUtf8 {
+        field_id,
+        error: error.utf8_error(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::CommandRequest;
+
+    #[test]
+    fn absent_fields_are_none() {
+        let request = CommandRequest::new();
+        let object = request.get_object();
+        assert_eq!(object.try_get_bool(1), Ok(None));
+        assert_eq!(object.try_get_double(1), Ok(None));
+        assert_eq!(object.try_get_sint64(1), Ok(None));
+        assert_eq!(object.try_get_enum::<u32>(1), Ok(None));
+        assert_eq!(object.try_get_string(1), Ok(None));
+        assert!(object.try_get_object(1).unwrap().is_none());
+        assert_eq!(object.try_get_string_list(1), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn single_values_are_returned() {
+        let mut request = CommandRequest::new();
+        let mut object = request.get_object_mut();
+        object.add_uint32(1, 7);
+        object.add_string(2, "seven");
+        object.add_object(3).add_int32(1, -7);
+
+        let object = request.get_object();
+        assert_eq!(object.try_get_uint32(1), Ok(Some(7)));
+        assert_eq!(object.try_get_string(2), Ok(Some("seven".to_owned())));
+        let nested = object.try_get_object(3).unwrap().unwrap();
+        assert_eq!(nested.try_get_int32(1), Ok(Some(-7)));
+    }
+
+    #[test]
+    fn repeated_values_are_a_wrong_count() {
+        let mut request = CommandRequest::new();
+        let mut object = request.get_object_mut();
+        object.add_uint32_list(1, &[1, 2, 3]);
+        object.add_string_list(2, &["a".to_owned(), "b".to_owned()]);
+        object.add_object(4);
+        object.add_object(4);
+
+        let object = request.get_object();
+        assert_eq!(
+            object.try_get_uint32(1),
+            Err(SchemaError::WrongCount {
+                field_id: 1,
+                count: 3
+            })
+        );
+        assert_eq!(
+            object.try_get_string(2),
+            Err(SchemaError::WrongCount {
+                field_id: 2,
+                count: 2
+            })
+        );
+        assert_eq!(
+            object.try_get_object(4).err(),
+            Some(SchemaError::WrongCount {
+                field_id: 4,
+                count: 2
+            })
+        );
+        assert_eq!(
+            object.try_get_string_list(2),
+            Ok(vec!["a".to_owned(), "b".to_owned()])
+        );
+    }
+
+    #[test]
+    fn invalid_strings_are_reported() {
+        let mut request = CommandRequest::new();
+        let mut object = request.get_object_mut();
+        object.add_bytes(1, &[0xff, 0xfe]);
+        object.add_string(2, "valid");
+        object.add_bytes(2, &[b'a', 0xc0]);
+
+        let object = request.get_object();
+        assert!(matches!(
+            object.try_get_string(1),
+            Err(SchemaError::InvalidUtf8 { field_id: 1, .. })
+        ));
+        assert_eq!(object.try_get_bytes(1), Ok(Some(vec![0xff, 0xfe])));
+        assert_eq!(object.try_index_string(2, 0), Ok("valid".to_owned()));
+        match object.try_get_string_list(2) {
+            Err(SchemaError::InvalidUtf8 { field_id, error }) => {
+                assert_eq!(field_id, 2);
+                assert_eq!(error.valid_up_to(), 1);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,1627 @@
+//! Serde support for schema objects, so that any type implementing Serialize and Deserialize can
+//! be written into and read from an object.
+//!
+//! Struct fields are mapped to field IDs through their serialized name: a field renamed with
+//! `#[serde(rename = "3")]` is stored in field 3, while fields whose name is not a number are
+//! numbered from 1 in declaration order. Structs with several fields mapped to the same field ID
+//! are rejected with SchemaError::DuplicateField. Integers use the `int32`, `int64`, `uint32` and
+//! `uint64` wire types, unless they are wrapped in one of the hint types of this module, such as
+//! Sint32, or annotated with the matching module, such as
+//! `#[serde(with = "schema::serde::sint32")]`.
+//!
+//! Options are written as absent fields, sequences as list fields, maps as schema maps, and
+//! nested structs and tuples as objects. Enums may only hold unit variants, which are written as
+//! their variant index.
+
+use crate::schema::{
+    FieldId, ObjectMut, ObjectRef, SchemaError, MAP_KEY_FIELD_ID, MAP_VALUE_FIELD_ID,
+};
+use ::serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use ::serde::ser::{self, Impossible, Serialize};
+use ::serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Writes the fields of a struct into the given object.
+pub fn to_object<T: Serialize + ?Sized>(
+    value: &T,
+    object: &mut ObjectMut,
+) -> Result<(), SchemaError> {
+    value.serialize(ObjectSerializer {
+        object: object.reborrow(),
+    })
+}
+
+/// Reads a struct from the fields of the given object.
+pub fn from_object<T: DeserializeOwned>(object: &ObjectRef) -> Result<T, SchemaError> {
+    T::deserialize(ObjectDeserializer {
+        object: object.reborrow(),
+    })
+}
+
+impl ser::Error for SchemaError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SchemaError::Custom(message.to_string())
+    }
+}
+
+impl de::Error for SchemaError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SchemaError::Custom(message.to_string())
+    }
+}
+
+const SINT32: &str = "$spatialos::sint32";
+const SINT64: &str = "$spatialos::sint64";
+const FIXED32: &str = "$spatialos::fixed32";
+const FIXED64: &str = "$spatialos::fixed64";
+const SFIXED32: &str = "$spatialos::sfixed32";
+const SFIXED64: &str = "$spatialos::sfixed64";
+
+/// The wire type requested for an integer, carried through the name of a newtype struct.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hint {
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+}
+
+impl Hint {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            SINT32 => Some(Hint::Sint32),
+            SINT64 => Some(Hint::Sint64),
+            FIXED32 => Some(Hint::Fixed32),
+            FIXED64 => Some(Hint::Fixed64),
+            SFIXED32 => Some(Hint::Sfixed32),
+            SFIXED64 => Some(Hint::Sfixed64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// An i32 written with the `sint32` wire type.
+pub struct Sint32(pub i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// An i64 written with the `sint64` wire type.
+pub struct Sint64(pub i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// A u32 written with the `fixed32` wire type.
+pub struct Fixed32(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// A u64 written with the `fixed64` wire type.
+pub struct Fixed64(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// An i32 written with the `sfixed32` wire type.
+pub struct Sfixed32(pub i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// An i64 written with the `sfixed64` wire type.
+pub struct Sfixed64(pub i64);
+
+impl Serialize for Sint32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SINT32, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sint32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(SINT32, HintVisitor(PhantomData))
+            .map(Sint32)
+    }
+}
+
+impl Serialize for Sint64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SINT64, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sint64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(SINT64, HintVisitor(PhantomData))
+            .map(Sint64)
+    }
+}
+
+impl Serialize for Fixed32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(FIXED32, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(FIXED32, HintVisitor(PhantomData))
+            .map(Fixed32)
+    }
+}
+
+impl Serialize for Fixed64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(FIXED64, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(FIXED64, HintVisitor(PhantomData))
+            .map(Fixed64)
+    }
+}
+
+impl Serialize for Sfixed32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SFIXED32, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sfixed32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(SFIXED32, HintVisitor(PhantomData))
+            .map(Sfixed32)
+    }
+}
+
+impl Serialize for Sfixed64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SFIXED64, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sfixed64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(SFIXED64, HintVisitor(PhantomData))
+            .map(Sfixed64)
+    }
+}
+
+/// Serializes an i32 field with the `sint32` wire type, without wrapping it in Sint32.
+pub mod sint32 {
+    use super::Sint32;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+        Sint32(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+        Sint32::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// Serializes an i64 field with the `sint64` wire type, without wrapping it in Sint64.
+pub mod sint64 {
+    use super::Sint64;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        Sint64(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        Sint64::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// Serializes a u32 field with the `fixed32` wire type, without wrapping it in Fixed32.
+pub mod fixed32 {
+    use super::Fixed32;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        Fixed32(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        Fixed32::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// Serializes a u64 field with the `fixed64` wire type, without wrapping it in Fixed64.
+pub mod fixed64 {
+    use super::Fixed64;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        Fixed64(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        Fixed64::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// Serializes an i32 field with the `sfixed32` wire type, without wrapping it in Sfixed32.
+pub mod sfixed32 {
+    use super::Sfixed32;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+        Sfixed32(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+        Sfixed32::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// Serializes an i64 field with the `sfixed64` wire type, without wrapping it in Sfixed64.
+pub mod sfixed64 {
+    use super::Sfixed64;
+    use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        Sfixed64(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        Sfixed64::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+struct HintVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for HintVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+/// Returns the field ID of a struct field, from its serialized name or its position.
+fn field_id(name: &str, position: FieldId) -> FieldId {
+    name.parse().unwrap_or(position)
+}
+
+/// Records a field ID as used by a struct, and fails if it already was, for example by a field
+/// renamed to the position of another field.
+fn claim_field_id(used: &mut Vec<FieldId>, field_id: FieldId) -> Result<(), SchemaError> {
+    if used.contains(&field_id) {
+        return Err(SchemaError::DuplicateField(field_id));
+    }
+    used.push(field_id);
+    Ok(())
+}
+
+/// Returns the number of values held by a field, whatever its type.
+fn value_count(object: &ObjectRef, field_id: FieldId) -> u32 {
+    object
+        .get_uint64_count(field_id)
+        .max(object.get_fixed32_count(field_id))
+        .max(object.get_fixed64_count(field_id))
+        .max(object.get_bytes_count(field_id))
+        .max(object.get_object_count(field_id))
+}
+
+fn not_a_struct() -> SchemaError {
+    SchemaError::Unsupported("a value other than a struct at the top level of an object")
+}
+
+fn enum_with_data() -> SchemaError {
+    SchemaError::Unsupported("an enum variant holding data")
+}
+
+/// Serializes a struct into the fields of an object.
+struct ObjectSerializer<'o> {
+    object: ObjectMut<'o>,
+}
+
+impl<'o> Serializer for ObjectSerializer<'o> {
+    type Ok = ();
+    type Error = SchemaError;
+    type SerializeSeq = Impossible<(), SchemaError>;
+    type SerializeTuple = Impossible<(), SchemaError>;
+    type SerializeTupleStruct = Impossible<(), SchemaError>;
+    type SerializeTupleVariant = Impossible<(), SchemaError>;
+    type SerializeMap = Impossible<(), SchemaError>;
+    type SerializeStruct = StructSerializer<'o>;
+    type SerializeStructVariant = Impossible<(), SchemaError>;
+
+    fn serialize_bool(self, _value: bool) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_i8(self, _value: i8) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_i16(self, _value: i16) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_i32(self, _value: i32) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_i64(self, _value: i64) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_u8(self, _value: u8) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_u16(self, _value: u16) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_u32(self, _value: u32) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_u64(self, _value: u64) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_f32(self, _value: f32) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_f64(self, _value: f64) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_char(self, _value: char) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_str(self, _value: &str) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_none(self) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_unit(self) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SchemaError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SchemaError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SchemaError> {
+        Ok(StructSerializer::new(self.object))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SchemaError> {
+        Err(not_a_struct())
+    }
+}
+
+/// Serializes a value into a field of an object. Values serialized within a list are appended to
+/// the same field.
+struct FieldSerializer<'a, 'o> {
+    object: &'a mut ObjectMut<'o>,
+    field_id: FieldId,
+    hint: Option<Hint>,
+    in_list: bool,
+}
+
+impl<'a, 'o> FieldSerializer<'a, 'o> {
+    fn new(object: &'a mut ObjectMut<'o>, field_id: FieldId) -> Self {
+        Self {
+            object,
+            field_id,
+            hint: None,
+            in_list: false,
+        }
+    }
+}
+
+impl<'a, 'o> Serializer for FieldSerializer<'a, 'o> {
+    type Ok = ();
+    type Error = SchemaError;
+    type SerializeSeq = ListSerializer<'a, 'o>;
+    type SerializeTuple = StructSerializer<'a>;
+    type SerializeTupleStruct = StructSerializer<'a>;
+    type SerializeTupleVariant = Impossible<(), SchemaError>;
+    type SerializeMap = MapSerializer<'a, 'o>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = Impossible<(), SchemaError>;
+
+    fn serialize_bool(self, value: bool) -> Result<(), SchemaError> {
+        self.object.add_bool(self.field_id, value);
+        Ok(())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), SchemaError> {
+        self.serialize_i32(value.into())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), SchemaError> {
+        self.serialize_i32(value.into())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), SchemaError> {
+        match self.hint {
+            Some(Hint::Sint32) => self.object.add_sint32(self.field_id, value),
+            Some(Hint::Sfixed32) => self.object.add_sfixed32(self.field_id, value),
+            _ => self.object.add_int32(self.field_id, value),
+        }
+        Ok(())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), SchemaError> {
+        match self.hint {
+            Some(Hint::Sint64) => self.object.add_sint64(self.field_id, value),
+            Some(Hint::Sfixed64) => self.object.add_sfixed64(self.field_id, value),
+            _ => self.object.add_int64(self.field_id, value),
+        }
+        Ok(())
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), SchemaError> {
+        self.serialize_u32(value.into())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), SchemaError> {
+        self.serialize_u32(value.into())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), SchemaError> {
+        match self.hint {
+            Some(Hint::Fixed32) => self.object.add_fixed32(self.field_id, value),
+            _ => self.object.add_uint32(self.field_id, value),
+        }
+        Ok(())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), SchemaError> {
+        match self.hint {
+            Some(Hint::Fixed64) => self.object.add_fixed64(self.field_id, value),
+            _ => self.object.add_uint64(self.field_id, value),
+        }
+        Ok(())
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), SchemaError> {
+        self.object.add_float(self.field_id, value);
+        Ok(())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), SchemaError> {
+        self.object.add_double(self.field_id, value);
+        Ok(())
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), SchemaError> {
+        self.serialize_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), SchemaError> {
+        self.object.add_string(self.field_id, value);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), SchemaError> {
+        self.object.add_bytes(self.field_id, value);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), SchemaError> {
+        if self.in_list {
+            return Err(SchemaError::Unsupported("an option in a list"));
+        }
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SchemaError> {
+        if self.in_list {
+            return Err(SchemaError::Unsupported("an option in a list"));
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SchemaError> {
+        self.object.add_object(self.field_id);
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SchemaError> {
+        self.object.add_uint32(self.field_id, variant_index);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), SchemaError> {
+        let hint = Hint::from_name(name).or(self.hint);
+        value.serialize(FieldSerializer { hint, ..self })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), SchemaError> {
+        Err(enum_with_data())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SchemaError> {
+        if self.in_list {
+            return Err(SchemaError::Unsupported("a list of lists"));
+        }
+        Ok(ListSerializer {
+            object: self.object,
+            field_id: self.field_id,
+            hint: self.hint,
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SchemaError> {
+        Ok(StructSerializer::new(self.object.add_object(self.field_id)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SchemaError> {
+        Ok(StructSerializer::new(self.object.add_object(self.field_id)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SchemaError> {
+        Err(enum_with_data())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SchemaError> {
+        if self.in_list {
+            return Err(SchemaError::Unsupported("a list of maps"));
+        }
+        Ok(MapSerializer {
+            object: self.object,
+            field_id: self.field_id,
+            entry: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SchemaError> {
+        Ok(StructSerializer::new(self.object.add_object(self.field_id)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SchemaError> {
+        Err(enum_with_data())
+    }
+}
+
+/// Serializes the fields of a struct, or the elements of a tuple, into an object.
+struct StructSerializer<'o> {
+    object: ObjectMut<'o>,
+    position: FieldId,
+    field_ids: Vec<FieldId>,
+}
+
+impl<'o> StructSerializer<'o> {
+    fn new(object: ObjectMut<'o>) -> Self {
+        Self {
+            object,
+            position: 0,
+            field_ids: Vec::new(),
+        }
+    }
+
+    fn serialize_next<T: Serialize + ?Sized>(
+        &mut self,
+        name: Option<&str>,
+        value: &T,
+    ) -> Result<(), SchemaError> {
+        self.position += 1;
+        let field_id = name.map_or(self.position, |name| field_id(name, self.position));
+        claim_field_id(&mut self.field_ids, field_id)?;
+        value.serialize(FieldSerializer::new(&mut self.object, field_id))
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer<'_> {
+    type Ok = ();
+    type Error = SchemaError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SchemaError> {
+        self.serialize_next(Some(key), value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), SchemaError> {
+        self.position += 1;
+        claim_field_id(&mut self.field_ids, field_id(key, self.position))
+    }
+
+    fn end(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for StructSerializer<'_> {
+    type Ok = ();
+    type Error = SchemaError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SchemaError> {
+        self.serialize_next(None, value)
+    }
+
+    fn end(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for StructSerializer<'_> {
+    type Ok = ();
+    type Error = SchemaError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SchemaError> {
+        self.serialize_next(None, value)
+    }
+
+    fn end(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+}
+
+/// Serializes the elements of a sequence into a list field.
+struct ListSerializer<'a, 'o> {
+    object: &'a mut ObjectMut<'o>,
+    field_id: FieldId,
+    hint: Option<Hint>,
+}
+
+impl ser::SerializeSeq for ListSerializer<'_, '_> {
+    type Ok = ();
+    type Error = SchemaError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SchemaError> {
+        value.serialize(FieldSerializer {
+            object: &mut *self.object,
+            field_id: self.field_id,
+            hint: self.hint,
+            in_list: true,
+        })
+    }
+
+    fn end(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+}
+
+/// Serializes the entries of a map into a map field, as objects holding a key and a value.
+struct MapSerializer<'a, 'o> {
+    object: &'a mut ObjectMut<'o>,
+    field_id: FieldId,
+    entry: Option<ObjectMut<'a>>,
+}
+
+impl ser::SerializeMap for MapSerializer<'_, '_> {
+    type Ok = ();
+    type Error = SchemaError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SchemaError> {
+        // The entry is owned by the map object, which stays borrowed until the serializer ends.
+        let mut entry =
+            unsafe { ObjectMut::from_raw(self.object.add_object(self.field_id).as_ptr()) };
+        key.serialize(FieldSerializer::new(&mut entry, MAP_KEY_FIELD_ID))?;
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SchemaError> {
+        let mut entry = self
+            .entry
+            .take()
+            .ok_or_else(|| SchemaError::Custom("map value serialized before its key".into()))?;
+        value.serialize(FieldSerializer::new(&mut entry, MAP_VALUE_FIELD_ID))
+    }
+
+    fn end(self) -> Result<(), SchemaError> {
+        Ok(())
+    }
+}
+
+/// Deserializes a struct from the fields of an object.
+struct ObjectDeserializer<'o> {
+    object: ObjectRef<'o>,
+}
+
+impl<'de> Deserializer<'de> for ObjectDeserializer<'_> {
+    type Error = SchemaError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SchemaError> {
+        Err(not_a_struct())
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_map(StructFields::new(self.object, fields)?)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes a value from a field of an object, or from an element of a list field.
+struct FieldDeserializer<'a, 'o> {
+    object: &'a ObjectRef<'o>,
+    field_id: FieldId,
+    hint: Option<Hint>,
+    index: Option<u32>,
+}
+
+impl<'a, 'o> FieldDeserializer<'a, 'o> {
+    fn new(object: &'a ObjectRef<'o>, field_id: FieldId) -> Self {
+        Self {
+            object,
+            field_id,
+            hint: None,
+            index: None,
+        }
+    }
+
+    fn read<T>(
+        &self,
+        count: fn(&ObjectRef<'o>, FieldId) -> u32,
+        get: fn(&ObjectRef<'o>, FieldId) -> T,
+        index: fn(&ObjectRef<'o>, FieldId, u32) -> T,
+    ) -> Result<T, SchemaError> {
+        if let Some(position) = self.index {
+            return Ok(index(self.object, self.field_id, position));
+        }
+        match count(self.object, self.field_id) {
+            0 => Err(SchemaError::MissingField(self.field_id)),
+            1 => Ok(get(self.object, self.field_id)),
+            count => Err(SchemaError::WrongCount {
+                field_id: self.field_id,
+                count,
+            }),
+        }
+    }
+
+    fn read_string(&self) -> Result<String, SchemaError> {
+        let bytes = self.read(
+            ObjectRef::get_bytes_count,
+            ObjectRef::get_bytes,
+            ObjectRef::index_bytes,
+        )?;
+        String::from_utf8(bytes).map_err(|error| SchemaError::InvalidUtf8 {
+            field_id: self.field_id,
+            error: error.utf8_error(),
+        })
+    }
+
+    fn read_object(&self) -> Result<ObjectRef<'a>, SchemaError> {
+        let object = self.object;
+        if let Some(position) = self.index {
+            return Ok(object.index_object(self.field_id, position));
+        }
+        match object.get_object_count(self.field_id) {
+            0 => Err(SchemaError::MissingField(self.field_id)),
+            1 => Ok(object.index_object(self.field_id, 0)),
+            count => Err(SchemaError::WrongCount {
+                field_id: self.field_id,
+                count,
+            }),
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldDeserializer<'_, '_> {
+    type Error = SchemaError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SchemaError> {
+        Err(SchemaError::Unsupported(
+            "a value whose type is not known in advance",
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_bool(self.read(
+            ObjectRef::get_bool_count,
+            ObjectRef::get_bool,
+            ObjectRef::index_bool,
+        )?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_i32(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_i32(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_i32(match self.hint {
+            Some(Hint::Sint32) => self.read(
+                ObjectRef::get_sint32_count,
+                ObjectRef::get_sint32,
+                ObjectRef::index_sint32,
+            )?,
+            Some(Hint::Sfixed32) => self.read(
+                ObjectRef::get_sfixed32_count,
+                ObjectRef::get_sfixed32,
+                ObjectRef::index_sfixed32,
+            )?,
+            _ => self.read(
+                ObjectRef::get_int32_count,
+                ObjectRef::get_int32,
+                ObjectRef::index_int32,
+            )?,
+        })
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_i64(match self.hint {
+            Some(Hint::Sint64) => self.read(
+                ObjectRef::get_sint64_count,
+                ObjectRef::get_sint64,
+                ObjectRef::index_sint64,
+            )?,
+            Some(Hint::Sfixed64) => self.read(
+                ObjectRef::get_sfixed64_count,
+                ObjectRef::get_sfixed64,
+                ObjectRef::index_sfixed64,
+            )?,
+            _ => self.read(
+                ObjectRef::get_int64_count,
+                ObjectRef::get_int64,
+                ObjectRef::index_int64,
+            )?,
+        })
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_u32(match self.hint {
+            Some(Hint::Fixed32) => self.read(
+                ObjectRef::get_fixed32_count,
+                ObjectRef::get_fixed32,
+                ObjectRef::index_fixed32,
+            )?,
+            _ => self.read(
+                ObjectRef::get_uint32_count,
+                ObjectRef::get_uint32,
+                ObjectRef::index_uint32,
+            )?,
+        })
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_u64(match self.hint {
+            Some(Hint::Fixed64) => self.read(
+                ObjectRef::get_fixed64_count,
+                ObjectRef::get_fixed64,
+                ObjectRef::index_fixed64,
+            )?,
+            _ => self.read(
+                ObjectRef::get_uint64_count,
+                ObjectRef::get_uint64,
+                ObjectRef::index_uint64,
+            )?,
+        })
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_f32(self.read(
+            ObjectRef::get_float_count,
+            ObjectRef::get_float,
+            ObjectRef::index_float,
+        )?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_f64(self.read(
+            ObjectRef::get_double_count,
+            ObjectRef::get_double,
+            ObjectRef::index_double,
+        )?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_string(self.read_string()?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_string(self.read_string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_string(self.read_string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_byte_buf(self.read(
+            ObjectRef::get_bytes_count,
+            ObjectRef::get_bytes,
+            ObjectRef::index_bytes,
+        )?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        if self.index.is_none() && value_count(self.object, self.field_id) == 0 {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        let hint = Hint::from_name(name).or(self.hint);
+        visitor.visit_newtype_struct(FieldDeserializer { hint, ..self })
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        if self.index.is_some() {
+            return Err(SchemaError::Unsupported("a list of lists"));
+        }
+        visitor.visit_seq(ListElements {
+            object: self.object,
+            field_id: self.field_id,
+            hint: self.hint,
+            index: 0,
+            len: value_count(self.object, self.field_id),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_seq(TupleFields {
+            object: self.read_object()?,
+            position: 0,
+            len: len as FieldId,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        if self.index.is_some() {
+            return Err(SchemaError::Unsupported("a list of maps"));
+        }
+        visitor.visit_map(MapEntries {
+            object: self.object,
+            field_id: self.field_id,
+            index: 0,
+            len: self.object.get_object_count(self.field_id),
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        visitor.visit_map(StructFields::new(self.read_object()?, fields)?)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SchemaError> {
+        let variant_index = self.read(
+            ObjectRef::get_uint32_count,
+            ObjectRef::get_uint32,
+            ObjectRef::index_uint32,
+        )?;
+        visitor.visit_enum(IntoDeserializer::<SchemaError>::into_deserializer(
+            variant_index,
+        ))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SchemaError> {
+        visitor.visit_unit()
+    }
+}
+
+/// Visits the fields of a struct, in declaration order.
+struct StructFields<'o> {
+    object: ObjectRef<'o>,
+    fields: &'static [&'static str],
+    position: usize,
+}
+
+impl<'o> StructFields<'o> {
+    fn new(object: ObjectRef<'o>, fields: &'static [&'static str]) -> Result<Self, SchemaError> {
+        let mut field_ids = Vec::with_capacity(fields.len());
+        for (index, name) in fields.iter().enumerate() {
+            claim_field_id(&mut field_ids, field_id(name, index as FieldId + 1))?;
+        }
+        Ok(Self {
+            object,
+            fields,
+            position: 0,
+        })
+    }
+}
+
+impl<'de> de::MapAccess<'de> for StructFields<'_> {
+    type Error = SchemaError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SchemaError> {
+        match self.fields.get(self.position) {
+            Some(name) => {
+                self.position += 1;
+                seed.deserialize(IntoDeserializer::<SchemaError>::into_deserializer(*name))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SchemaError> {
+        let position = self.position as FieldId;
+        let field_id = field_id(self.fields[self.position - 1], position);
+        seed.deserialize(FieldDeserializer::new(&self.object, field_id))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len() - self.position)
+    }
+}
+
+/// Visits the elements of a tuple, stored in fields numbered from 1.
+struct TupleFields<'o> {
+    object: ObjectRef<'o>,
+    position: FieldId,
+    len: FieldId,
+}
+
+impl<'de> de::SeqAccess<'de> for TupleFields<'_> {
+    type Error = SchemaError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SchemaError> {
+        if self.position == self.len {
+            return Ok(None);
+        }
+        self.position += 1;
+        seed.deserialize(FieldDeserializer::new(&self.object, self.position))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.len - self.position) as usize)
+    }
+}
+
+/// Visits the elements of a list field.
+struct ListElements<'a, 'o> {
+    object: &'a ObjectRef<'o>,
+    field_id: FieldId,
+    hint: Option<Hint>,
+    index: u32,
+    len: u32,
+}
+
+impl<'de> de::SeqAccess<'de> for ListElements<'_, '_> {
+    type Error = SchemaError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SchemaError> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let element = FieldDeserializer {
+            object: self.object,
+            field_id: self.field_id,
+            hint: self.hint,
+            index: Some(self.index),
+        };
+        self.index += 1;
+        seed.deserialize(element).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.len - self.index) as usize)
+    }
+}
+
+/// Visits the entries of a map field.
+struct MapEntries<'a, 'o> {
+    object: &'a ObjectRef<'o>,
+    field_id: FieldId,
+    index: u32,
+    len: u32,
+}
+
+impl<'de> de::MapAccess<'de> for MapEntries<'_, '_> {
+    type Error = SchemaError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SchemaError> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let entry = self.object.index_object(self.field_id, self.index);
+        seed.deserialize(FieldDeserializer::new(&entry, MAP_KEY_FIELD_ID))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SchemaError> {
+        let entry = self.object.index_object(self.field_id, self.index);
+        self.index += 1;
+        seed.deserialize(FieldDeserializer::new(&entry, MAP_VALUE_FIELD_ID))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.len - self.index) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{self, CommandRequest};
+    use ::serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    fn write<T: Serialize>(value: &T) -> Result<CommandRequest, SchemaError> {
+        let mut request = CommandRequest::new();
+        to_object(value, &mut request.get_object_mut())?;
+        Ok(request)
+    }
+
+    fn round_trip<T>(value: &T) -> CommandRequest
+    where
+        T: Serialize + DeserializeOwned + PartialEq + fmt::Debug,
+    {
+        let request = write(value).unwrap();
+        assert_eq!(&from_object::<T>(&request.get_object()).unwrap(), value);
+        request
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Renamed {
+        #[serde(rename = "5")]
+        count: u32,
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Integers {
+        int32: i32,
+        int64: i64,
+        uint32: u32,
+        uint64: u64,
+        sint32: Sint32,
+        sint64: Sint64,
+        fixed32: Fixed32,
+        fixed64: Fixed64,
+        sfixed32: Sfixed32,
+        sfixed64: Sfixed64,
+        #[serde(with = "schema::serde::sint32")]
+        with_sint32: i32,
+        #[serde(with = "schema::serde::sint64")]
+        with_sint64: i64,
+        #[serde(with = "schema::serde::fixed32")]
+        with_fixed32: u32,
+        #[serde(with = "schema::serde::fixed64")]
+        with_fixed64: u64,
+        #[serde(with = "schema::serde::sfixed32")]
+        with_sfixed32: i32,
+        #[serde(with = "schema::serde::sfixed64")]
+        with_sfixed64: i64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inner {
+        x: f64,
+        flag: bool,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Composite {
+        missing: Option<u32>,
+        present: Option<String>,
+        list: Vec<i64>,
+        map: BTreeMap<String, u32>,
+        nested: Inner,
+        nested_list: Vec<Inner>,
+        optional_nested: Option<Inner>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Color {
+        Red,
+        Green,
+        Blue,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Colors {
+        color: Color,
+        colors: Vec<Color>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Single {
+        value: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Text {
+        text: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Circle(f64),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct WithShape {
+        shape: Shape,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Colliding {
+        first: u32,
+        #[serde(rename = "1")]
+        second: u32,
+    }
+
+    #[test]
+    fn renamed_fields_use_their_field_id() {
+        let request = round_trip(&Renamed {
+            count: 3,
+            name: "tree".to_owned(),
+        });
+        let object = request.get_object();
+        assert_eq!(object.get_uint32(5), 3);
+        assert_eq!(object.get_string(2), "tree");
+        assert_eq!(object.get_uint32_count(1), 0);
+    }
+
+    #[test]
+    fn integers_use_their_wire_type() {
+        let request = round_trip(&Integers {
+            int32: -1,
+            int64: i64::MIN,
+            uint32: u32::MAX,
+            uint64: u64::MAX,
+            sint32: Sint32(-2),
+            sint64: Sint64(-3),
+            fixed32: Fixed32(4),
+            fixed64: Fixed64(5),
+            sfixed32: Sfixed32(-6),
+            sfixed64: Sfixed64(-7),
+            with_sint32: -8,
+            with_sint64: -9,
+            with_fixed32: 10,
+            with_fixed64: 11,
+            with_sfixed32: -12,
+            with_sfixed64: -13,
+        });
+        let object = request.get_object();
+        assert_eq!(object.get_int32(1), -1);
+        assert_eq!(object.get_int64(2), i64::MIN);
+        assert_eq!(object.get_uint32(3), u32::MAX);
+        assert_eq!(object.get_uint64(4), u64::MAX);
+        assert_eq!(object.get_sint32(5), -2);
+        assert_eq!(object.get_sint64(6), -3);
+        assert_eq!(object.get_fixed32(7), 4);
+        assert_eq!(object.get_fixed64(8), 5);
+        assert_eq!(object.get_sfixed32(9), -6);
+        assert_eq!(object.get_sfixed64(10), -7);
+        assert_eq!(object.get_sint32(11), -8);
+        assert_eq!(object.get_sint64(12), -9);
+        assert_eq!(object.get_fixed32(13), 10);
+        assert_eq!(object.get_fixed64(14), 11);
+        assert_eq!(object.get_sfixed32(15), -12);
+        assert_eq!(object.get_sfixed64(16), -13);
+    }
+
+    #[test]
+    fn options_lists_maps_and_nested_structs_round_trip() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let request = round_trip(&Composite {
+            missing: None,
+            present: Some("here".to_owned()),
+            list: vec![-1, 0, 1],
+            map,
+            nested: Inner { x: 1.5, flag: true },
+            nested_list: vec![
+                Inner {
+                    x: 2.0,
+                    flag: false,
+                },
+                Inner { x: 3.0, flag: true },
+            ],
+            optional_nested: Some(Inner { x: 4.0, flag: true }),
+        });
+        let object = request.get_object();
+        assert_eq!(value_count(&object, 1), 0);
+        assert_eq!(object.get_int64_list(3), vec![-1, 0, 1]);
+        assert_eq!(object.get_object_count(4), 2);
+        assert_eq!(object.get_object_count(6), 2);
+
+        round_trip(&Composite {
+            missing: Some(7),
+            present: None,
+            list: Vec::new(),
+            map: BTreeMap::new(),
+            nested: Inner {
+                x: 0.0,
+                flag: false,
+            },
+            nested_list: Vec::new(),
+            optional_nested: None,
+        });
+    }
+
+    #[test]
+    fn unit_enums_are_written_as_their_index() {
+        let request = round_trip(&Colors {
+            color: Color::Blue,
+            colors: vec![Color::Green, Color::Red],
+        });
+        let object = request.get_object();
+        assert_eq!(object.get_uint32(1), 2);
+        assert_eq!(object.get_uint32_list(2), vec![1, 0]);
+    }
+
+    #[test]
+    fn malformed_objects_are_reported() {
+        let empty = CommandRequest::new();
+        assert_eq!(
+            from_object::<Single>(&empty.get_object()),
+            Err(SchemaError::MissingField(1))
+        );
+
+        let mut request = CommandRequest::new();
+        request.get_object_mut().add_uint32_list(1, &[1, 2]);
+        assert_eq!(
+            from_object::<Single>(&request.get_object()),
+            Err(SchemaError::WrongCount {
+                field_id: 1,
+                count: 2
+            })
+        );
+
+        let mut request = CommandRequest::new();
+        request.get_object_mut().add_bytes(1, &[0xff]);
+        assert!(matches!(
+            from_object::<Text>(&request.get_object()),
+            Err(SchemaError::InvalidUtf8 { field_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn unsupported_values_are_rejected() {
+        assert_eq!(
+            write(&WithShape {
+                shape: Shape::Circle(1.0)
+            })
+            .err(),
+            Some(enum_with_data())
+        );
+        assert_eq!(write(&5u32).err(), Some(not_a_struct()));
+    }
+
+    #[test]
+    fn colliding_field_ids_are_rejected() {
+        assert_eq!(
+            write(&Colliding {
+                first: 1,
+                second: 2
+            })
+            .err(),
+            Some(SchemaError::DuplicateField(1))
+        );
+        let mut request = CommandRequest::new();
+        request.get_object_mut().add_uint32(1, 1);
+        assert_eq!(
+            from_object::<Colliding>(&request.get_object()),
+            Err(SchemaError::DuplicateField(1))
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! Schema values whose type is only known at runtime, read from objects through a
+//! SchemaDescriptor and written back without one.
+
+use crate::schema::descriptor::{FieldType, PrimitiveType, SchemaDescriptor, TypeReference};
+use crate::schema::{
+    EntityId, FieldId, ObjectMut, ObjectRef, SchemaError, MAP_KEY_FIELD_ID, MAP_VALUE_FIELD_ID,
+};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An integer, along with the schema type it is written as.
+pub enum IntValue {
+    Int32(i32),
+    Int64(i64),
+    Uint32(u32),
+    Uint64(u64),
+    Sint32(i32),
+    Sint64(i64),
+    Fixed32(u32),
+    Fixed64(u64),
+    Sfixed32(i32),
+    Sfixed64(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A floating point number, along with the schema type it is written as.
+pub enum FloatValue {
+    Float(f32),
+    Double(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A dynamically typed schema value. Objects hold their fields by field ID, and maps hold their
+/// entries in the order they were read.
+pub enum Value {
+    Bool(bool),
+    Int(IntValue),
+    Float(FloatValue),
+    String(String),
+    Bytes(Vec<u8>),
+    EntityId(EntityId),
+    Enum(u32),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Option(Option<Box<Value>>),
+    Object(BTreeMap<FieldId, Value>),
+}
+
+impl Value {
+    /// Writes the fields of an object value into the given object.
+    pub fn write_to(&self, object: &mut ObjectMut) -> Result<(), SchemaError> {
+        match self {
+            Value::Object(fields) => write_fields(fields, object),
+            _ => Err(SchemaError::Unsupported(
+                "a value other than an object at the top level of an object",
+            )),
+        }
+    }
+
+    fn write_field(&self, object: &mut ObjectMut, field_id: FieldId) -> Result<(), SchemaError> {
+        match self {
+            Value::Bool(value) => object.add_bool(field_id, value),
+            Value::Int(IntValue::Int32(value)) => object.add_int32(field_id, value),
+            Value::Int(IntValue::Int64(value)) => object.add_int64(field_id, value),
+            Value::Int(IntValue::Uint32(value)) => object.add_uint32(field_id, value),
+            Value::Int(IntValue::Uint64(value)) => object.add_uint64(field_id, value),
+            Value::Int(IntValue::Sint32(value)) => object.add_sint32(field_id, value),
+            Value::Int(IntValue::Sint64(value)) => object.add_sint64(field_id, value),
+            Value::Int(IntValue::Fixed32(value)) => object.add_fixed32(field_id, value),
+            Value::Int(IntValue::Fixed64(value)) => object.add_fixed64(field_id, value),
+            Value::Int(IntValue::Sfixed32(value)) => object.add_sfixed32(field_id, value),
+            Value::Int(IntValue::Sfixed64(value)) => object.add_sfixed64(field_id, value),
+            Value::Float(FloatValue::Float(value)) => object.add_float(field_id, value),
+            Value::Float(FloatValue::Double(value)) => object.add_double(field_id, value),
+            Value::String(value) => object.add_string(field_id, value),
+            Value::Bytes(value) => object.add_bytes(field_id, value),
+            Value::EntityId(value) => object.add_entity_id(field_id, value),
+            Value::Enum(value) => object.add_uint32(field_id, value),
+            Value::List(values) => {
+                for value in values {
+                    if let Value::List(_) | Value::Map(_) | Value::Option(_) = value {
+                        return Err(SchemaError::Unsupported("a list of lists, maps or options"));
+                    }
+                    value.write_field(object, field_id)?;
+                }
+            }
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    let mut entry = object.add_object(field_id);
+                    key.write_field(&mut entry, MAP_KEY_FIELD_ID)?;
+                    value.write_field(&mut entry, MAP_VALUE_FIELD_ID)?;
+                }
+            }
+            Value::Option(value) => {
+                if let Some(value) = value {
+                    value.write_field(object, field_id)?;
+                }
+            }
+            Value::Object(fields) => write_fields(fields, &mut object.add_object(field_id))?,
+        }
+        Ok(())
+    }
+}
+
+fn write_fields(
+    fields: &BTreeMap<FieldId, Value>,
+    object: &mut ObjectMut,
+) -> Result<(), SchemaError> {
+    for (field_id, value) in fields {
+        value.write_field(object, *field_id)?;
+    }
+    Ok(())
+}
+
+impl ObjectRef<'_> {
+    /// Reads the object as the given type of the descriptor, into an object value. Singular
+    /// fields which are not set, as in component updates, are left out of the value.
+    pub fn to_value(
+        &self,
+        descriptor: &SchemaDescriptor,
+        type_name: &str,
+    ) -> Result<Value, SchemaError> {
+        let type_descriptor = descriptor
+            .get_type(type_name)
+            .ok_or_else(|| SchemaError::UnknownType(type_name.to_owned()))?;
+        let mut fields = BTreeMap::new();
+        for field in &type_descriptor.fields {
+            if let Some(value) = read_field(self, descriptor, field.field_id, &field.field_type)? {
+                fields.insert(field.field_id, value);
+            }
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+fn read_field(
+    object: &ObjectRef,
+    descriptor: &SchemaDescriptor,
+    field_id: FieldId,
+    field_type: &FieldType,
+) -> Result<Option<Value>, SchemaError> {
+    let value = match field_type {
+        FieldType::Singular(value_type) => match count(object, value_type, field_id) {
+            0 => return Ok(None),
+            count => read_value(object, descriptor, value_type, field_id, count - 1)?,
+        },
+        FieldType::Option(value_type) => match count(object, value_type, field_id) {
+            0 => Value::Option(None),
+            count => Value::Option(Some(Box::new(read_value(
+                object,
+                descriptor,
+                value_type,
+                field_id,
+                count - 1,
+            )?))),
+        },
+        FieldType::List(value_type) => Value::List(
+            (0..count(object, value_type, field_id))
+                .map(|index| read_value(object, descriptor, value_type, field_id, index))
+                .collect::<Result<_, _>>()?,
+        ),
+        FieldType::Map(key_type, value_type) => Value::Map(
+            (0..object.get_object_count(field_id))
+                .map(|index| {
+                    let entry = object.index_object(field_id, index);
+                    Ok((
+                        read_entry(&entry, descriptor, key_type, MAP_KEY_FIELD_ID)?,
+                        read_entry(&entry, descriptor, value_type, MAP_VALUE_FIELD_ID)?,
+                    ))
+                })
+                .collect::<Result<_, SchemaError>>()?,
+        ),
+    };
+    Ok(Some(value))
+}
+
+fn read_entry(
+    entry: &ObjectRef,
+    descriptor: &SchemaDescriptor,
+    value_type: &TypeReference,
+    field_id: FieldId,
+) -> Result<Value, SchemaError> {
+    match count(entry, value_type, field_id) {
+        0 => Err(SchemaError::MissingField(field_id)),
+        count => read_value(entry, descriptor, value_type, field_id, count - 1),
+    }
+}
+
+/// Returns the number of values of the given type held by a field.
+fn count(object: &ObjectRef, value_type: &TypeReference, field_id: FieldId) -> u32 {
+    match value_type {
+        TypeReference::Primitive(primitive) => match primitive {
+            PrimitiveType::Bool => object.get_bool_count(field_id),
+            PrimitiveType::Float => object.get_float_count(field_id),
+            PrimitiveType::Double => object.get_double_count(field_id),
+            PrimitiveType::Int32 => object.get_int32_count(field_id),
+            PrimitiveType::Int64 => object.get_int64_count(field_id),
+            PrimitiveType::Uint32 => object.get_uint32_count(field_id),
+            PrimitiveType::Uint64 => object.get_uint64_count(field_id),
+            PrimitiveType::Sint32 => object.get_sint32_count(field_id),
+            PrimitiveType::Sint64 => object.get_sint64_count(field_id),
+            PrimitiveType::Fixed32 => object.get_fixed32_count(field_id),
+            PrimitiveType::Fixed64 => object.get_fixed64_count(field_id),
+            PrimitiveType::Sfixed32 => object.get_sfixed32_count(field_id),
+            PrimitiveType::Sfixed64 => object.get_sfixed64_count(field_id),
+            PrimitiveType::String | PrimitiveType::Bytes => object.get_bytes_count(field_id),
+            PrimitiveType::EntityId => object.get_entity_id_count(field_id),
+            PrimitiveType::Entity => object.get_object_count(field_id),
+        },
+        TypeReference::Enum(_) => object.get_enum_count(field_id),
+        TypeReference::Type(_) => object.get_object_count(field_id),
+    }
+}
+
+/// Reads the value at the given index of a field. Singular fields are read at their last index,
+/// which holds the value returned by the getters of ObjectRef.
+fn read_value(
+    object: &ObjectRef,
+    descriptor: &SchemaDescriptor,
+    value_type: &TypeReference,
+    field_id: FieldId,
+    index: u32,
+) -> Result<Value, SchemaError> {
+    let primitive = match value_type {
+        TypeReference::Primitive(primitive) => primitive,
+        TypeReference::Enum(_) => return Ok(Value::Enum(object.index_enum(field_id, index))),
+        TypeReference::Type(name) => {
+            return object
+                .index_object(field_id, index)
+                .to_value(descriptor, name)
+        }
+    };
+    Ok(match primitive {
+        PrimitiveType::Bool => Value::Bool(object.index_bool(field_id, index)),
+        PrimitiveType::Float => {
+            Value::Float(FloatValue::Float(object.index_float(field_id, index)))
+        }
+        PrimitiveType::Double => {
+            Value::Float(FloatValue::Double(object.index_double(field_id, index)))
+        }
+        PrimitiveType::Int32 => Value::Int(IntValue::Int32(object.index_int32(field_id, index))),
+        PrimitiveType::Int64 => Value::Int(IntValue::Int64(object.index_int64(field_id, index))),
+        PrimitiveType::Uint32 => Value::Int(IntValue::Uint32(object.index_uint32(field_id, index))),
+        PrimitiveType::Uint64 => Value::Int(IntValue::Uint64(object.index_uint64(field_id, index))),
+        PrimitiveType::Sint32 => Value::Int(IntValue::Sint32(object.index_sint32(field_id, index))),
+        PrimitiveType::Sint64 => Value::Int(IntValue::Sint64(object.index_sint64(field_id, index))),
+        PrimitiveType::Fixed32 => {
+            Value::Int(IntValue::Fixed32(object.index_fixed32(field_id, index)))
+        }
+        PrimitiveType::Fixed64 => {
+            Value::Int(IntValue::Fixed64(object.index_fixed64(field_id, index)))
+        }
+        PrimitiveType::Sfixed32 => {
+            Value::Int(IntValue::Sfixed32(object.index_sfixed32(field_id, index)))
+        }
+        PrimitiveType::Sfixed64 => {
+            Value::Int(IntValue::Sfixed64(object.index_sfixed64(field_id, index)))
+        }
+        PrimitiveType::String => Value::String(
+            String::from_utf8(object.index_bytes(field_id, index)).map_err(|error| {
+                SchemaError::InvalidUtf8 {
+                    field_id,
+                    error: error.utf8_error(),
+                }
+            })?,
+        ),
+        PrimitiveType::Bytes => Value::Bytes(object.index_bytes(field_id, index)),
+        PrimitiveType::EntityId => Value::EntityId(object.index_entity_id(field_id, index)),
+        PrimitiveType::Entity => return Err(SchemaError::Unsupported("an entity field")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::descriptor::{EnumDescriptor, FieldDescriptor, TypeDescriptor};
+    use crate::schema::CommandRequest;
+
+    const PRIMITIVES: [PrimitiveType; 16] = [
+        PrimitiveType::Bool,
+        PrimitiveType::Float,
+        PrimitiveType::Double,
+        PrimitiveType::Int32,
+        PrimitiveType::Int64,
+        PrimitiveType::Uint32,
+        PrimitiveType::Uint64,
+        PrimitiveType::Sint32,
+        PrimitiveType::Sint64,
+        PrimitiveType::Fixed32,
+        PrimitiveType::Fixed64,
+        PrimitiveType::Sfixed32,
+        PrimitiveType::Sfixed64,
+        PrimitiveType::String,
+        PrimitiveType::Bytes,
+        PrimitiveType::EntityId,
+    ];
+
+    fn field(field_id: FieldId, field_type: FieldType) -> FieldDescriptor {
+        FieldDescriptor {
+            name: format!("field_{}", field_id),
+            field_id,
+            field_type,
+        }
+    }
+
+    /// Describes a type holding one singular field of each primitive type, numbered from 1 in
+    /// the order of PRIMITIVES, followed by an enum, an option, a list, a map and an object.
+    fn descriptor() -> SchemaDescriptor {
+        let mut descriptor = SchemaDescriptor::new();
+        descriptor.add_enum(EnumDescriptor {
+            name: "test.Color".into(),
+            values: vec![("RED".into(), 0), ("BLUE".into(), 2)],
+        });
+        descriptor.add_type(TypeDescriptor {
+            name: "test.Inner".into(),
+            fields: vec![field(
+                1,
+                FieldType::Singular(TypeReference::Primitive(PrimitiveType::Uint32)),
+            )],
+        });
+        let mut fields: Vec<_> = PRIMITIVES
+            .iter()
+            .zip(1..)
+            .map(|(primitive, field_id)| {
+                field(
+                    field_id,
+                    FieldType::Singular(TypeReference::Primitive(*primitive)),
+                )
+            })
+            .collect();
+        fields.push(field(
+            20,
+            FieldType::Singular(TypeReference::Enum("test.Color".into())),
+        ));
+        fields.push(field(
+            21,
+            FieldType::Option(TypeReference::Primitive(PrimitiveType::Int32)),
+        ));
+        fields.push(field(
+            22,
+            FieldType::List(TypeReference::Primitive(PrimitiveType::String)),
+        ));
+        fields.push(field(
+            23,
+            FieldType::Map(
+                TypeReference::Primitive(PrimitiveType::Uint32),
+                TypeReference::Type("test.Inner".into()),
+            ),
+        ));
+        fields.push(field(
+            24,
+            FieldType::Singular(TypeReference::Type("test.Inner".into())),
+        ));
+        descriptor.add_type(TypeDescriptor {
+            name: "test.Everything".into(),
+            fields,
+        });
+        descriptor
+    }
+
+    fn inner(value: u32) -> Value {
+        Value::Object(
+            vec![(1, Value::Int(IntValue::Uint32(value)))]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn every_primitive_type_round_trips() {
+        let descriptor = descriptor();
+        let mut request = CommandRequest::new();
+        {
+            let mut object = request.get_object_mut();
+            object.add_bool(1, true);
+            object.add_float(2, 1.5);
+            object.add_double(3, -2.25);
+            object.add_int32(4, -3);
+            object.add_int64(5, -4_000_000_000);
+            object.add_uint32(6, 5);
+            object.add_uint64(7, 6_000_000_000);
+            object.add_sint32(8, -7);
+            object.add_sint64(9, -8_000_000_000);
+            object.add_fixed32(10, 9);
+            object.add_fixed64(11, 10_000_000_000);
+            object.add_sfixed32(12, -11);
+            object.add_sfixed64(13, -12_000_000_000);
+            object.add_string(14, "thirteen");
+            object.add_bytes(15, &[14, 15]);
+            object.add_entity_id(16, 16);
+            object.add_uint32(20, 2);
+            object.add_int32(21, 21);
+            object.add_string(22, "a");
+            object.add_string(22, "b");
+            let mut entry = object.add_object(23);
+            entry.add_uint32(MAP_KEY_FIELD_ID, 1);
+            entry.add_object(MAP_VALUE_FIELD_ID).add_uint32(1, 2);
+            object.add_object(24).add_uint32(1, 24);
+        }
+
+        let value = request
+            .get_object()
+            .to_value(&descriptor, "test.Everything")
+            .unwrap();
+        let expected = Value::Object(
+            vec![
+                (1, Value::Bool(true)),
+                (2, Value::Float(FloatValue::Float(1.5))),
+                (3, Value::Float(FloatValue::Double(-2.25))),
+                (4, Value::Int(IntValue::Int32(-3))),
+                (5, Value::Int(IntValue::Int64(-4_000_000_000))),
+                (6, Value::Int(IntValue::Uint32(5))),
+                (7, Value::Int(IntValue::Uint64(6_000_000_000))),
+                (8, Value::Int(IntValue::Sint32(-7))),
+                (9, Value::Int(IntValue::Sint64(-8_000_000_000))),
+                (10, Value::Int(IntValue::Fixed32(9))),
+                (11, Value::Int(IntValue::Fixed64(10_000_000_000))),
+                (12, Value::Int(IntValue::Sfixed32(-11))),
+                (13, Value::Int(IntValue::Sfixed64(-12_000_000_000))),
+                (14, Value::String("thirteen".into())),
+                (15, Value::Bytes(vec![14, 15])),
+                (16, Value::EntityId(16)),
+                (20, Value::Enum(2)),
+                (
+                    21,
+                    Value::Option(Some(Box::new(Value::Int(IntValue::Int32(21))))),
+                ),
+                (
+                    22,
+                    Value::List(vec![Value::String("a".into()), Value::String("b".into())]),
+                ),
+                (
+                    23,
+                    Value::Map(vec![(Value::Int(IntValue::Uint32(1)), inner(2))]),
+                ),
+                (24, inner(24)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(value, expected);
+
+        let mut written = CommandRequest::new();
+        value.write_to(&mut written.get_object_mut()).unwrap();
+        assert_eq!(
+            written
+                .get_object()
+                .to_value(&descriptor, "test.Everything")
+                .unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn unset_fields_are_left_out_and_read_as_empty() {
+        let request = CommandRequest::new();
+        let value = request
+            .get_object()
+            .to_value(&descriptor(), "test.Everything")
+            .unwrap();
+        let expected = Value::Object(
+            vec![
+                (21, Value::Option(None)),
+                (22, Value::List(Vec::new())),
+                (23, Value::Map(Vec::new())),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn unknown_types_are_rejected() {
+        let request = CommandRequest::new();
+        assert!(matches!(
+            request.get_object().to_value(&descriptor(), "test.Missing"),
+            Err(SchemaError::UnknownType(name)) if name == "test.Missing"
+        ));
+    }
+
+    #[test]
+    fn nested_lists_cannot_be_written() {
+        let value = Value::Object(
+            vec![(
+                1,
+                Value::List(vec![Value::List(vec![Value::Int(IntValue::Uint32(1))])]),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let mut request = CommandRequest::new();
+        assert!(matches!(
+            value.write_to(&mut request.get_object_mut()),
+            Err(SchemaError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn only_objects_can_be_written_at_the_top_level() {
+        let mut request = CommandRequest::new();
+        assert!(matches!(
+            Value::Bool(true).write_to(&mut request.get_object_mut()),
+            Err(SchemaError::Unsupported(_))
+        ));
+    }
+}
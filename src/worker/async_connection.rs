@@ -0,0 +1,511 @@
+use crate::worker::connection::{
+    CommandParameters, Connection, ConnectionSender, OpReceiver, WorkerConnection,
+};
+use crate::worker::op::{
+    CommandResponseOp, CreateEntityResponseOp, EntityQueryResponseOp, OpList, WorkerOp,
+};
+use crate::worker::{CommandRequest, ComponentData, EntityId, EntityQuery, RequestId};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How long the op thread blocks waiting for ops before checking whether it should stop.
+const OP_LIST_TIMEOUT_MILLIS: u32 = 100;
+
+/// The receiving half of a connection, whose op lists are retrieved on the op thread of an
+/// AsyncConnection.
+pub trait OpSource: Send + 'static {
+    fn get_op_list(&mut self, timeout_millis: u32) -> OpList;
+}
+
+/// The sending half of a connection, through which an AsyncConnection sends requests from any
+/// thread.
+pub trait RequestSender: Send + Sync + 'static {
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId;
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+}
+
+impl OpSource for OpReceiver {
+    fn get_op_list(&mut self, timeout_millis: u32) -> OpList {
+        OpReceiver::get_op_list(self, timeout_millis)
+    }
+}
+
+impl RequestSender for ConnectionSender {
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        ConnectionSender::send_command_request(self, entity_id, request, timeout_millis)
+    }
+
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId {
+        ConnectionSender::send_command_request_with_parameters(
+            self,
+            entity_id,
+            request,
+            timeout_millis,
+            parameters,
+        )
+    }
+
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        ConnectionSender::send_entity_query_request(self, entity_query, timeout_millis)
+    }
+
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        ConnectionSender::send_create_entity_request(self, components, entity_id, timeout_millis)
+    }
+}
+
+/// A connection shared by both halves. The lock is not held while waiting for ops, which are
+/// polled instead, so this suits connections whose get_op_list does not block, such as
+/// MockConnection.
+impl<C: WorkerConnection + Send + 'static> OpSource for Arc<Mutex<C>> {
+    fn get_op_list(&mut self, timeout_millis: u32) -> OpList {
+        let op_list = self.lock().unwrap().get_op_list(0);
+        if op_list.is_empty() {
+            std::thread::sleep(Duration::from_millis(u64::from(timeout_millis)));
+        }
+        op_list
+    }
+}
+
+impl<C: WorkerConnection + Send + 'static> RequestSender for Arc<Mutex<C>> {
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.lock()
+            .unwrap()
+            .send_command_request(entity_id, request, timeout_millis)
+    }
+
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId {
+        self.lock().unwrap().send_command_request_with_parameters(
+            entity_id,
+            request,
+            timeout_millis,
+            parameters,
+        )
+    }
+
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.lock()
+            .unwrap()
+            .send_entity_query_request(entity_query, timeout_millis)
+    }
+
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.lock()
+            .unwrap()
+            .send_create_entity_request(components, entity_id, timeout_millis)
+    }
+}
+
+/// The kind of response awaited by a request. Pending requests are keyed by their ID and the kind
+/// of their response, so a response is only routed to a request of the matching kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ResponseKind {
+    Command,
+    EntityQuery,
+    CreateEntity,
+}
+
+type PendingRequests = HashMap<(RequestId, ResponseKind), oneshot::Sender<WorkerOp<'static>>>;
+
+struct Shared<S> {
+    sender: S,
+    pending: Mutex<PendingRequests>,
+}
+
+/// A connection driven by a dedicated thread, which retrieves op lists in the background. Responses
+/// to requests sent through this type are routed to the futures returned when sending them, while
+/// every other op is delivered through the associated OpStream.
+pub struct AsyncConnection<S: RequestSender = ConnectionSender> {
+    shared: Arc<Shared<S>>,
+}
+
+impl AsyncConnection {
+    /// Starts processing the ops of the given connection on a dedicated thread. The thread stops
+    /// once the connection is lost, or once both halves have been dropped.
+    pub fn new(connection: Connection) -> (Self, OpStream) {
+        let (connection_sender, op_receiver) = connection.split();
+        Self::from_parts(connection_sender, op_receiver)
+    }
+}
+
+impl<S: RequestSender> AsyncConnection<S> {
+    /// Starts processing the ops of the receiving half on a dedicated thread, while requests are
+    /// sent through the sending half. The thread stops once the connection is lost, or once both
+    /// halves of the async connection have been dropped.
+    pub fn from_parts<R: OpSource>(sender: S, op_source: R) -> (Self, OpStream) {
+        let shared = Arc::new(Shared {
+            sender,
+            pending: Mutex::new(HashMap::new()),
+        });
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || process_ops(thread_shared, op_source, sender));
+        let stream = OpStream {
+            receiver,
+            ops: Vec::new().into_iter(),
+        };
+        (Self { shared }, stream)
+    }
+
+    /// Returns the sending half of the underlying connection, to send messages which do not expect
+    /// a response.
+    pub fn sender(&self) -> &S {
+        &self.shared.sender
+    }
+
+    /// Sends a command request and waits for its response. Returns None if the request was invalid
+    /// or if the connection was lost before a response was received.
+    pub fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> impl Future<Output = Option<CommandResponseOp<'static>>> {
+        let receiver = self.register(ResponseKind::Command, |sender| {
+            sender.send_command_request(entity_id, request, timeout_millis)
+        });
+        command_response(receiver)
+    }
+
+    /// Sends a command request with the given parameters and waits for its response, as
+    /// send_command_request does.
+    pub fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> impl Future<Output = Option<CommandResponseOp<'static>>> {
+        let receiver = self.register(ResponseKind::Command, |sender| {
+            sender.send_command_request_with_parameters(
+                entity_id,
+                request,
+                timeout_millis,
+                parameters,
+            )
+        });
+        command_response(receiver)
+    }
+
+    /// Sends an entity query and waits for its response. Returns None if the query was invalid or
+    /// if the connection was lost before a response was received.
+    pub fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> impl Future<Output = Option<EntityQueryResponseOp<'static>>> {
+        let receiver = self.register(ResponseKind::EntityQuery, |sender| {
+            sender.send_entity_query_request(entity_query, timeout_millis)
+        });
+        async move {
+            match receiver.await.ok()? {
+                WorkerOp::EntityQueryResponse(op) => Some(op),
+                _ => None,
+            }
+        }
+    }
+
+    /// Sends a create entity request and waits for its response. Returns None if the request was
+    /// invalid or if the connection was lost before a response was received.
+    pub fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> impl Future<Output = Option<CreateEntityResponseOp>> {
+        let receiver = self.register(ResponseKind::CreateEntity, |sender| {
+            sender.send_create_entity_request(components, entity_id, timeout_millis)
+        });
+        async move {
+            match receiver.await.ok()? {
+                WorkerOp::CreateEntityResponse(op) => Some(op),
+                _ => None,
+            }
+        }
+    }
+
+    fn register<F>(&self, kind: ResponseKind, send: F) -> oneshot::Receiver<WorkerOp<'static>>
+    where
+        F: FnOnce(&S) -> RequestId,
+    {
+        let (sender, receiver) = oneshot::channel();
+        // The pending map is locked before sending so that the op thread cannot route the response
+        // before the request is registered.
+        let mut pending = self.shared.pending.lock().unwrap();
+        let request_id = send(&self.shared.sender);
+        if request_id >= 0 {
+            pending.insert((request_id, kind), sender);
+        }
+        receiver
+    }
+}
+
+async fn command_response(
+    receiver: oneshot::Receiver<WorkerOp<'static>>,
+) -> Option<CommandResponseOp<'static>> {
+    match receiver.await.ok()? {
+        WorkerOp::CommandResponse(op) => Some(op),
+        _ => None,
+    }
+}
+
+/// A stream of the ops received on an AsyncConnection, excluding the responses to requests sent
+/// through it.
+pub struct OpStream {
+    receiver: mpsc::UnboundedReceiver<Vec<WorkerOp<'static>>>,
+    ops: std::vec::IntoIter<WorkerOp<'static>>,
+}
+
+impl Stream for OpStream {
+    type Item = WorkerOp<'static>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<WorkerOp<'static>>> {
+        loop {
+            if let Some(op) = self.ops.next() {
+                return Poll::Ready(Some(op));
+            }
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(ops)) => self.ops = ops.into_iter(),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn response_key(op: &WorkerOp) -> Option<(RequestId, ResponseKind)> {
+    match op {
+        WorkerOp::CommandResponse(op) => Some((op.request_id, ResponseKind::Command)),
+        WorkerOp::EntityQueryResponse(op) => Some((op.request_id, ResponseKind::EntityQuery)),
+        WorkerOp::CreateEntityResponse(op) => Some((op.request_id, ResponseKind::CreateEntity)),
+        _ => None,
+    }
+}
+
+fn process_ops<S, R: OpSource>(
+    shared: Arc<Shared<S>>,
+    mut op_source: R,
+    sender: mpsc::UnboundedSender<Vec<WorkerOp<'static>>>,
+) {
+    loop {
+        // The op source is only used by this thread, so senders are never blocked while it waits
+        // for ops.
+        let mut op_list = op_source.get_op_list(OP_LIST_TIMEOUT_MILLIS);
+        let mut disconnected = false;
+        let mut forwarded = Vec::new();
+        {
+            let mut pending = shared.pending.lock().unwrap();
+            for op in op_list.drain() {
+                // The op list is destroyed on this thread, so the data of the ops handed over to
+                // other threads is acquired.
+                let op = op.into_owned();
+                if let WorkerOp::Disconnect(_) = op {
+                    disconnected = true;
+                }
+                match response_key(&op).and_then(|key| pending.remove(&key)) {
+                    Some(response_sender) => {
+                        let _ = response_sender.send(op);
+                    }
+                    None => forwarded.push(op),
+                }
+            }
+            if disconnected {
+                pending.clear();
+            }
+        }
+        if !forwarded.is_empty() {
+            let _ = sender.send(forwarded);
+        }
+        if disconnected || (sender.is_closed() && Arc::strong_count(&shared) == 1) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+    use crate::worker::mock::MockConnection;
+    use crate::worker::{ConnectionStatusCode, StatusCode};
+    use std::task::{Wake, Waker};
+    use std::thread::Thread;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Drives a future to completion on the current thread, which is parked until it is woken.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    fn next_op(stream: &mut OpStream) -> Option<WorkerOp<'static>> {
+        block_on(std::future::poll_fn(|context| {
+            Pin::new(&mut *stream).poll_next(context)
+        }))
+    }
+
+    type SharedMock = Arc<Mutex<MockConnection>>;
+
+    fn start() -> (SharedMock, AsyncConnection<SharedMock>, OpStream) {
+        let mock = Arc::new(Mutex::new(MockConnection::new("worker")));
+        let (connection, stream) = AsyncConnection::from_parts(mock.clone(), mock.clone());
+        (mock, connection, stream)
+    }
+
+    fn request() -> CommandRequest {
+        CommandRequest::new(1000, 1, schema::CommandRequest::new())
+    }
+
+    #[test]
+    fn responses_are_routed_by_request_id_and_kind() {
+        let (mock, connection, mut stream) = start();
+        let first = connection.send_command_request(1, request(), None);
+        let second = connection.send_command_request(2, request(), None);
+        let create = connection.send_create_entity_request(Vec::new(), None, None);
+        {
+            let mut mock = mock.lock().unwrap();
+            // A command response with the ID of the create entity request is not routed to it.
+            mock.command_response(3, 3, StatusCode::Success, None);
+            mock.create_entity_response(3, StatusCode::Success, 10);
+            mock.command_response(2, 2, StatusCode::Timeout, None);
+            mock.command_response(1, 1, StatusCode::Success, None);
+        }
+
+        let first = block_on(first).unwrap();
+        assert_eq!((first.request_id, first.entity_id), (1, 1));
+        assert!(matches!(first.status_code, StatusCode::Success));
+        let second = block_on(second).unwrap();
+        assert_eq!((second.request_id, second.entity_id), (2, 2));
+        assert!(matches!(second.status_code, StatusCode::Timeout));
+        let create = block_on(create).unwrap();
+        assert_eq!((create.request_id, create.entity_id), (3, 10));
+
+        match next_op(&mut stream) {
+            Some(WorkerOp::CommandResponse(op)) => assert_eq!(op.request_id, 3),
+            _ => panic!("expected the unmatched command response"),
+        }
+    }
+
+    #[test]
+    fn responses_are_filtered_out_of_the_op_stream() {
+        let (mock, connection, mut stream) = start();
+        let response = connection.send_command_request(1, request(), None);
+        {
+            let mut mock = mock.lock().unwrap();
+            mock.add_entity(5);
+            mock.command_response(1, 1, StatusCode::Success, None);
+            mock.add_entity(6);
+        }
+
+        assert!(block_on(response).is_some());
+        let mut entity_ids = Vec::new();
+        while entity_ids.len() < 2 {
+            match next_op(&mut stream) {
+                Some(WorkerOp::AddEntity(op)) => entity_ids.push(op.entity_id),
+                _ => panic!("expected only the AddEntity ops"),
+            }
+        }
+        assert_eq!(entity_ids, vec![5, 6]);
+    }
+
+    #[test]
+    fn requests_resolve_to_none_on_disconnect() {
+        let (mock, connection, mut stream) = start();
+        let response = connection.send_command_request(1, request(), None);
+        mock.lock()
+            .unwrap()
+            .disconnect(ConnectionStatusCode::NetworkError, "lost");
+
+        assert!(block_on(response).is_none());
+        match next_op(&mut stream) {
+            Some(WorkerOp::Disconnect(op)) => assert_eq!(op.reason, "lost"),
+            _ => panic!("expected the Disconnect op"),
+        }
+        assert!(next_op(&mut stream).is_none());
+    }
+}
@@ -0,0 +1,420 @@
+//! Tracking of the authority of the worker over entity components.
+//!
+//! SpatialOS silently drops the component updates sent by a worker which is not authoritative over
+//! the component. Every WorkerConnection records the AuthorityChange ops it receives, so that
+//! updates can be checked before they are sent: WorkerConnection::write and ConnectionSender::write
+//! return an error instead of a writer when the worker is not authoritative, and updates sent
+//! without authority are counted and logged as warnings through the enabled logging crates.
+//!
+//! Handlers registered with WorkerConnection::on_authority_loss_imminent or
+//! OpReceiver::on_authority_loss_imminent flush the final state of a component when its authority
+//! loss becomes imminent. They are run by get_op_list, after the authority changes of the list
+//! have been recorded and before the list is returned, and the loss is acknowledged once every
+//! handler of the component has run.
+
+use crate::schema::{Component, Update};
+use crate::worker::connection::{ConnectionSender, WorkerConnection};
+use crate::worker::op::{AuthorityChangeOp, OpList, WorkerOp};
+use crate::worker::{Authority, ComponentId, ComponentUpdate, EntityId};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+type AuthorityLossCallback = Box<dyn FnMut(EntityId, &dyn UpdateSender) + Send>;
+
+#[derive(Debug, Default)]
+/// The authority of the worker over each entity component, as reported by AuthorityChange ops.
+pub struct AuthorityTracker {
+    authority: RwLock<HashMap<(EntityId, ComponentId), Authority>>,
+    unauthoritative_updates: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when writing to a component the worker is not authoritative over.
+pub struct AuthorityError {
+    pub entity_id: EntityId,
+    pub component_id: ComponentId,
+}
+
+/// A connection whose authority is tracked, through which a ComponentWriter sends its updates. It
+/// is implemented by ConnectionSender and by every WorkerConnection.
+pub trait UpdateSender {
+    fn authority_tracker(&self) -> &AuthorityTracker;
+
+    fn send_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool;
+
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    );
+}
+
+/// Sends updates to a component the worker was authoritative over when the writer was created.
+pub struct ComponentWriter<'a, C: Component, S: UpdateSender + ?Sized = ConnectionSender> {
+    connection: &'a S,
+    entity_id: EntityId,
+    _component: PhantomData<fn() -> C>,
+}
+
+#[derive(Default)]
+/// The handlers invoked when the authority loss of a component becomes imminent.
+pub struct AuthorityLossHandlers {
+    callbacks: HashMap<ComponentId, Vec<AuthorityLossCallback>>,
+}
+
+impl AuthorityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the authority changes of the list, and forgets the components and entities it
+    /// removes.
+    pub fn process_ops(&self, op_list: &OpList) {
+        let mut authority = self.authority.write().unwrap();
+        for op in op_list {
+            match op {
+                WorkerOp::AuthorityChange(op) => {
+                    authority.insert((op.entity_id, op.component_id), op.authority);
+                }
+                WorkerOp::RemoveComponent(op) => {
+                    authority.remove(&(op.entity_id, op.component_id));
+                }
+                WorkerOp::RemoveEntity(op) => {
+                    authority.retain(|(entity_id, _), _| *entity_id != op.entity_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the authority of the worker over the component, which is NotAuthoritative unless
+    /// an AuthorityChange op stated otherwise.
+    pub fn authority(&self, entity_id: EntityId, component_id: ComponentId) -> Authority {
+        self.authority
+            .read()
+            .unwrap()
+            .get(&(entity_id, component_id))
+            .copied()
+            .unwrap_or(Authority::NotAuthoritative)
+    }
+
+    /// Returns true if the worker can send updates to the component, including while its
+    /// authority loss is imminent.
+    pub fn is_authoritative(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.authority(entity_id, component_id) != Authority::NotAuthoritative
+    }
+
+    /// Returns the number of updates sent for components the worker was not authoritative over.
+    pub fn unauthoritative_updates(&self) -> u64 {
+        self.unauthoritative_updates.load(Ordering::Relaxed)
+    }
+
+    /// Counts and logs an update about to be sent without authority.
+    pub(crate) fn check_update(&self, entity_id: EntityId, component_id: ComponentId) {
+        if self.is_authoritative(entity_id, component_id) {
+            return;
+        }
+        self.unauthoritative_updates.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "log")]
+        log::warn!(
+            "update to component {} of entity {} sent without authority",
+            component_id,
+            entity_id
+        );
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            entity_id,
+            component_id,
+            "update to component sent without authority"
+        );
+    }
+}
+
+impl Display for AuthorityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "worker is not authoritative over component {} of entity {}",
+            self.component_id, self.entity_id
+        )
+    }
+}
+
+impl std::error::Error for AuthorityError {}
+
+impl<T: WorkerConnection + ?Sized> UpdateSender for T {
+    fn authority_tracker(&self) -> &AuthorityTracker {
+        WorkerConnection::authority_tracker(self)
+    }
+
+    fn send_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.send_component_update(entity_id, update)
+    }
+
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        WorkerConnection::send_authority_loss_imminent_acknowledgement(
+            self,
+            entity_id,
+            component_id,
+        )
+    }
+}
+
+impl dyn UpdateSender + '_ {
+    /// Returns a writer sending updates to the component of the given entity, or an error if the
+    /// worker is not authoritative over it.
+    pub fn write<C: Component>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<ComponentWriter<'_, C, Self>, AuthorityError> {
+        ComponentWriter::new(self, entity_id)
+    }
+}
+
+impl<'a, C: Component, S: UpdateSender + ?Sized> ComponentWriter<'a, C, S> {
+    /// Returns a writer for the component, or an error if the worker is not authoritative over it.
+    pub fn new(connection: &'a S, entity_id: EntityId) -> Result<Self, AuthorityError> {
+        if !connection
+            .authority_tracker()
+            .is_authoritative(entity_id, C::ID)
+        {
+            return Err(AuthorityError {
+                entity_id,
+                component_id: C::ID,
+            });
+        }
+        Ok(Self {
+            connection,
+            entity_id,
+            _component: PhantomData,
+        })
+    }
+
+    pub fn entity_id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    /// Sends an update to the component. Returns false if the update could not be sent.
+    pub fn send_update(&self, update: &C::Update) -> bool {
+        self.connection.send_update(
+            self.entity_id,
+            ComponentUpdate::new(C::ID, update.to_update()),
+        )
+    }
+
+    /// Sends the full state of the component as an update. Returns false if the update could not
+    /// be sent.
+    pub fn send(&self, component: &C) -> bool {
+        self.connection.send_update(
+            self.entity_id,
+            ComponentUpdate::new(C::ID, component.to_data().into_update()),
+        )
+    }
+}
+
+impl AuthorityLossHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler invoked with the ID of the entity whenever the authority loss of the
+    /// component becomes imminent. Once every handler of the component has run, the loss is
+    /// acknowledged, so handlers must send any final update before returning.
+    pub fn on_authority_loss_imminent<F>(&mut self, component_id: ComponentId, callback: F)
+    where
+        F: FnMut(EntityId, &dyn UpdateSender) + Send + 'static,
+    {
+        self.callbacks
+            .entry(component_id)
+            .or_default()
+            .push(Box::new(callback));
+    }
+
+    /// Applies the AuthorityChange ops of the list in order. The connection must have recorded the
+    /// list, so that handlers can still write to the components losing authority.
+    pub fn process_ops<S: UpdateSender>(&mut self, op_list: &OpList, connection: &S) {
+        for op in op_list {
+            if let WorkerOp::AuthorityChange(op) = op {
+                self.apply(op, connection);
+            }
+        }
+    }
+
+    /// Runs the handlers registered for the component if its authority loss is imminent, then
+    /// acknowledges the loss. Components without handlers are left to the caller to acknowledge.
+    pub fn apply<S: UpdateSender>(&mut self, op: &AuthorityChangeOp, connection: &S) {
+        if op.authority != Authority::AuthorityLossImminent {
+            return;
+        }
+        if let Some(callbacks) = self.callbacks.get_mut(&op.component_id) {
+            for callback in callbacks {
+                callback(op.entity_id, connection);
+            }
+            connection.send_authority_loss_imminent_acknowledgement(op.entity_id, op.component_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::Metadata;
+    use crate::worker::mock::{MockConnection, SentMessage};
+    use crate::worker::simulation::Simulation;
+    use crate::worker::test_util::metadata;
+    use std::sync::{Arc, Mutex};
+
+    /// Checks a connection whose worker gains authority over the Metadata of the entity with its
+    /// next op list, and never over that of the entity after it.
+    fn check_writer<W: WorkerConnection>(connection: &mut W, entity_id: EntityId) {
+        assert_eq!(
+            connection.write::<Metadata>(entity_id).err(),
+            Some(AuthorityError {
+                entity_id,
+                component_id: Metadata::ID,
+            })
+        );
+        connection.get_op_list(0);
+        assert_eq!(
+            connection.authority(entity_id, Metadata::ID),
+            Authority::Authoritative
+        );
+        let writer = connection.write::<Metadata>(entity_id).unwrap();
+        assert!(writer.send(&metadata("rock")));
+        assert!(connection.write::<Metadata>(entity_id + 1).is_err());
+        assert_eq!(connection.unauthoritative_updates(), 0);
+
+        connection.send_component_update(
+            entity_id + 1,
+            ComponentUpdate::new(Metadata::ID, metadata("rock").to_data().into_update()),
+        );
+        assert_eq!(connection.unauthoritative_updates(), 1);
+    }
+
+    #[test]
+    fn mock_connections_track_authority() {
+        let mut connection = MockConnection::new("worker");
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        check_writer(&mut connection, 1);
+        assert_eq!(connection.take_component_updates().len(), 2);
+    }
+
+    #[test]
+    fn simulated_connections_track_authority() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut connection = simulation.connect("server-1", vec!["server".to_owned()]);
+        check_writer(&mut connection, entity_id);
+        assert_eq!(
+            simulation
+                .read_component::<Metadata>(entity_id)
+                .map(|metadata| metadata.entity_type),
+            Some("rock".to_owned())
+        );
+    }
+
+    #[test]
+    fn authority_loss_is_only_acknowledged_when_imminent() {
+        let mut connection = MockConnection::new("worker");
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        connection.get_op_list(0);
+        assert!(!connection.acknowledge_authority_loss(1, Metadata::ID));
+
+        connection.authority_change(1, Metadata::ID, Authority::AuthorityLossImminent);
+        connection.get_op_list(0);
+        assert!(connection.write::<Metadata>(1).is_ok());
+        assert!(connection.acknowledge_authority_loss(1, Metadata::ID));
+
+        connection.remove_entity(1);
+        connection.get_op_list(0);
+        assert_eq!(
+            connection.authority(1, Metadata::ID),
+            Authority::NotAuthoritative
+        );
+    }
+
+    /// Describes the updates and acknowledgements sent through the connection.
+    fn sent(connection: &MockConnection) -> Vec<String> {
+        connection
+            .take_sent()
+            .into_iter()
+            .filter_map(|message| match message {
+                SentMessage::ComponentUpdate {
+                    entity_id, update, ..
+                } => Some(format!(
+                    "update {} {}",
+                    entity_id,
+                    update.schema_type.unwrap().get_fields().get_string(1)
+                )),
+                SentMessage::AuthorityLossImminentAcknowledgement {
+                    entity_id,
+                    component_id,
+                } => Some(format!("acknowledge {} {}", entity_id, component_id)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn handlers_run_in_order_before_the_loss_is_acknowledged() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut connection = MockConnection::new("worker");
+        for name in &["first", "second"] {
+            let calls = calls.clone();
+            connection.on_authority_loss_imminent(Metadata::ID, move |entity_id, connection| {
+                calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", name, entity_id));
+                let writer = connection.write::<Metadata>(entity_id).unwrap();
+                assert!(writer.send(&metadata(name)));
+            });
+        }
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        connection.authority_change(2, Metadata::ID, Authority::Authoritative);
+        connection.authority_change(2, Metadata::ID, Authority::AuthorityLossImminent);
+        connection.authority_change(1, Metadata::ID, Authority::AuthorityLossImminent);
+        connection.get_op_list(0);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["first 2", "second 2", "first 1", "second 1"]
+        );
+        assert_eq!(
+            sent(&connection),
+            vec![
+                "update 2 first",
+                "update 2 second",
+                "acknowledge 2 53",
+                "update 1 first",
+                "update 1 second",
+                "acknowledge 1 53",
+            ]
+        );
+    }
+
+    #[test]
+    fn handlers_only_run_for_imminent_losses_of_their_component() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut connection = MockConnection::new("worker");
+        let counted = calls.clone();
+        connection.on_authority_loss_imminent(Metadata::ID, move |_, _| {
+            *counted.lock().unwrap() += 1;
+        });
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        connection.authority_change(1, Metadata::ID, Authority::NotAuthoritative);
+        connection.authority_change(1, Metadata::ID + 1, Authority::AuthorityLossImminent);
+        connection.get_op_list(0);
+
+        assert_eq!(*calls.lock().unwrap(), 0);
+        assert!(sent(&connection).is_empty());
+    }
+}
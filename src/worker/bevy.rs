@@ -0,0 +1,465 @@
+//! A Bevy plugin which mirrors the entities received by a worker connection into the Bevy world.
+//!
+//! Each SpatialOS entity is spawned with a SpatialEntityId component holding its ID. The
+//! components registered with SpatialOsPlugin::with_component are inserted, updated and removed as
+//! ops are received, and Authoritative marks those the worker is authoritative over. Changes made
+//! by Bevy systems to authoritative components are sent back to SpatialOS at the end of each frame.
+//! Component and authority ops are routed to the components the same way as by the EcsAdapter of
+//! the ecs module.
+//!
+//! The plugin works with any WorkerConnection, held by the SpatialConnection non-send resource. It
+//! connects to SpatialOS by itself when created with SpatialOsPlugin::new, while a MockConnection
+//! or SimulatedConnection can be inserted by tests into an app using
+//! SpatialOsPlugin::without_connecting. Once disconnected, the mirrored entities are despawned.
+
+use crate::schema::Component;
+use crate::worker::connection::{Connection, ConnectionFuture, WorkerConnection};
+use crate::worker::ecs::{ComponentHooks, OpRouter};
+use crate::worker::op::WorkerOp;
+use crate::worker::{Authority, EntityId};
+use bevy_app::{App, Plugin, PostUpdate, PreUpdate};
+use bevy_ecs::component::Component as BevyComponent;
+use bevy_ecs::prelude::{
+    DetectChanges, DetectChangesMut, Entity, Event, IntoSystemConfigs, Mut, NonSend, Query, Ref,
+    Resource, With, World,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+pub use crate::worker::ecs::{Authoritative, SpatialEntityId};
+
+/// Connects to SpatialOS when the app starts, and keeps the world in sync with the connection.
+pub struct SpatialOsPlugin<W: WorkerConnection + 'static = Connection> {
+    connect: Option<Box<dyn Fn() -> ConnectionFuture + Send + Sync>>,
+    router: OpRouter<World, Entity>,
+    systems: Vec<fn(&mut App)>,
+    _connection: PhantomData<fn() -> W>,
+}
+
+/// The connection of the worker, inserted as a non-send resource once the connection future has
+/// completed, and removed when the worker is disconnected.
+pub struct SpatialConnection<W: WorkerConnection = Connection>(pub W);
+
+#[derive(Resource, Default)]
+/// The Bevy entity spawned for each SpatialOS entity in the view of the worker.
+pub struct SpatialEntities {
+    entities: HashMap<EntityId, Entity>,
+}
+
+#[derive(Event, Debug)]
+/// Sent when the connection is lost.
+pub struct Disconnected {
+    pub reason: String,
+}
+
+/// The connection future, polled each frame until it completes.
+struct PendingConnection(ConnectionFuture);
+
+#[derive(Resource)]
+struct RegisteredComponents(OpRouter<World, Entity>);
+
+impl SpatialOsPlugin {
+    /// Creates a plugin which connects with the future returned by the given function, such as
+    /// ConnectionFuture::connect_async.
+    pub fn new<F>(connect: F) -> Self
+    where
+        F: Fn() -> ConnectionFuture + Send + Sync + 'static,
+    {
+        Self {
+            connect: Some(Box::new(connect)),
+            ..Self::without_connecting()
+        }
+    }
+}
+
+impl<W: WorkerConnection + 'static> SpatialOsPlugin<W> {
+    /// Creates a plugin which does not connect by itself. The world is kept in sync with the
+    /// connection once it is inserted into the app as a SpatialConnection.
+    pub fn without_connecting() -> Self {
+        Self {
+            connect: None,
+            router: OpRouter::new(),
+            systems: Vec::new(),
+            _connection: PhantomData,
+        }
+    }
+
+    /// Registers a component to mirror into the world. Updates to the component are sent as the
+    /// full state of the component.
+    pub fn with_component<C>(mut self) -> Self
+    where
+        C: Component + BevyComponent,
+        C::Update: 'static,
+    {
+        self.router.register::<C>(ComponentHooks {
+            add: add_component::<C>,
+            remove: remove_component::<C>,
+            update: update_component::<C>,
+            authority: change_authority::<C>,
+        });
+        self.systems.push(|app| {
+            app.add_systems(PostUpdate, send_updates::<W, C>);
+        });
+        self
+    }
+}
+
+impl<W: WorkerConnection + 'static> Plugin for SpatialOsPlugin<W> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RegisteredComponents(self.router.clone()))
+            .init_resource::<SpatialEntities>()
+            .add_event::<Disconnected>();
+        match &self.connect {
+            Some(connect) => {
+                app.insert_non_send_resource(PendingConnection(connect()))
+                    .add_systems(PreUpdate, (poll_connection, receive_ops::<W>).chain());
+            }
+            None => {
+                app.add_systems(PreUpdate, receive_ops::<W>);
+            }
+        }
+        for add_systems in &self.systems {
+            add_systems(app);
+        }
+    }
+}
+
+impl SpatialEntities {
+    pub fn get(&self, entity_id: EntityId) -> Option<Entity> {
+        self.entities.get(&entity_id).copied()
+    }
+}
+
+fn poll_connection(world: &mut World) {
+    let connection = match world.get_non_send_resource_mut::<PendingConnection>() {
+        Some(mut pending) => pending.0.get(Some(0)),
+        None => return,
+    };
+    if let Some(connection) = connection {
+        world.remove_non_send_resource::<PendingConnection>();
+        world.insert_non_send_resource(SpatialConnection(connection));
+    }
+}
+
+fn receive_ops<W: WorkerConnection + 'static>(world: &mut World) {
+    let op_list = match world.get_non_send_resource_mut::<SpatialConnection<W>>() {
+        Some(mut connection) => connection.0.get_op_list(0),
+        None => return,
+    };
+    world.resource_scope(|world, components: Mut<RegisteredComponents>| {
+        for op in &op_list {
+            match op {
+                WorkerOp::Disconnect(op) => {
+                    world.remove_non_send_resource::<SpatialConnection<W>>();
+                    let entities =
+                        std::mem::take(&mut world.resource_mut::<SpatialEntities>().entities);
+                    for entity in entities.into_values() {
+                        world.despawn(entity);
+                    }
+                    world.send_event(Disconnected {
+                        reason: op.reason.clone(),
+                    });
+                }
+                WorkerOp::AddEntity(op) => {
+                    let entity = world.spawn(SpatialEntityId(op.entity_id)).id();
+                    world
+                        .resource_mut::<SpatialEntities>()
+                        .entities
+                        .insert(op.entity_id, entity);
+                }
+                WorkerOp::RemoveEntity(op) => {
+                    let entity = world
+                        .resource_mut::<SpatialEntities>()
+                        .entities
+                        .remove(&op.entity_id);
+                    if let Some(entity) = entity {
+                        world.despawn(entity);
+                    }
+                }
+                op => components.0.route(world, op, |world, entity_id| {
+                    world.resource::<SpatialEntities>().get(entity_id)
+                }),
+            }
+        }
+    });
+}
+
+fn add_component<C: Component + BevyComponent>(
+    world: &mut World,
+    entity: Entity,
+    component: Box<dyn Any>,
+) {
+    if let Ok(component) = component.downcast::<C>() {
+        world.entity_mut(entity).insert(*component);
+    }
+}
+
+fn remove_component<C: Component + BevyComponent>(world: &mut World, entity: Entity) {
+    world.entity_mut(entity).remove::<(C, Authoritative<C>)>();
+}
+
+/// Applies an update to the component. Updates to authoritative components are the worker's own
+/// updates sent back by SpatialOS, so they are applied without marking the component as changed,
+/// which would send them again.
+fn update_component<C>(world: &mut World, entity: Entity, update: Box<dyn Any>)
+where
+    C: Component + BevyComponent,
+    C::Update: 'static,
+{
+    let update = match update.downcast::<C::Update>() {
+        Ok(update) => update,
+        Err(_) => return,
+    };
+    let authoritative = world.get::<Authoritative<C>>(entity).is_some();
+    if let Some(mut component) = world.get_mut::<C>(entity) {
+        if authoritative {
+            component.bypass_change_detection().apply_update(&update);
+        } else {
+            component.apply_update(&update);
+        }
+    }
+}
+
+fn change_authority<C: Component + BevyComponent>(
+    world: &mut World,
+    entity: Entity,
+    authority: &Authority,
+) {
+    let mut entity = world.entity_mut(entity);
+    match authority {
+        Authority::Authoritative | Authority::AuthorityLossImminent => {
+            entity.insert(Authoritative::<C>::default());
+        }
+        Authority::NotAuthoritative => {
+            entity.remove::<Authoritative<C>>();
+        }
+    }
+}
+
+/// Sends the components changed since the last frame, skipping those which were just received.
+fn send_updates<W: WorkerConnection + 'static, C: Component + BevyComponent>(
+    connection: Option<NonSend<SpatialConnection<W>>>,
+    query: Query<(&SpatialEntityId, Ref<C>), With<Authoritative<C>>>,
+) {
+    let connection = match connection {
+        Some(connection) => connection,
+        None => return,
+    };
+    for (entity, component) in query.iter() {
+        if component.is_changed() && !component.is_added() {
+            if let Ok(writer) = connection.0.write::<C>(entity.0) {
+                writer.send(&component);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Metadata, MetadataUpdate};
+    use crate::schema::{ObjectMut, ObjectRef, SchemaType, Update};
+    use crate::worker::mock::MockConnection;
+    use crate::worker::{ComponentId, ComponentUpdate, ConnectionStatusCode};
+    use bevy_ecs::event::Events;
+
+    #[derive(BevyComponent, Debug, Clone, PartialEq)]
+    /// The Metadata component, mirrored into the Bevy world.
+    struct Label {
+        entity_type: String,
+    }
+
+    impl SchemaType for Label {
+        fn from_object(object: &ObjectRef) -> Self {
+            Self {
+                entity_type: object.get_string(1),
+            }
+        }
+
+        fn write_object(&self, object: &mut ObjectMut) {
+            object.add_string(1, &self.entity_type);
+        }
+    }
+
+    impl Component for Label {
+        const ID: ComponentId = Metadata::ID;
+        type Update = MetadataUpdate;
+
+        fn apply_update(&mut self, update: &MetadataUpdate) {
+            if let Some(entity_type) = &update.entity_type {
+                self.entity_type = entity_type.clone();
+            }
+        }
+    }
+
+    fn label(entity_type: &str) -> Label {
+        Label {
+            entity_type: entity_type.to_owned(),
+        }
+    }
+
+    fn rename(entity_type: &str) -> ComponentUpdate {
+        let update = MetadataUpdate {
+            entity_type: Some(entity_type.to_owned()),
+        };
+        ComponentUpdate::new(Label::ID, update.to_update())
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(
+            SpatialOsPlugin::<MockConnection>::without_connecting().with_component::<Label>(),
+        )
+        .insert_non_send_resource(SpatialConnection(MockConnection::new("worker")));
+        app
+    }
+
+    fn mock(app: &mut App) -> &mut MockConnection {
+        &mut app
+            .world_mut()
+            .non_send_resource_mut::<SpatialConnection<MockConnection>>()
+            .into_inner()
+            .0
+    }
+
+    fn entity(app: &App, entity_id: EntityId) -> Option<Entity> {
+        app.world().resource::<SpatialEntities>().get(entity_id)
+    }
+
+    /// Adds an entity with a Label the worker is authoritative over, and returns its Bevy entity.
+    fn authoritative_entity(app: &mut App) -> Entity {
+        let connection = mock(app);
+        connection.add_entity(1);
+        connection.add_component(1, label("tree").to_component_data());
+        connection.authority_change(1, Label::ID, Authority::Authoritative);
+        app.update();
+        entity(app, 1).unwrap()
+    }
+
+    fn sent_labels(app: &mut App) -> Vec<(EntityId, Option<String>)> {
+        mock(app)
+            .take_component_updates()
+            .into_iter()
+            .map(|(entity_id, update)| {
+                let update = MetadataUpdate::from_update(update.schema_type.as_ref().unwrap());
+                (entity_id, update.entity_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entities_and_components_are_mirrored() {
+        let mut app = app();
+        let connection = mock(&mut app);
+        connection.add_entity(1);
+        connection.add_component(1, label("tree").to_component_data());
+        connection.component_update(1, rename("rock"));
+        app.update();
+
+        let entity = entity(&app, 1).unwrap();
+        assert_eq!(
+            app.world().get::<SpatialEntityId>(entity),
+            Some(&SpatialEntityId(1))
+        );
+        assert_eq!(app.world().get::<Label>(entity), Some(&label("rock")));
+
+        mock(&mut app).remove_component(1, Label::ID);
+        app.update();
+        assert_eq!(app.world().get::<Label>(entity), None);
+
+        mock(&mut app).remove_entity(1);
+        app.update();
+        assert_eq!(self::entity(&app, 1), None);
+        assert!(app.world().get_entity(entity).is_none());
+    }
+
+    #[test]
+    fn authority_is_exposed_as_a_marker() {
+        let mut app = app();
+        let entity = authoritative_entity(&mut app);
+        assert!(app.world().get::<Authoritative<Label>>(entity).is_some());
+
+        mock(&mut app).authority_change(1, Label::ID, Authority::AuthorityLossImminent);
+        app.update();
+        assert!(app.world().get::<Authoritative<Label>>(entity).is_some());
+
+        mock(&mut app).authority_change(1, Label::ID, Authority::NotAuthoritative);
+        app.update();
+        assert!(app.world().get::<Authoritative<Label>>(entity).is_none());
+
+        mock(&mut app).authority_change(1, Label::ID, Authority::Authoritative);
+        mock(&mut app).remove_component(1, Label::ID);
+        app.update();
+        assert!(app.world().get::<Authoritative<Label>>(entity).is_none());
+    }
+
+    #[test]
+    fn changes_to_authoritative_components_are_sent() {
+        let mut app = app();
+        let entity = authoritative_entity(&mut app);
+        app.update();
+        assert!(sent_labels(&mut app).is_empty());
+
+        app.world_mut()
+            .get_mut::<Label>(entity)
+            .unwrap()
+            .entity_type = "bush".to_owned();
+        app.update();
+        assert_eq!(sent_labels(&mut app), vec![(1, Some("bush".to_owned()))]);
+
+        app.update();
+        assert!(sent_labels(&mut app).is_empty());
+    }
+
+    #[test]
+    fn changes_without_authority_are_not_sent() {
+        let mut app = app();
+        let entity = authoritative_entity(&mut app);
+        mock(&mut app).authority_change(1, Label::ID, Authority::NotAuthoritative);
+        app.update();
+
+        app.world_mut()
+            .get_mut::<Label>(entity)
+            .unwrap()
+            .entity_type = "bush".to_owned();
+        app.update();
+        assert!(sent_labels(&mut app).is_empty());
+    }
+
+    #[test]
+    fn received_updates_are_not_sent_back() {
+        let mut app = app();
+        let entity = authoritative_entity(&mut app);
+        app.update();
+        sent_labels(&mut app);
+
+        mock(&mut app).component_update(1, rename("rock"));
+        app.update();
+        app.update();
+        assert_eq!(app.world().get::<Label>(entity), Some(&label("rock")));
+        assert!(sent_labels(&mut app).is_empty());
+    }
+
+    #[test]
+    fn mirrored_entities_are_despawned_on_disconnect() {
+        let mut app = app();
+        let entity = authoritative_entity(&mut app);
+        mock(&mut app).disconnect(ConnectionStatusCode::NetworkError, "lost");
+        app.update();
+
+        assert!(app.world().get_entity(entity).is_none());
+        assert_eq!(self::entity(&app, 1), None);
+        assert!(app
+            .world()
+            .get_non_send_resource::<SpatialConnection<MockConnection>>()
+            .is_none());
+        let events = app.world().resource::<Events<Disconnected>>();
+        let reasons: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| event.reason.clone())
+            .collect();
+        assert_eq!(reasons, vec!["lost".to_owned()]);
+    }
+}
@@ -0,0 +1,259 @@
+//! A registry of the Rust types of components, so that component data and updates can be decoded
+//! when their component ID is only known at runtime. The Dispatcher decodes the ops it receives
+//! through a registry, and ConnectionParameters::set_component_vtables takes the vtables of its
+//! handlers.
+
+use crate::schema::{self, Component, ObjectRef, Update};
+use crate::worker::component_vtable::{ComponentHandler, ComponentVtable};
+use crate::worker::op::{OpComponentData, OpComponentUpdate};
+use crate::worker::ComponentId;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone)]
+/// The Rust types registered for a component.
+pub struct ComponentInfo {
+    pub component_id: ComponentId,
+    /// The name of the Rust type of the component.
+    pub name: &'static str,
+    pub data_type: TypeId,
+    pub update_type: TypeId,
+    /// The types of the command requests and responses, if a handler was registered.
+    pub command_request_type: Option<TypeId>,
+    pub command_response_type: Option<TypeId>,
+    /// The vtable of the component, if a handler was registered.
+    pub vtable: Option<ComponentVtable>,
+    read_data: fn(&ObjectRef) -> Box<dyn Any>,
+    read_update: fn(&schema::ComponentUpdate) -> Box<dyn Any>,
+    read_op_update: fn(&OpComponentUpdate) -> Option<Box<dyn Any>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when registering the handler of a component which is not registered.
+pub struct UnregisteredComponent {
+    pub component_id: ComponentId,
+}
+
+impl Display for UnregisteredComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "component {} is not registered", self.component_id)
+    }
+}
+
+impl std::error::Error for UnregisteredComponent {}
+
+#[derive(Debug, Clone, Default)]
+/// A set of components, looked up by component ID.
+pub struct ComponentRegistry {
+    components: HashMap<ComponentId, ComponentInfo>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a component type, replacing any component registered with the same ID.
+    pub fn register<C>(&mut self)
+    where
+        C: Component + 'static,
+        C::Update: 'static,
+    {
+        self.components.insert(
+            C::ID,
+            ComponentInfo {
+                component_id: C::ID,
+                name: std::any::type_name::<C>(),
+                data_type: TypeId::of::<C>(),
+                update_type: TypeId::of::<C::Update>(),
+                command_request_type: None,
+                command_response_type: None,
+                vtable: None,
+                read_data: read_data::<C>,
+                read_update: read_update::<C>,
+                read_op_update: read_op_update::<C>,
+            },
+        );
+    }
+
+    /// Registers the handler of the user handles of a component, whose vtable is then used by
+    /// ConnectionParameters::set_component_vtables. The component must have been registered
+    /// first, with its Rust type.
+    pub fn register_handler<H: ComponentHandler>(&mut self) -> Result<(), UnregisteredComponent> {
+        let component = self
+            .components
+            .get_mut(&H::ID)
+            .ok_or(UnregisteredComponent {
+                component_id: H::ID,
+            })?;
+        component.command_request_type = Some(TypeId::of::<H::CommandRequest>());
+        component.command_response_type = Some(TypeId::of::<H::CommandResponse>());
+        component.vtable = Some(ComponentVtable::from_handler::<H>());
+        Ok(())
+    }
+
+    pub fn get(&self, component_id: ComponentId) -> Option<&ComponentInfo> {
+        self.components.get(&component_id)
+    }
+
+    pub fn contains(&self, component_id: ComponentId) -> bool {
+        self.components.contains_key(&component_id)
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.components.values()
+    }
+
+    /// Returns the vtables of every component registered with a handler.
+    pub fn vtables(&self) -> Vec<ComponentVtable> {
+        self.components
+            .values()
+            .filter_map(|component| component.vtable)
+            .collect()
+    }
+
+    /// Deserializes component data into the registered type of its component, which can be
+    /// recovered with Box::downcast. Returns None if the component is not registered.
+    pub fn read_data(
+        &self,
+        component_id: ComponentId,
+        data: &schema::ComponentData,
+    ) -> Option<Box<dyn Any>> {
+        let component = self.get(component_id)?;
+        Some((component.read_data)(&data.get_fields()))
+    }
+
+    /// Deserializes a component update into the registered update type of its component. Returns
+    /// None if the component is not registered.
+    pub fn read_update(
+        &self,
+        component_id: ComponentId,
+        update: &schema::ComponentUpdate,
+    ) -> Option<Box<dyn Any>> {
+        let component = self.get(component_id)?;
+        Some((component.read_update)(update))
+    }
+
+    /// Deserializes the component data of an op. Returns None if the component is not
+    /// registered, or if the data is only available through a user handle.
+    pub fn read_op_data(&self, data: &OpComponentData) -> Option<Box<dyn Any>> {
+        let component = self.get(data.component_id())?;
+        Some((component.read_data)(&data.get_fields()?))
+    }
+
+    /// Deserializes the component update of an op. Returns None if the component is not
+    /// registered, or if the update is only available through a user handle.
+    pub fn read_op_update(&self, update: &OpComponentUpdate) -> Option<Box<dyn Any>> {
+        let component = self.get(update.component_id())?;
+        (component.read_op_update)(update)
+    }
+}
+
+fn read_data<C: Component + 'static>(fields: &ObjectRef) -> Box<dyn Any> {
+    Box::new(C::from_object(fields))
+}
+
+fn read_update<C>(update: &schema::ComponentUpdate) -> Box<dyn Any>
+where
+    C: Component,
+    C::Update: 'static,
+{
+    Box::new(C::Update::from_update(update))
+}
+
+fn read_op_update<C>(update: &OpComponentUpdate) -> Option<Box<dyn Any>>
+where
+    C: Component,
+    C::Update: 'static,
+{
+    update
+        .read::<C>()
+        .map(|update| Box::new(update) as Box<dyn Any>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Coordinates, Metadata, MetadataUpdate, Position};
+    use crate::worker::test_util::MetadataHandler;
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<Metadata>();
+        registry.register::<Position>();
+        registry
+    }
+
+    #[test]
+    fn data_is_read_into_the_registered_type() {
+        let registry = registry();
+        let metadata = Metadata {
+            entity_type: "tree".to_owned(),
+        };
+        let read = registry
+            .read_data(Metadata::ID, &metadata.to_data())
+            .unwrap();
+        assert_eq!(
+            read.downcast::<Metadata>().ok().map(|read| *read),
+            Some(metadata)
+        );
+
+        let position = Position {
+            coords: Coordinates {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+        };
+        let read = registry
+            .read_data(Position::ID, &position.to_data())
+            .unwrap();
+        assert!(read.is::<Position>());
+        assert!(registry.read_data(1000, &position.to_data()).is_none());
+    }
+
+    #[test]
+    fn updates_are_read_into_the_registered_update_type() {
+        let registry = registry();
+        let update = MetadataUpdate {
+            entity_type: Some("rock".to_owned()),
+        };
+        let read = registry
+            .read_update(Metadata::ID, &update.to_update())
+            .unwrap();
+        assert_eq!(
+            read.downcast::<MetadataUpdate>().ok().map(|read| *read),
+            Some(update.clone())
+        );
+        assert!(registry.read_update(1000, &update.to_update()).is_none());
+    }
+
+    #[test]
+    fn vtables_are_returned_for_components_with_a_handler() {
+        let mut registry = registry();
+        assert!(registry.vtables().is_empty());
+
+        registry.register_handler::<MetadataHandler>().unwrap();
+        let vtables = registry.vtables();
+        assert_eq!(vtables.len(), 1);
+        assert_eq!(vtables[0].component_id, Metadata::ID);
+        assert!(vtables[0].component_data_serialize.is_some());
+
+        let metadata = registry.get(Metadata::ID).unwrap();
+        assert_eq!(metadata.command_request_type, Some(TypeId::of::<String>()));
+        assert!(registry.get(Position::ID).unwrap().vtable.is_none());
+    }
+
+    #[test]
+    fn handlers_of_unregistered_components_are_rejected() {
+        let mut registry = ComponentRegistry::new();
+        assert_eq!(
+            registry.register_handler::<MetadataHandler>(),
+            Err(UnregisteredComponent {
+                component_id: Metadata::ID
+            })
+        );
+        assert!(registry.vtables().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::schema;
 use crate::worker::CommandIndex;
 use crate::worker::CommandRequestHandle;
 use crate::worker::CommandResponseHandle;
@@ -8,7 +9,13 @@ use spatialos_sys::{
     Schema_CommandRequest, Schema_CommandResponse, Schema_ComponentData, Schema_ComponentUpdate,
     Worker_CommandRequestHandle, Worker_ComponentVtable,
 };
+use std::any::TypeId;
+use std::fmt;
+use std::mem::ManuallyDrop;
 use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::NonNull;
+use std::sync::Arc;
 
 pub type CommandRequestFreeFn =
     unsafe extern "C" fn(ComponentId, CommandIndex, *mut c_void, *mut Worker_CommandRequestHandle);
@@ -108,6 +115,7 @@ pub type ComponentUpdateSerialize = unsafe extern "C" fn(
     *mut *mut Schema_ComponentUpdate,
 );
 
+#[derive(Debug, Clone, Copy)]
 pub struct ComponentVtable {
     /// Component ID that this vtable is for. If this is the default vtable, this field is ignored.
     pub component_id: ComponentId,
@@ -227,3 +235,488 @@ impl From<Worker_ComponentVtable> for ComponentVtable {
         }
     }
 }
+
+// The SDK calls the functions of a vtable from its internal threads, passing them the user data, so
+// the user data must already be safe to share between threads.
+unsafe impl Send for ComponentVtable {}
+unsafe impl Sync for ComponentVtable {}
+
+/// Converts the user handles of a component to and from schema data. Each handle holds a Rust
+/// value shared by reference-counting, so the SDK can copy it without copying the value.
+///
+/// Every method is called from internal SDK threads, once the vtable returned by
+/// ComponentVtable::from_handler is given to the connection parameters.
+pub trait ComponentHandler: 'static {
+    /// The ID of the component the handles belong to.
+    const ID: ComponentId;
+
+    type Data: Send + Sync + 'static;
+    type Update: Send + Sync + 'static;
+    type CommandRequest: Send + Sync + 'static;
+    type CommandResponse: Send + Sync + 'static;
+
+    /// Reads component data received from SpatialOS. Returning None makes the SDK drop the op.
+    fn deserialize_data(data: &schema::ComponentData) -> Option<Self::Data>;
+
+    /// Writes component data sent to SpatialOS.
+    fn serialize_data(data: &Self::Data) -> schema::ComponentData;
+
+    fn deserialize_update(update: &schema::ComponentUpdate) -> Option<Self::Update>;
+
+    fn serialize_update(update: &Self::Update) -> schema::ComponentUpdate;
+
+    fn deserialize_command_request(
+        command_index: CommandIndex,
+        request: &schema::CommandRequest,
+    ) -> Option<Self::CommandRequest>;
+
+    fn serialize_command_request(
+        command_index: CommandIndex,
+        request: &Self::CommandRequest,
+    ) -> schema::CommandRequest;
+
+    fn deserialize_command_response(
+        command_index: CommandIndex,
+        response: &schema::CommandResponse,
+    ) -> Option<Self::CommandResponse>;
+
+    fn serialize_command_response(
+        command_index: CommandIndex,
+        response: &Self::CommandResponse,
+    ) -> schema::CommandResponse;
+}
+
+impl ComponentVtable {
+    /// Creates the vtable of a component whose user handles are converted by the given handler.
+    ///
+    /// The SDK does not take ownership of the user handles it is sent. It adds its own reference
+    /// through the copy function of the vtable whenever it keeps a handle, and drops it through
+    /// the free function, so the connection releases its reference once a send returns.
+    pub fn from_handler<H: ComponentHandler>() -> Self {
+        Self {
+            component_id: H::ID,
+            user_data: std::ptr::null_mut(),
+            command_request_free: Some(command_free),
+            command_request_copy: Some(command_copy),
+            command_request_deserialize: Some(command_request_deserialize::<H>),
+            command_request_serialize: Some(command_request_serialize::<H>),
+            command_response_free: Some(command_free),
+            command_response_copy: Some(command_copy),
+            command_response_deserialize: Some(command_response_deserialize::<H>),
+            command_response_serialize: Some(command_response_serialize::<H>),
+            component_data_free: Some(component_free),
+            component_data_copy: Some(component_copy),
+            component_data_deserialize: Some(component_data_deserialize::<H>),
+            component_data_serialize: Some(component_data_serialize::<H>),
+            component_update_free: Some(component_free),
+            component_update_copy: Some(component_copy),
+            component_update_deserialize: Some(component_update_deserialize::<H>),
+            component_update_serialize: Some(component_update_serialize::<H>),
+        }
+    }
+}
+
+/// The allocation behind a user handle. The fields preceding the value do not depend on its
+/// type, so any handle can be copied, freed or checked without knowing it.
+#[repr(C)]
+struct HandleInner<T> {
+    type_id: TypeId,
+    retain: unsafe fn(*const c_void),
+    release: unsafe fn(*const c_void),
+    value: T,
+}
+
+/// A reference to a Rust value used as a user handle. Cloning the handle adds a reference to the
+/// same value, which is dropped with its last reference.
+pub struct UserHandle {
+    inner: NonNull<c_void>,
+}
+
+impl UserHandle {
+    pub fn new<T: Send + Sync + 'static>(value: T) -> Self {
+        let inner = Arc::new(HandleInner {
+            type_id: TypeId::of::<T>(),
+            retain: retain::<T>,
+            release: release::<T>,
+            value,
+        });
+        Self {
+            inner: NonNull::new(Arc::into_raw(inner) as *mut c_void).unwrap(),
+        }
+    }
+
+    /// Takes ownership of a reference to a handle.
+    ///
+    /// # Safety
+    ///
+    /// The handle must have been created by UserHandle or by the vtable of a ComponentHandler, and
+    /// the reference must not be released elsewhere.
+    pub unsafe fn from_raw(handle: *mut c_void) -> Self {
+        Self {
+            inner: NonNull::new(handle).expect("user handle is null"),
+        }
+    }
+
+    /// Releases ownership of the reference, which must then be released with UserHandle::from_raw
+    /// or by the SDK.
+    pub fn into_raw(self) -> *mut c_void {
+        ManuallyDrop::new(self).inner.as_ptr()
+    }
+
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.inner.as_ptr()
+    }
+
+    /// Returns the value of the handle, or None if it holds a value of another type.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        let inner = self.inner.as_ptr() as *const HandleInner<T>;
+        unsafe {
+            if (*inner).type_id == TypeId::of::<T>() {
+                Some(&(*inner).value)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn header(&self) -> &HandleInner<()> {
+        unsafe { &*(self.inner.as_ptr() as *const HandleInner<()>) }
+    }
+}
+
+/// Returns the value of a handle held by an op, or None if it is null or holds a value of another
+/// type.
+///
+/// # Safety
+///
+/// The handle must have been created by UserHandle or by the vtable of a ComponentHandler, and
+/// must outlive 'a.
+pub(crate) unsafe fn downcast_handle<'a, T: 'static>(handle: *mut c_void) -> Option<&'a T> {
+    if handle.is_null() {
+        return None;
+    }
+    let handle = ManuallyDrop::new(UserHandle::from_raw(handle));
+    handle
+        .downcast_ref::<T>()
+        .map(|value| &*(value as *const T))
+}
+
+/// Releases a reference to a handle which was passed to the SDK, if any. The SDK copies the user
+/// handles it is given, rather than taking ownership of them as it does for schema data.
+///
+/// # Safety
+///
+/// See UserHandle::from_raw.
+pub(crate) unsafe fn release_handle(handle: *mut c_void) {
+    if !handle.is_null() {
+        drop(UserHandle::from_raw(handle));
+    }
+}
+
+/// Handles are only ever read, and hold values which are Send and Sync.
+unsafe impl Send for UserHandle {}
+unsafe impl Sync for UserHandle {}
+
+impl Clone for UserHandle {
+    fn clone(&self) -> Self {
+        unsafe { (self.header().retain)(self.inner.as_ptr()) };
+        Self { inner: self.inner }
+    }
+}
+
+impl Drop for UserHandle {
+    fn drop(&mut self) {
+        unsafe { (self.header().release)(self.inner.as_ptr()) }
+    }
+}
+
+impl fmt::Debug for UserHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("UserHandle").field(&self.inner).finish()
+    }
+}
+
+unsafe fn retain<T>(inner: *const c_void) {
+    Arc::increment_strong_count(inner as *const HandleInner<T>)
+}
+
+unsafe fn release<T>(inner: *const c_void) {
+    Arc::decrement_strong_count(inner as *const HandleInner<T>)
+}
+
+/// Returns a new reference to the same handle, as the SDK expects from the copy functions.
+unsafe fn copy_handle(handle: *mut c_void) -> *mut c_void {
+    let handle = ManuallyDrop::new(UserHandle::from_raw(handle));
+    UserHandle::clone(&handle).into_raw()
+}
+
+/// Reads schema data owned by the SDK into a new handle. Panics are caught, as they must not
+/// unwind into the SDK, and reported as a failure to deserialize.
+unsafe fn deserialize_handle<R, W, T, F>(
+    schema_type: *mut R,
+    handle: *mut *mut c_void,
+    read: F,
+) -> u8
+where
+    W: From<*mut R>,
+    T: Send + Sync + 'static,
+    F: FnOnce(&W) -> Option<T>,
+{
+    let schema_type = ManuallyDrop::new(W::from(schema_type));
+    match panic::catch_unwind(AssertUnwindSafe(|| read(&schema_type))) {
+        Ok(Some(value)) => {
+            *handle = UserHandle::new(value).into_raw();
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Writes the value of a handle into new schema data, owned by the SDK once returned. The SDK has
+/// no way to report a failure to serialize, so if the handler panics or the handle holds a value
+/// of another type, the failure is logged and empty schema data is written instead.
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+unsafe fn serialize_handle<R, W, T, F>(handle: *mut c_void, schema_type: *mut *mut R, write: F)
+where
+    W: Into<*mut R> + Default,
+    T: 'static,
+    F: FnOnce(&T) -> W,
+{
+    let handle = ManuallyDrop::new(UserHandle::from_raw(handle));
+    let written = match handle.downcast_ref::<T>() {
+        Some(value) => panic::catch_unwind(AssertUnwindSafe(|| write(value)))
+            .map_err(|_| "the handler panicked"),
+        None => Err("the user handle holds a value of another type"),
+    };
+    *schema_type = match written {
+        Ok(written) => written.into(),
+        Err(reason) => {
+            #[cfg(feature = "log")]
+            log::error!("sending empty schema data for a user handle: {}", reason);
+            #[cfg(feature = "tracing")]
+            tracing::error!(reason, "sending empty schema data for a user handle");
+            W::default().into()
+        }
+    };
+}
+
+unsafe extern "C" fn component_free(_: ComponentId, _: *mut c_void, handle: *mut c_void) {
+    release_handle(handle)
+}
+
+unsafe extern "C" fn component_copy(
+    _: ComponentId,
+    _: *mut c_void,
+    handle: *mut c_void,
+) -> *mut c_void {
+    copy_handle(handle)
+}
+
+unsafe extern "C" fn command_free(
+    _: ComponentId,
+    _: CommandIndex,
+    _: *mut c_void,
+    handle: *mut c_void,
+) {
+    release_handle(handle)
+}
+
+unsafe extern "C" fn command_copy(
+    _: ComponentId,
+    _: CommandIndex,
+    _: *mut c_void,
+    handle: *mut c_void,
+) -> *mut c_void {
+    copy_handle(handle)
+}
+
+unsafe extern "C" fn component_data_deserialize<H: ComponentHandler>(
+    _: ComponentId,
+    _: *mut c_void,
+    data: *mut Schema_ComponentData,
+    handle: *mut *mut ComponentDataHandle,
+) -> u8 {
+    deserialize_handle(data, handle, H::deserialize_data)
+}
+
+unsafe extern "C" fn component_data_serialize<H: ComponentHandler>(
+    _: ComponentId,
+    _: *mut c_void,
+    handle: *mut ComponentDataHandle,
+    data: *mut *mut Schema_ComponentData,
+) {
+    serialize_handle(handle, data, H::serialize_data)
+}
+
+unsafe extern "C" fn component_update_deserialize<H: ComponentHandler>(
+    _: ComponentId,
+    _: *mut c_void,
+    update: *mut Schema_ComponentUpdate,
+    handle: *mut *mut ComponentUpdateHandle,
+) -> u8 {
+    deserialize_handle(update, handle, H::deserialize_update)
+}
+
+unsafe extern "C" fn component_update_serialize<H: ComponentHandler>(
+    _: ComponentId,
+    _: *mut c_void,
+    handle: *mut ComponentUpdateHandle,
+    update: *mut *mut Schema_ComponentUpdate,
+) {
+    serialize_handle(handle, update, H::serialize_update)
+}
+
+unsafe extern "C" fn command_request_deserialize<H: ComponentHandler>(
+    _: ComponentId,
+    command_index: CommandIndex,
+    _: *mut c_void,
+    request: *mut Schema_CommandRequest,
+    handle: *mut *mut CommandRequestHandle,
+) -> u8 {
+    deserialize_handle(request, handle, |request: &schema::CommandRequest| {
+        H::deserialize_command_request(command_index, request)
+    })
+}
+
+unsafe extern "C" fn command_request_serialize<H: ComponentHandler>(
+    _: ComponentId,
+    command_index: CommandIndex,
+    _: *mut c_void,
+    handle: *mut CommandRequestHandle,
+    request: *mut *mut Schema_CommandRequest,
+) {
+    serialize_handle(handle, request, |value| {
+        H::serialize_command_request(command_index, value)
+    })
+}
+
+unsafe extern "C" fn command_response_deserialize<H: ComponentHandler>(
+    _: ComponentId,
+    command_index: CommandIndex,
+    _: *mut c_void,
+    response: *mut Schema_CommandResponse,
+    handle: *mut *mut CommandResponseHandle,
+) -> u8 {
+    deserialize_handle(response, handle, |response: &schema::CommandResponse| {
+        H::deserialize_command_response(command_index, response)
+    })
+}
+
+unsafe extern "C" fn command_response_serialize<H: ComponentHandler>(
+    _: ComponentId,
+    command_index: CommandIndex,
+    _: *mut c_void,
+    handle: *mut CommandResponseHandle,
+    response: *mut *mut Schema_CommandResponse,
+) {
+    serialize_handle(handle, response, |value| {
+        H::serialize_command_response(command_index, value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Handler;
+
+    impl ComponentHandler for Handler {
+        const ID: ComponentId = 1000;
+
+        type Data = u32;
+        type Update = ();
+        type CommandRequest = ();
+        type CommandResponse = ();
+
+        fn deserialize_data(data: &schema::ComponentData) -> Option<u32> {
+            Some(data.get_fields().get_uint32(1))
+        }
+
+        fn serialize_data(data: &u32) -> schema::ComponentData {
+            assert_ne!(*data, 0, "zero cannot be serialized");
+            let mut serialized = schema::ComponentData::new();
+            serialized.get_fields_mut().add_uint32(1, *data);
+            serialized
+        }
+
+        fn deserialize_update(_: &schema::ComponentUpdate) -> Option<()> {
+            None
+        }
+
+        fn serialize_update(_: &()) -> schema::ComponentUpdate {
+            schema::ComponentUpdate::new()
+        }
+
+        fn deserialize_command_request(_: CommandIndex, _: &schema::CommandRequest) -> Option<()> {
+            None
+        }
+
+        fn serialize_command_request(_: CommandIndex, _: &()) -> schema::CommandRequest {
+            schema::CommandRequest::new()
+        }
+
+        fn deserialize_command_response(
+            _: CommandIndex,
+            _: &schema::CommandResponse,
+        ) -> Option<()> {
+            None
+        }
+
+        fn serialize_command_response(_: CommandIndex, _: &()) -> schema::CommandResponse {
+            schema::CommandResponse::new()
+        }
+    }
+
+    fn serialize(handle: UserHandle) -> schema::ComponentData {
+        let mut data = std::ptr::null_mut();
+        unsafe {
+            component_data_serialize::<Handler>(
+                Handler::ID,
+                std::ptr::null_mut(),
+                handle.as_ptr(),
+                &mut data,
+            )
+        };
+        schema::ComponentData::from(data)
+    }
+
+    #[test]
+    fn handles_are_serialized_by_the_handler() {
+        let data = serialize(UserHandle::new(7u32));
+        assert_eq!(data.get_fields().get_uint32(1), 7);
+    }
+
+    #[test]
+    fn failures_to_serialize_write_empty_data() {
+        let panicked = serialize(UserHandle::new(0u32));
+        assert!(panicked.get_fields().get_unique_field_ids().is_empty());
+
+        let mismatched = serialize(UserHandle::new("not a u32"));
+        assert!(mismatched.get_fields().get_unique_field_ids().is_empty());
+    }
+
+    #[test]
+    fn deserialized_handles_are_reference_counted() {
+        let data = serialize(UserHandle::new(3u32));
+        let mut handle = std::ptr::null_mut();
+        let data: *mut schema::ffi::ComponentData = data.into();
+        let read = unsafe {
+            component_data_deserialize::<Handler>(
+                Handler::ID,
+                std::ptr::null_mut(),
+                data,
+                &mut handle,
+            )
+        };
+        drop(schema::ComponentData::from(data));
+        assert_eq!(read, 1);
+
+        let copy = unsafe { component_copy(Handler::ID, std::ptr::null_mut(), handle) };
+        unsafe { component_free(Handler::ID, std::ptr::null_mut(), handle) };
+        assert_eq!(unsafe { downcast_handle::<u32>(copy) }, Some(&3));
+        unsafe { component_free(Handler::ID, std::ptr::null_mut(), copy) };
+    }
+}
@@ -1,27 +1,62 @@
 use spatialos_sys::{
     Worker_ConnectAsync, Worker_Connection, Worker_ConnectionFuture_Destroy,
     Worker_ConnectionFuture_Get, Worker_ConnectionParameters, Worker_Connection_Destroy,
-    Worker_Connection_GetOpList, Worker_Connection_SendEntityQueryRequest,
-    Worker_Connection_SendLogMessage, Worker_DefaultConnectionParameters,
+    Worker_Connection_GetConnectionStatusCode, Worker_Connection_GetConnectionStatusDetailString,
+    Worker_Connection_GetOpList, Worker_Connection_GetWorkerAttributes,
+    Worker_Connection_GetWorkerFlag, Worker_Connection_GetWorkerId, Worker_Connection_IsConnected,
+    Worker_Connection_SendAddComponent, Worker_Connection_SendAuthorityLossImminentAcknowledgement,
+    Worker_Connection_SendCommandFailure, Worker_Connection_SendCommandRequest,
+    Worker_Connection_SendCommandResponse, Worker_Connection_SendComponentUpdate,
+    Worker_Connection_SendCreateEntityRequest, Worker_Connection_SendDeleteEntityRequest,
+    Worker_Connection_SendEntityQueryRequest, Worker_Connection_SendLogMessage,
+    Worker_Connection_SendMetrics, Worker_Connection_SendRemoveComponent,
+    Worker_Connection_SendReserveEntityIdsRequest, Worker_DefaultConnectionParameters,
     Worker_ModularKcpNetworkParameters, Worker_NetworkConnectionType, Worker_NetworkParameters,
     Worker_NetworkSecurityType,
 };
 
 use spatialos_sys::{
-    Worker_ComponentVtable, Worker_CompressionParameters, Worker_ConnectionFuture,
-    Worker_EntityQuery, Worker_ErasureCodecParameters, Worker_FlowControlParameters,
-    Worker_HeartbeatParameters, Worker_KcpNetworkParameters, Worker_KcpTransportParameters,
-    Worker_LogMessage, Worker_LogsinkParameters, Worker_ModularTcpNetworkParameters,
-    Worker_ProtocolLoggingParameters, Worker_RakNetNetworkParameters, Worker_TcpNetworkParameters,
-    Worker_ThreadAffinityParameters,
+    Worker_CommandParameters, Worker_CommandRequest, Worker_CommandResponse, Worker_ComponentData,
+    Worker_ComponentUpdate, Worker_ComponentUpdateLoopback, Worker_ComponentVtable,
+    Worker_CompressionParameters, Worker_ConnectionFuture, Worker_EntityQuery,
+    Worker_ErasureCodecParameters, Worker_FlowControlParameters, Worker_HeartbeatParameters,
+    Worker_KcpNetworkParameters, Worker_KcpTransportParameters, Worker_LogsinkParameters,
+    Worker_ModularTcpNetworkParameters, Worker_ProtocolLoggingParameters,
+    Worker_RakNetNetworkParameters, Worker_TcpNetworkParameters, Worker_ThreadAffinityParameters,
+    Worker_UpdateParameters,
 };
 
-use crate::worker::log_message::LogMessage;
+use crate::schema::Component;
+use crate::worker::authority::{
+    AuthorityError, AuthorityLossHandlers, AuthorityTracker, ComponentWriter, UpdateSender,
+};
+use crate::worker::component_registry::ComponentRegistry;
+use crate::worker::component_vtable::release_handle;
+use crate::worker::log_message::{LogMessage, RawLogMessage};
+use crate::worker::metrics::{Metrics, RawMetrics};
 use crate::worker::op::OpList;
+use crate::worker::Authority;
+use crate::worker::CommandRequest;
+use crate::worker::CommandResponse;
+use crate::worker::ComponentData;
+use crate::worker::ComponentId;
+use crate::worker::ComponentUpdate;
+use crate::worker::EntityId;
 use crate::worker::EntityQuery;
 use crate::worker::RequestId;
+use crate::worker::{ConnectionStatusCode, WorkerAttributes};
+use crate::{const_to_string, to_c_string, vector_to_owned_array};
 use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
+use std::ops::Deref;
+use std::os::raw::{c_char, c_void};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tokio")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 
 /// Network connection type used by Worker_NetworkParameters.
 pub enum NetworkConnectionType {
@@ -293,9 +328,8 @@ pub struct ConnectionParameters {
 impl From<Worker_ConnectionParameters> for ConnectionParameters {
     fn from(parameters: Worker_ConnectionParameters) -> Self {
         let worker_type = unsafe { CStr::from_ptr(parameters.worker_type) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             network: NetworkParameters::from(parameters.network),
             send_queue_capacity: parameters.send_queue_capacity,
@@ -348,8 +382,24 @@ impl Default for ConnectionParameters {
     }
 }
 
+impl ConnectionParameters {
+    /// Sets the component vtables to those of every component registered with a handler.
+    pub fn set_component_vtables(&mut self, registry: &ComponentRegistry) {
+        let vtables = registry
+            .vtables()
+            .into_iter()
+            .map(|vtable| vtable.into())
+            .collect::<Vec<Worker_ComponentVtable>>();
+        let (vtables, vtable_count) = vector_to_owned_array(vtables);
+        self.component_vtable_count = vtable_count as u32;
+        self.component_vtables = vtables;
+    }
+}
+
 pub struct ConnectionFuture {
     inner: *mut Worker_ConnectionFuture,
+    #[cfg(feature = "tokio")]
+    delay: Option<Pin<Box<tokio::time::Sleep>>>,
 }
 
 impl Drop for ConnectionFuture {
@@ -386,6 +436,8 @@ impl ConnectionFuture {
                     worker_id.as_ptr() as *const c_char,
                     &params.into() as *const Worker_ConnectionParameters,
                 ),
+                #[cfg(feature = "tokio")]
+                delay: None,
             }
         }
     }
@@ -408,21 +460,173 @@ impl ConnectionFuture {
     }
 }
 
-pub struct Connection {
+#[cfg(feature = "tokio")]
+/// How long to wait between two checks of a pending ConnectionFuture when it is awaited.
+const CONNECTION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[cfg(feature = "tokio")]
+impl Future for ConnectionFuture {
+    type Output = Connection;
+
+    /// Checks whether the connection is available without blocking, and schedules another check
+    /// on the tokio timer otherwise.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Connection> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                match delay.as_mut().poll(cx) {
+                    Poll::Ready(()) => self.delay = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            if let Some(connection) = self.get(Some(0)) {
+                return Poll::Ready(connection);
+            }
+            self.delay = Some(Box::pin(tokio::time::sleep(CONNECTION_POLL_INTERVAL)));
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Enum defining how a component update is sent back to the worker which sent it.
+pub enum UpdateLoopback {
+    /// The update is not sent back to the worker. Its local view is not updated until another
+    /// worker sends an update to the component.
+    None,
+    /// The update is added to the op list of the worker immediately, before SpatialOS has
+    /// received it. This is the default.
+    #[default]
+    ShortCircuited,
+}
+
+impl From<Worker_ComponentUpdateLoopback> for UpdateLoopback {
+    fn from(loopback: Worker_ComponentUpdateLoopback) -> Self {
+        match loopback {
+            Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_NONE => Self::None,
+            Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_SHORT_CIRCUITED => {
+                Self::ShortCircuited
+            }
+        }
+    }
+}
+
+impl From<UpdateLoopback> for Worker_ComponentUpdateLoopback {
+    fn from(loopback: UpdateLoopback) -> Self {
+        match loopback {
+            UpdateLoopback::None => {
+                Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_NONE
+            }
+            UpdateLoopback::ShortCircuited => {
+                Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_SHORT_CIRCUITED
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Parameters for sending a component update.
+pub struct UpdateParameters {
+    /// Controls how the update is sent back to the worker which sent it.
+    pub loopback: UpdateLoopback,
+}
+
+impl From<UpdateParameters> for Worker_UpdateParameters {
+    fn from(parameters: UpdateParameters) -> Self {
+        let loopback: Worker_ComponentUpdateLoopback = parameters.loopback.into();
+        Self {
+            loopback: loopback as u8,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Parameters for sending a command request.
+pub struct CommandParameters {
+    /// Allows the command to be handled by the sending worker without a round trip to SpatialOS
+    /// when it is authoritative over the target component.
+    pub allow_short_circuit: bool,
+}
+
+impl From<CommandParameters> for Worker_CommandParameters {
+    fn from(parameters: CommandParameters) -> Self {
+        Self {
+            allow_short_circuit: parameters.allow_short_circuit as u8,
+        }
+    }
+}
+
+/// Owns the underlying SDK connection, destroying it once every handle to it has been dropped.
+struct RawConnection {
     inner: *mut Worker_Connection,
+    authority: AuthorityTracker,
+}
+
+/// The SDK allows sending from any thread. Op lists are only retrieved through the OpReceiver,
+/// which is unique to a connection.
+unsafe impl Send for RawConnection {}
+unsafe impl Sync for RawConnection {}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        unsafe { Worker_Connection_Destroy(self.inner) }
+    }
+}
+
+/// A connection to SpatialOS. Every send method of ConnectionSender can be called directly on the
+/// connection, which can also be split to send from several threads while processing ops on
+/// another.
+pub struct Connection {
+    sender: ConnectionSender,
+    receiver: OpReceiver,
 }
 
 impl Connection {
-    /// Sends a log message from the worker to SpatialOS.
-    pub fn send_log_message(&mut self, log_message: LogMessage) {
-        unsafe {
-            Worker_Connection_SendLogMessage(
-                self.inner,
-                &log_message.into() as *const Worker_LogMessage,
-            )
+    /// Splits the connection into a sending half, which can be cloned and shared between threads,
+    /// and a receiving half which retrieves op lists. The connection is destroyed once both halves
+    /// and every clone of the sender have been dropped.
+    pub fn split(self) -> (ConnectionSender, OpReceiver) {
+        (self.sender, self.receiver)
+    }
+
+    /// Retrieves the list of operations that have occurred since the last call to this function.
+    ///
+    /// See OpReceiver::get_op_list.
+    pub fn get_op_list(&mut self, timeout_millis: u32) -> OpList {
+        self.receiver.get_op_list(timeout_millis)
+    }
+}
+
+impl Deref for Connection {
+    type Target = ConnectionSender;
+
+    fn deref(&self) -> &ConnectionSender {
+        &self.sender
+    }
+}
+
+impl From<*mut Worker_Connection> for Connection {
+    fn from(connection: *mut Worker_Connection) -> Self {
+        let raw = Arc::new(RawConnection {
+            inner: connection,
+            authority: AuthorityTracker::new(),
+        });
+        Self {
+            sender: ConnectionSender { raw: raw.clone() },
+            receiver: OpReceiver {
+                raw,
+                authority_loss_handlers: Mutex::new(AuthorityLossHandlers::new()),
+            },
         }
     }
+}
+
+/// The receiving half of a connection, which retrieves the ops sent by SpatialOS.
+pub struct OpReceiver {
+    raw: Arc<RawConnection>,
+    /// Only accessed through a unique reference, the mutex keeps the receiver Sync.
+    authority_loss_handlers: Mutex<AuthorityLossHandlers>,
+}
 
+impl OpReceiver {
     /// Retrieves the list of operations that have occurred since the last call to this function.
     ///
     /// If timeout_millis is non-zero, the function will block until there is at least one operation to
@@ -431,6 +635,9 @@ impl Connection {
     ///
     /// If timeout_millis is zero the function is non-blocking.
     ///
+    /// The authority changes of the list are recorded, then the handlers registered with
+    /// on_authority_loss_imminent are run, before the list is returned.
+    ///
     /// It is the caller's responsibility to destroy the returned Worker_OpList with the
     /// Worker_OpList_Destroy function.
     ///
@@ -439,14 +646,176 @@ impl Connection {
     /// Worker_Connection_SendComponentUpdate, without copying the data first. Otherwise, a double free
     /// could occur.
     pub fn get_op_list(&mut self, timeout_millis: u32) -> OpList {
-        OpList::from(unsafe { Worker_Connection_GetOpList(self.inner, timeout_millis) })
+        let op_list =
+            OpList::from(unsafe { Worker_Connection_GetOpList(self.raw.inner, timeout_millis) });
+        self.raw.authority.process_ops(&op_list);
+        let sender = ConnectionSender {
+            raw: self.raw.clone(),
+        };
+        self.authority_loss_handlers
+            .get_mut()
+            .unwrap()
+            .process_ops(&op_list, &sender);
+        op_list
+    }
+
+    /// Registers a handler run by get_op_list whenever the authority loss of the component
+    /// becomes imminent. See AuthorityLossHandlers::on_authority_loss_imminent.
+    pub fn on_authority_loss_imminent<F>(&mut self, component_id: ComponentId, callback: F)
+    where
+        F: FnMut(EntityId, &dyn UpdateSender) + Send + 'static,
+    {
+        self.authority_loss_handlers
+            .get_mut()
+            .unwrap()
+            .on_authority_loss_imminent(component_id, callback);
+    }
+}
+
+/// The sending half of a connection. It can be cloned and used from any number of threads.
+#[derive(Clone)]
+pub struct ConnectionSender {
+    raw: Arc<RawConnection>,
+}
+
+impl ConnectionSender {
+    /// Returns true if the connection has been successfully created and communication is ongoing.
+    pub fn is_connected(&self) -> bool {
+        unsafe { Worker_Connection_IsConnected(self.raw.inner) != 0 }
+    }
+
+    /// Returns the status code of the connection along with a human-readable description of it.
+    /// A code other than ConnectionStatusCode::Success means the connection is broken, and the
+    /// detail string explains the reason.
+    pub fn status(&self) -> (ConnectionStatusCode, String) {
+        let code = unsafe { Worker_Connection_GetConnectionStatusCode(self.raw.inner) };
+        let detail = const_to_string(unsafe {
+            Worker_Connection_GetConnectionStatusDetailString(self.raw.inner)
+        });
+        (ConnectionStatusCode::from(code), detail)
+    }
+
+    /// Returns the ID that was assigned to this worker at runtime.
+    pub fn worker_id(&self) -> String {
+        const_to_string(unsafe { Worker_Connection_GetWorkerId(self.raw.inner) })
+    }
+
+    /// Returns the attributes associated with this worker at runtime.
+    pub fn attributes(&self) -> WorkerAttributes {
+        WorkerAttributes::from(unsafe { *Worker_Connection_GetWorkerAttributes(self.raw.inner) })
+    }
+
+    /// Returns the value of the worker flag with the given name, or None if no such flag exists.
+    /// Names containing NUL bytes cannot name a flag, so None is returned for them.
+    pub fn flag<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        unsafe extern "C" fn callback(user_data: *mut c_void, value: *const c_char) {
+            if !value.is_null() {
+                *(user_data as *mut Option<String>) = Some(const_to_string(value));
+            }
+        }
+        let name = CString::new(name.as_ref()).ok()?;
+        let mut value: Option<String> = None;
+        unsafe {
+            Worker_Connection_GetWorkerFlag(
+                self.raw.inner,
+                name.as_ptr(),
+                &mut value as *mut Option<String> as *mut c_void,
+                Some(callback),
+            )
+        }
+        value
+    }
+
+    /// Sends a log message from the worker to SpatialOS.
+    pub fn send_log_message(&self, log_message: LogMessage) {
+        let log_message = RawLogMessage::new(log_message);
+        unsafe { Worker_Connection_SendLogMessage(self.raw.inner, &log_message.as_raw()) }
+    }
+
+    /// Sends metrics data for the worker to SpatialOS.
+    pub fn send_metrics(&self, metrics: Metrics) {
+        let metrics = RawMetrics::new(&metrics);
+        unsafe { Worker_Connection_SendMetrics(self.raw.inner, &metrics.as_raw()) }
+    }
+
+    /// Requests SpatialOS to reserve multiple entity IDs.
+    pub fn send_reserve_entity_ids_request(
+        &self,
+        number_of_entity_ids: u32,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        unsafe {
+            Worker_Connection_SendReserveEntityIdsRequest(
+                self.raw.inner,
+                number_of_entity_ids,
+                timeout_millis
+                    .as_ref()
+                    .map_or(std::ptr::null(), |t| t as *const u32),
+            )
+        }
+    }
+
+    /// Requests SpatialOS to create an entity with the given components. If entity_id is None, a
+    /// new entity ID is allocated, otherwise it must be an ID previously reserved with a reserve
+    /// entity IDs request.
+    ///
+    /// The component data is owned by the SDK once sent.
+    pub fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let components = components
+            .into_iter()
+            .map(|c| c.into())
+            .collect::<Vec<Worker_ComponentData>>();
+        let handles = components
+            .iter()
+            .map(|component| component.user_handle)
+            .collect::<Vec<_>>();
+        let (components, component_count) = vector_to_owned_array(components);
+        let request_id = unsafe {
+            Worker_Connection_SendCreateEntityRequest(
+                self.raw.inner,
+                component_count as u32,
+                components,
+                entity_id
+                    .as_ref()
+                    .map_or(std::ptr::null(), |e| e as *const EntityId),
+                timeout_millis
+                    .as_ref()
+                    .map_or(std::ptr::null(), |t| t as *const u32),
+            )
+        };
+        for handle in handles {
+            unsafe { release_handle(handle) };
+        }
+        request_id
+    }
+
+    /// Requests SpatialOS to delete an entity.
+    pub fn send_delete_entity_request(
+        &self,
+        entity_id: EntityId,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        unsafe {
+            Worker_Connection_SendDeleteEntityRequest(
+                self.raw.inner,
+                entity_id,
+                timeout_millis
+                    .as_ref()
+                    .map_or(std::ptr::null(), |t| t as *const u32),
+            )
+        }
     }
 
     /// Queries SpatialOS for entity data.
     ///
     /// Returns RequestId -1 if the query constraint or result type are not valid.
     pub fn send_entity_query_request(
-        &mut self,
+        &self,
         entity_query: EntityQuery,
         timeout_millis: Option<u32>,
     ) -> RequestId {
@@ -454,7 +823,7 @@ impl Connection {
         if let Some(timeout_millis) = timeout_millis {
             unsafe {
                 Worker_Connection_SendEntityQueryRequest(
-                    self.inner,
+                    self.raw.inner,
                     &query as *const Worker_EntityQuery,
                     &timeout_millis as *const u32,
                 )
@@ -462,23 +831,621 @@ impl Connection {
         } else {
             unsafe {
                 Worker_Connection_SendEntityQueryRequest(
-                    self.inner,
+                    self.raw.inner,
                     &query as *const Worker_EntityQuery,
                     std::ptr::null(),
                 )
             }
         }
     }
+
+    /// Returns the authority of the worker over the component, as reported by the AuthorityChange
+    /// ops received so far.
+    pub fn authority(&self, entity_id: EntityId, component_id: ComponentId) -> Authority {
+        self.raw.authority.authority(entity_id, component_id)
+    }
+
+    /// Returns true if the worker can send updates to the component.
+    pub fn is_authoritative(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.raw.authority.is_authoritative(entity_id, component_id)
+    }
+
+    /// Returns the number of component updates sent for components the worker was not
+    /// authoritative over, which SpatialOS drops.
+    pub fn unauthoritative_updates(&self) -> u64 {
+        self.raw.authority.unauthoritative_updates()
+    }
+
+    /// Returns a writer sending updates to the component of the given entity, or an error if the
+    /// worker is not authoritative over it.
+    pub fn write<C: Component>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<ComponentWriter<'_, C>, AuthorityError> {
+        ComponentWriter::new(self, entity_id)
+    }
+
+    /// Sends a component update for the given entity to SpatialOS.
+    ///
+    /// The update data is owned by the SDK once sent. Returns false if the update could not be
+    /// sent. Updates to components the worker is not authoritative over are dropped by SpatialOS,
+    /// and are counted and logged as warnings.
+    pub fn send_component_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.component_update(entity_id, update, None)
+    }
+
+    /// Sends a component update for the given entity to SpatialOS, controlling how the update is
+    /// sent back to this worker.
+    pub fn send_component_update_with_parameters(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.component_update(entity_id, update, Some(parameters.into()))
+    }
+
+    fn component_update(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: Option<Worker_UpdateParameters>,
+    ) -> bool {
+        self.raw
+            .authority
+            .check_update(entity_id, update.component_id);
+        let mut update: Worker_ComponentUpdate = update.into();
+        let sent = unsafe {
+            Worker_Connection_SendComponentUpdate(
+                self.raw.inner,
+                entity_id,
+                &mut update as *mut Worker_ComponentUpdate,
+                parameters
+                    .as_ref()
+                    .map_or(std::ptr::null(), |p| p as *const Worker_UpdateParameters),
+            ) != 0
+        };
+        unsafe { release_handle(update.user_handle) };
+        sent
+    }
+
+    /// Adds a new component to the given entity in SpatialOS.
+    ///
+    /// The component data is owned by the SDK once sent. Returns false if the component could not
+    /// be sent.
+    pub fn send_add_component(&self, entity_id: EntityId, component_data: ComponentData) -> bool {
+        self.add_component(entity_id, component_data, None)
+    }
+
+    /// Adds a new component to the given entity in SpatialOS, controlling how the added component
+    /// is sent back to this worker.
+    pub fn send_add_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.add_component(entity_id, component_data, Some(parameters.into()))
+    }
+
+    fn add_component(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: Option<Worker_UpdateParameters>,
+    ) -> bool {
+        let mut component_data: Worker_ComponentData = component_data.into();
+        let sent = unsafe {
+            Worker_Connection_SendAddComponent(
+                self.raw.inner,
+                entity_id,
+                &mut component_data as *mut Worker_ComponentData,
+                parameters
+                    .as_ref()
+                    .map_or(std::ptr::null(), |p| p as *const Worker_UpdateParameters),
+            ) != 0
+        };
+        unsafe { release_handle(component_data.user_handle) };
+        sent
+    }
+
+    /// Removes a component from the given entity in SpatialOS. Returns false if the removal could
+    /// not be sent.
+    pub fn send_remove_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.remove_component(entity_id, component_id, None)
+    }
+
+    /// Removes a component from the given entity in SpatialOS, controlling how the removal is sent
+    /// back to this worker.
+    pub fn send_remove_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.remove_component(entity_id, component_id, Some(parameters.into()))
+    }
+
+    fn remove_component(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: Option<Worker_UpdateParameters>,
+    ) -> bool {
+        unsafe {
+            Worker_Connection_SendRemoveComponent(
+                self.raw.inner,
+                entity_id,
+                component_id,
+                parameters
+                    .as_ref()
+                    .map_or(std::ptr::null(), |p| p as *const Worker_UpdateParameters),
+            ) != 0
+        }
+    }
+
+    /// Sends a command request targeting the given entity and command to SpatialOS. If
+    /// timeout_millis is None, the default timeout from the network parameters is used.
+    ///
+    /// The request data is owned by the SDK once sent.
+    pub fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.command_request(entity_id, request, timeout_millis, None)
+    }
+
+    /// Sends a command request targeting the given entity and command to SpatialOS, allowing it
+    /// to be short-circuited if the parameters allow it.
+    pub fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId {
+        self.command_request(entity_id, request, timeout_millis, Some(parameters.into()))
+    }
+
+    fn command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: Option<Worker_CommandParameters>,
+    ) -> RequestId {
+        let mut request: Worker_CommandRequest = request.into();
+        let request_id = unsafe {
+            Worker_Connection_SendCommandRequest(
+                self.raw.inner,
+                entity_id,
+                &mut request as *mut Worker_CommandRequest,
+                timeout_millis
+                    .as_ref()
+                    .map_or(std::ptr::null(), |t| t as *const u32),
+                parameters
+                    .as_ref()
+                    .map_or(std::ptr::null(), |p| p as *const Worker_CommandParameters),
+            )
+        };
+        unsafe { release_handle(request.user_handle) };
+        request_id
+    }
+
+    /// Sends a response to the incoming command request with the given ID.
+    ///
+    /// The response data is owned by the SDK once sent. Returns false if the response could not be
+    /// sent.
+    pub fn send_command_response(&self, request_id: RequestId, response: CommandResponse) -> bool {
+        let mut response: Worker_CommandResponse = response.into();
+        let sent = unsafe {
+            Worker_Connection_SendCommandResponse(
+                self.raw.inner,
+                request_id,
+                &mut response as *mut Worker_CommandResponse,
+            ) != 0
+        };
+        unsafe { release_handle(response.user_handle) };
+        sent
+    }
+
+    /// Marks the incoming command request with the given ID as failed. Returns false if the
+    /// failure could not be sent. NUL bytes are removed from the message.
+    pub fn send_command_failure<S: AsRef<str>>(&self, request_id: RequestId, message: S) -> bool {
+        let message = to_c_string(message.as_ref());
+        unsafe {
+            Worker_Connection_SendCommandFailure(self.raw.inner, request_id, message.as_ptr()) != 0
+        }
+    }
+
+    /// Acknowledges that the worker is ready to lose authority over the component, once it has
+    /// sent its final updates. Returns false without sending anything if the authority loss of
+    /// the component is not imminent.
+    pub fn acknowledge_authority_loss(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> bool {
+        if self.authority(entity_id, component_id) != Authority::AuthorityLossImminent {
+            return false;
+        }
+        self.send_authority_loss_imminent_acknowledgement(entity_id, component_id);
+        true
+    }
+
+    /// Sends an acknowledgement of the receipt of an AuthorityLossImminent authority change for a
+    /// component. Sending the acknowledgement signifies that this worker is ready to lose authority
+    /// over the component.
+    pub fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        unsafe {
+            Worker_Connection_SendAuthorityLossImminentAcknowledgement(
+                self.raw.inner,
+                entity_id,
+                component_id,
+            )
+        }
+    }
 }
 
-impl From<*mut Worker_Connection> for Connection {
-    fn from(connection: *mut Worker_Connection) -> Self {
-        Self { inner: connection }
+impl UpdateSender for ConnectionSender {
+    fn authority_tracker(&self) -> &AuthorityTracker {
+        &self.raw.authority
+    }
+
+    fn send_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.send_component_update(entity_id, update)
+    }
+
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        ConnectionSender::send_authority_loss_imminent_acknowledgement(
+            self,
+            entity_id,
+            component_id,
+        )
     }
 }
 
-impl Drop for Connection {
-    fn drop(&mut self) {
-        unsafe { Worker_Connection_Destroy(self.inner) }
+/// The operations of a worker connection, implemented by Connection and by
+/// mock::MockConnection, so that worker logic can be written once and tested without a runtime.
+pub trait WorkerConnection {
+    /// Retrieves the ops received since the previous call. Their authority changes are recorded
+    /// in the authority tracker of the connection, then its authority loss handlers are run,
+    /// before the list is returned.
+    fn get_op_list(&mut self, timeout_millis: u32) -> OpList;
+    /// Returns the authority of the worker over each component, as recorded by get_op_list. Send
+    /// methods count the updates sent without authority in it.
+    fn authority_tracker(&self) -> &AuthorityTracker;
+    /// Returns the handlers run by get_op_list when the authority loss of a component becomes
+    /// imminent.
+    fn authority_loss_handlers(&mut self) -> &mut AuthorityLossHandlers;
+    fn is_connected(&self) -> bool;
+    fn worker_id(&self) -> String;
+    fn attributes(&self) -> WorkerAttributes;
+    fn flag(&self, name: &str) -> Option<String>;
+    fn send_log_message(&self, log_message: LogMessage);
+    fn send_metrics(&self, metrics: Metrics);
+    fn send_reserve_entity_ids_request(
+        &self,
+        number_of_entity_ids: u32,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_delete_entity_request(
+        &self,
+        entity_id: EntityId,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_component_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool;
+    fn send_component_update_with_parameters(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: UpdateParameters,
+    ) -> bool;
+    fn send_add_component(&self, entity_id: EntityId, component_data: ComponentData) -> bool;
+    fn send_add_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: UpdateParameters,
+    ) -> bool;
+    fn send_remove_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool;
+    fn send_remove_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: UpdateParameters,
+    ) -> bool;
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId;
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId;
+    fn send_command_response(&self, request_id: RequestId, response: CommandResponse) -> bool;
+    fn send_command_failure(&self, request_id: RequestId, message: &str) -> bool;
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    );
+
+    /// Returns the authority of the worker over the component, as reported by the AuthorityChange
+    /// ops received so far.
+    fn authority(&self, entity_id: EntityId, component_id: ComponentId) -> Authority {
+        self.authority_tracker().authority(entity_id, component_id)
+    }
+
+    /// Returns true if the worker can send updates to the component.
+    fn is_authoritative(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.authority_tracker()
+            .is_authoritative(entity_id, component_id)
+    }
+
+    /// Returns the number of component updates sent for components the worker was not
+    /// authoritative over.
+    fn unauthoritative_updates(&self) -> u64 {
+        self.authority_tracker().unauthoritative_updates()
+    }
+
+    /// Returns a writer sending updates to the component of the given entity, or an error if the
+    /// worker is not authoritative over it.
+    fn write<C: Component>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<ComponentWriter<'_, C, Self>, AuthorityError> {
+        ComponentWriter::new(self, entity_id)
+    }
+
+    /// Registers a handler run by get_op_list whenever the authority loss of the component
+    /// becomes imminent. See AuthorityLossHandlers::on_authority_loss_imminent.
+    fn on_authority_loss_imminent<F>(&mut self, component_id: ComponentId, callback: F)
+    where
+        Self: Sized,
+        F: FnMut(EntityId, &dyn UpdateSender) + Send + 'static,
+    {
+        self.authority_loss_handlers()
+            .on_authority_loss_imminent(component_id, callback);
+    }
+
+    /// Acknowledges that the worker is ready to lose authority over the component. Returns false
+    /// without sending anything if the authority loss of the component is not imminent.
+    fn acknowledge_authority_loss(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        if self.authority(entity_id, component_id) != Authority::AuthorityLossImminent {
+            return false;
+        }
+        self.send_authority_loss_imminent_acknowledgement(entity_id, component_id);
+        true
+    }
+}
+
+impl WorkerConnection for Connection {
+    fn get_op_list(&mut self, timeout_millis: u32) -> OpList {
+        self.receiver.get_op_list(timeout_millis)
+    }
+
+    fn authority_tracker(&self) -> &AuthorityTracker {
+        &self.sender.raw.authority
+    }
+
+    fn authority_loss_handlers(&mut self) -> &mut AuthorityLossHandlers {
+        self.receiver.authority_loss_handlers.get_mut().unwrap()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.sender.is_connected()
+    }
+
+    fn worker_id(&self) -> String {
+        self.sender.worker_id()
+    }
+
+    fn attributes(&self) -> WorkerAttributes {
+        self.sender.attributes()
+    }
+
+    fn flag(&self, name: &str) -> Option<String> {
+        self.sender.flag(name)
+    }
+
+    fn send_log_message(&self, log_message: LogMessage) {
+        self.sender.send_log_message(log_message)
+    }
+
+    fn send_metrics(&self, metrics: Metrics) {
+        self.sender.send_metrics(metrics)
+    }
+
+    fn send_reserve_entity_ids_request(
+        &self,
+        number_of_entity_ids: u32,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.sender
+            .send_reserve_entity_ids_request(number_of_entity_ids, timeout_millis)
+    }
+
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.sender
+            .send_create_entity_request(components, entity_id, timeout_millis)
+    }
+
+    fn send_delete_entity_request(
+        &self,
+        entity_id: EntityId,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.sender
+            .send_delete_entity_request(entity_id, timeout_millis)
+    }
+
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.sender
+            .send_entity_query_request(entity_query, timeout_millis)
+    }
+
+    fn send_component_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.sender.send_component_update(entity_id, update)
+    }
+
+    fn send_component_update_with_parameters(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.sender
+            .send_component_update_with_parameters(entity_id, update, parameters)
+    }
+
+    fn send_add_component(&self, entity_id: EntityId, component_data: ComponentData) -> bool {
+        self.sender.send_add_component(entity_id, component_data)
+    }
+
+    fn send_add_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.sender
+            .send_add_component_with_parameters(entity_id, component_data, parameters)
+    }
+
+    fn send_remove_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.sender.send_remove_component(entity_id, component_id)
+    }
+
+    fn send_remove_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.sender
+            .send_remove_component_with_parameters(entity_id, component_id, parameters)
+    }
+
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.sender
+            .send_command_request(entity_id, request, timeout_millis)
+    }
+
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId {
+        self.sender.send_command_request_with_parameters(
+            entity_id,
+            request,
+            timeout_millis,
+            parameters,
+        )
+    }
+
+    fn send_command_response(&self, request_id: RequestId, response: CommandResponse) -> bool {
+        self.sender.send_command_response(request_id, response)
+    }
+
+    fn send_command_failure(&self, request_id: RequestId, message: &str) -> bool {
+        self.sender.send_command_failure(request_id, message)
+    }
+
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        self.sender
+            .send_authority_loss_imminent_acknowledgement(entity_id, component_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_loopback_round_trips_through_the_sdk_enum() {
+        for &loopback in &[UpdateLoopback::None, UpdateLoopback::ShortCircuited] {
+            let raw: Worker_ComponentUpdateLoopback = loopback.into();
+            assert_eq!(UpdateLoopback::from(raw), loopback);
+        }
+        assert_eq!(
+            Worker_ComponentUpdateLoopback::from(UpdateLoopback::None) as u8,
+            Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_NONE as u8
+        );
+    }
+
+    #[test]
+    fn update_parameters_convert_to_the_sdk_loopback() {
+        let raw: Worker_UpdateParameters = UpdateParameters::default().into();
+        assert_eq!(
+            raw.loopback,
+            Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_SHORT_CIRCUITED as u8
+        );
+        let raw: Worker_UpdateParameters = UpdateParameters {
+            loopback: UpdateLoopback::None,
+        }
+        .into();
+        assert_eq!(
+            raw.loopback,
+            Worker_ComponentUpdateLoopback::WORKER_COMPONENT_UPDATE_LOOPBACK_NONE as u8
+        );
+    }
+
+    #[test]
+    fn command_parameters_convert_to_the_sdk_flag() {
+        let raw: Worker_CommandParameters = CommandParameters::default().into();
+        assert_eq!(raw.allow_short_circuit, 0);
+        let raw: Worker_CommandParameters = CommandParameters {
+            allow_short_circuit: true,
+        }
+        .into();
+        assert_eq!(raw.allow_short_circuit, 1);
     }
 }
@@ -0,0 +1,176 @@
+//! Callbacks invoked with the component data and updates received by a worker, decoded into the
+//! Rust types of their components.
+//!
+//! The Dispatcher decodes ops through a ComponentRegistry, so a single pass over an op list can
+//! serve callbacks of many component types, which are looked up by component ID at runtime.
+
+use crate::schema::Component;
+use crate::worker::component_registry::ComponentRegistry;
+use crate::worker::op::{OpList, WorkerOp};
+use crate::worker::{ComponentId, EntityId};
+use std::any::Any;
+use std::collections::HashMap;
+
+type ComponentCallback = Box<dyn FnMut(EntityId, &dyn Any) + Send>;
+
+#[derive(Default)]
+/// The callbacks invoked with the decoded AddComponent and ComponentUpdate ops of each component.
+pub struct Dispatcher {
+    registry: ComponentRegistry,
+    add_callbacks: HashMap<ComponentId, Vec<ComponentCallback>>,
+    update_callbacks: HashMap<ComponentId, Vec<ComponentCallback>>,
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher decoding ops with the given registry. Components registered with a
+    /// callback are added to the registry if it does not hold them yet.
+    pub fn new(registry: ComponentRegistry) -> Self {
+        Self {
+            registry,
+            ..Self::default()
+        }
+    }
+
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    /// Registers a callback invoked with the data of the component whenever it is added to an
+    /// entity.
+    pub fn on_add_component<C, F>(&mut self, mut callback: F)
+    where
+        C: Component + 'static,
+        C::Update: 'static,
+        F: FnMut(EntityId, &C) + Send + 'static,
+    {
+        self.register::<C>();
+        self.add_callbacks
+            .entry(C::ID)
+            .or_default()
+            .push(Box::new(move |entity_id, data| {
+                if let Some(data) = data.downcast_ref::<C>() {
+                    callback(entity_id, data);
+                }
+            }));
+    }
+
+    /// Registers a callback invoked with each update received for the component.
+    pub fn on_component_update<C, F>(&mut self, mut callback: F)
+    where
+        C: Component + 'static,
+        C::Update: 'static,
+        F: FnMut(EntityId, &C::Update) + Send + 'static,
+    {
+        self.register::<C>();
+        self.update_callbacks
+            .entry(C::ID)
+            .or_default()
+            .push(Box::new(move |entity_id, update| {
+                if let Some(update) = update.downcast_ref::<C::Update>() {
+                    callback(entity_id, update);
+                }
+            }));
+    }
+
+    /// Invokes the callbacks of the AddComponent and ComponentUpdate ops of the list, in the
+    /// order of the ops. Ops whose data is only available through a user handle are skipped.
+    pub fn process_ops(&mut self, op_list: &OpList) {
+        for op in op_list {
+            match op {
+                WorkerOp::AddComponent(op) => {
+                    if let Some(callbacks) = self.add_callbacks.get_mut(&op.data.component_id()) {
+                        if let Some(data) = self.registry.read_op_data(&op.data) {
+                            for callback in callbacks {
+                                callback(op.entity_id, data.as_ref());
+                            }
+                        }
+                    }
+                }
+                WorkerOp::ComponentUpdate(op) => {
+                    if let Some(callbacks) =
+                        self.update_callbacks.get_mut(&op.update.component_id())
+                    {
+                        if let Some(update) = self.registry.read_op_update(&op.update) {
+                            for callback in callbacks {
+                                callback(op.entity_id, update.as_ref());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn register<C>(&mut self)
+    where
+        C: Component + 'static,
+        C::Update: 'static,
+    {
+        if !self.registry.contains(C::ID) {
+            self.registry.register::<C>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Metadata, MetadataUpdate, Position};
+    use crate::schema::Update;
+    use crate::worker::connection::WorkerConnection;
+    use crate::worker::mock::MockConnection;
+    use crate::worker::ComponentUpdate;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn decoded_ops_reach_the_callbacks_of_their_component() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher = Dispatcher::new(ComponentRegistry::new());
+        let added = received.clone();
+        dispatcher.on_add_component(move |entity_id, metadata: &Metadata| {
+            added
+                .lock()
+                .unwrap()
+                .push(format!("add {} {}", entity_id, metadata.entity_type));
+        });
+        let updated = received.clone();
+        dispatcher.on_component_update::<Metadata, _>(move |entity_id, update| {
+            updated
+                .lock()
+                .unwrap()
+                .push(format!("update {} {:?}", entity_id, update.entity_type));
+        });
+        assert!(dispatcher.registry().contains(Metadata::ID));
+        assert!(!dispatcher.registry().contains(Position::ID));
+
+        let mut connection = MockConnection::new("worker");
+        connection.add_entity(1);
+        connection.add_component(
+            1,
+            Metadata {
+                entity_type: "tree".to_owned(),
+            }
+            .to_component_data(),
+        );
+        connection.component_update(
+            1,
+            ComponentUpdate::new(
+                Metadata::ID,
+                MetadataUpdate {
+                    entity_type: Some("rock".to_owned()),
+                }
+                .to_update(),
+            ),
+        );
+        dispatcher.process_ops(&connection.get_op_list(0));
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                "add 1 tree".to_owned(),
+                "update 1 Some(\"rock\")".to_owned()
+            ]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! An adapter mirroring the ops received by a worker into a lightweight ECS, such as hecs.
+//!
+//! The ECS is abstracted by the EcsWorld trait, which the hecs feature implements for
+//! hecs::World. Each SpatialOS entity is spawned with a
+//! SpatialEntityId component, registered components are inserted, updated and removed as ops are
+//! processed, and Authoritative marks those the worker is authoritative over. Systems mark the
+//! components they change with Dirty, and gather_updates turns them into component updates.
+//!
+//! The routing of component and authority ops to the hooks of their component is done by
+//! OpRouter, which the Bevy plugin shares, along with SpatialEntityId and Authoritative.
+
+use crate::schema::{self, Component};
+use crate::worker::authority::UpdateSender;
+use crate::worker::component_registry::ComponentRegistry;
+use crate::worker::connection::WorkerConnection;
+use crate::worker::op::{OpList, WorkerOp};
+use crate::worker::{self, Authority, ComponentId, EntityId};
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// The operations the adapter needs from an ECS world. For hecs, these map to World::spawn,
+/// World::despawn, World::insert_one, World::remove_one and World::query_one_mut.
+pub trait EcsWorld {
+    type Entity: Copy;
+
+    fn spawn(&mut self) -> Self::Entity;
+
+    fn despawn(&mut self, entity: Self::Entity);
+
+    fn insert<T: Send + Sync + 'static>(&mut self, entity: Self::Entity, component: T);
+
+    fn remove<T: Send + Sync + 'static>(&mut self, entity: Self::Entity);
+
+    fn get_mut<T: Send + Sync + 'static>(&mut self, entity: Self::Entity) -> Option<&mut T>;
+}
+
+/// Operations on entities which no longer exist are ignored, as the adapter only removes the
+/// components of the entities it spawned.
+#[cfg(feature = "hecs")]
+impl EcsWorld for hecs::World {
+    type Entity = hecs::Entity;
+
+    fn spawn(&mut self) -> hecs::Entity {
+        hecs::World::spawn(self, ())
+    }
+
+    fn despawn(&mut self, entity: hecs::Entity) {
+        let _ = hecs::World::despawn(self, entity);
+    }
+
+    fn insert<T: Send + Sync + 'static>(&mut self, entity: hecs::Entity, component: T) {
+        let _ = self.insert_one(entity, component);
+    }
+
+    fn remove<T: Send + Sync + 'static>(&mut self, entity: hecs::Entity) {
+        let _ = self.remove_one::<T>(entity);
+    }
+
+    fn get_mut<T: Send + Sync + 'static>(&mut self, entity: hecs::Entity) -> Option<&mut T> {
+        self.query_one_mut::<&mut T>(entity).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::component::Component))]
+/// The ID of the SpatialOS entity an ECS entity was spawned for.
+pub struct SpatialEntityId(pub EntityId);
+
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::component::Component))]
+/// Marks a component the worker is authoritative over.
+pub struct Authoritative<C> {
+    _component: PhantomData<fn() -> C>,
+}
+
+/// Marks a component changed by the worker, to be sent by the next call to gather_updates or
+/// send_updates.
+pub struct Dirty<C> {
+    _component: PhantomData<fn() -> C>,
+}
+
+/// Mirrors the op stream of a connection into an ECS world.
+pub struct EcsAdapter<W: EcsWorld> {
+    entities: HashMap<EntityId, W::Entity>,
+    router: OpRouter<W, W::Entity>,
+    dirty: HashMap<ComponentId, DirtyHooks<W>>,
+}
+
+/// The operations applying the ops of a component to a world of type W, whose entities are of
+/// type E, generated for the Rust type of the component by each ECS integration. Components and
+/// updates are passed as decoded by the ComponentRegistry of the OpRouter.
+pub(crate) struct ComponentHooks<W, E> {
+    pub add: fn(&mut W, E, Box<dyn Any>),
+    pub remove: fn(&mut W, E),
+    pub update: fn(&mut W, E, Box<dyn Any>),
+    pub authority: fn(&mut W, E, &Authority),
+}
+
+/// Routes the component and authority ops of a list to the hooks of their component, decoding
+/// them through a ComponentRegistry.
+pub(crate) struct OpRouter<W, E> {
+    registry: ComponentRegistry,
+    hooks: HashMap<ComponentId, ComponentHooks<W, E>>,
+}
+
+/// The operations sending the components marked Dirty, generated for their Rust type.
+struct DirtyHooks<W: EcsWorld> {
+    gather: fn(&mut W, W::Entity) -> Option<schema::ComponentUpdate>,
+    send: fn(&mut W, W::Entity, EntityId, &dyn UpdateSender),
+}
+
+impl<C> Default for Authoritative<C> {
+    fn default() -> Self {
+        Self {
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<C> Default for Dirty<C> {
+    fn default() -> Self {
+        Self {
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<W, E> Clone for ComponentHooks<W, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<W, E> Copy for ComponentHooks<W, E> {}
+
+impl<W, E> Clone for OpRouter<W, E> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            hooks: self.hooks.clone(),
+        }
+    }
+}
+
+impl<W, E: Copy> OpRouter<W, E> {
+    pub fn new() -> Self {
+        Self {
+            registry: ComponentRegistry::new(),
+            hooks: HashMap::new(),
+        }
+    }
+
+    /// Registers the hooks of a component, replacing any hooks registered with the same ID.
+    pub fn register<C>(&mut self, hooks: ComponentHooks<W, E>)
+    where
+        C: Component + 'static,
+        C::Update: 'static,
+    {
+        self.registry.register::<C>();
+        self.hooks.insert(C::ID, hooks);
+    }
+
+    /// Applies an AddComponent, RemoveComponent, ComponentUpdate or AuthorityChange op to the
+    /// entity mirroring its SpatialOS entity, as returned by the given function. Other ops, ops of
+    /// unregistered components and ops whose data is only available through a user handle are
+    /// ignored.
+    pub fn route<F>(&self, world: &mut W, op: &WorkerOp, entity: F)
+    where
+        F: FnOnce(&W, EntityId) -> Option<E>,
+    {
+        let (entity_id, component_id) = match op {
+            WorkerOp::AddComponent(op) => (op.entity_id, op.data.component_id()),
+            WorkerOp::RemoveComponent(op) => (op.entity_id, op.component_id),
+            WorkerOp::ComponentUpdate(op) => (op.entity_id, op.update.component_id()),
+            WorkerOp::AuthorityChange(op) => (op.entity_id, op.component_id),
+            _ => return,
+        };
+        let hooks = match self.hooks.get(&component_id) {
+            Some(hooks) => hooks,
+            None => return,
+        };
+        let entity = match entity(world, entity_id) {
+            Some(entity) => entity,
+            None => return,
+        };
+        match op {
+            WorkerOp::AddComponent(op) => {
+                if let Some(component) = self.registry.read_op_data(&op.data) {
+                    (hooks.add)(world, entity, component);
+                }
+            }
+            WorkerOp::RemoveComponent(_) => (hooks.remove)(world, entity),
+            WorkerOp::ComponentUpdate(op) => {
+                if let Some(update) = self.registry.read_op_update(&op.update) {
+                    (hooks.update)(world, entity, update);
+                }
+            }
+            WorkerOp::AuthorityChange(op) => (hooks.authority)(world, entity, &op.authority),
+            _ => {}
+        }
+    }
+}
+
+impl<W: EcsWorld> EcsAdapter<W> {
+    pub fn new() -> Self {
+        Self {
+            entities: HashMap::new(),
+            router: OpRouter::new(),
+            dirty: HashMap::new(),
+        }
+    }
+
+    /// Registers a component to mirror into the world. Updates to the component are gathered as
+    /// the full state of the component.
+    pub fn register<C>(&mut self)
+    where
+        C: Component + Send + Sync + 'static,
+        C::Update: 'static,
+    {
+        self.router.register::<C>(ComponentHooks {
+            add: add_component::<W, C>,
+            remove: remove_component::<W, C>,
+            update: update_component::<W, C>,
+            authority: change_authority::<W, C>,
+        });
+        self.dirty.insert(
+            C::ID,
+            DirtyHooks {
+                gather: gather_update::<W, C>,
+                send: send_update::<W, C>,
+            },
+        );
+    }
+
+    /// Returns the ECS entity spawned for the given SpatialOS entity.
+    pub fn entity(&self, entity_id: EntityId) -> Option<W::Entity> {
+        self.entities.get(&entity_id).copied()
+    }
+
+    /// Applies the entity, component and authority ops of the list to the world. Other ops are
+    /// left to the caller.
+    pub fn process_ops(&mut self, world: &mut W, op_list: &OpList) {
+        for op in op_list {
+            match op {
+                WorkerOp::AddEntity(op) => {
+                    let entity = world.spawn();
+                    world.insert(entity, SpatialEntityId(op.entity_id));
+                    self.entities.insert(op.entity_id, entity);
+                }
+                WorkerOp::RemoveEntity(op) => {
+                    if let Some(entity) = self.entities.remove(&op.entity_id) {
+                        world.despawn(entity);
+                    }
+                }
+                op => {
+                    let entities = &self.entities;
+                    self.router
+                        .route(world, op, |_, entity_id| entities.get(&entity_id).copied());
+                }
+            }
+        }
+    }
+
+    /// Clears every Dirty marker in the world, and returns an update for each marked component
+    /// the worker is authoritative over.
+    pub fn gather_updates(&self, world: &mut W) -> Vec<(EntityId, worker::ComponentUpdate)> {
+        let mut updates = Vec::new();
+        for (entity_id, entity) in &self.entities {
+            for (component_id, hooks) in &self.dirty {
+                if let Some(update) = (hooks.gather)(world, *entity) {
+                    updates.push((
+                        *entity_id,
+                        worker::ComponentUpdate::new(*component_id, update),
+                    ));
+                }
+            }
+        }
+        updates
+    }
+
+    /// Clears every Dirty marker in the world, and sends each marked component through the
+    /// writer returned by WorkerConnection::write, so that components the worker is not
+    /// authoritative over are not sent.
+    pub fn send_updates(&self, world: &mut W, connection: &impl WorkerConnection) {
+        for (entity_id, entity) in &self.entities {
+            for hooks in self.dirty.values() {
+                (hooks.send)(world, *entity, *entity_id, connection);
+            }
+        }
+    }
+}
+
+impl<W: EcsWorld> Default for EcsAdapter<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn add_component<W: EcsWorld, C: Component + Send + Sync + 'static>(
+    world: &mut W,
+    entity: W::Entity,
+    component: Box<dyn Any>,
+) {
+    if let Ok(component) = component.downcast::<C>() {
+        world.insert(entity, *component);
+    }
+}
+
+fn remove_component<W: EcsWorld, C: Component + Send + Sync + 'static>(
+    world: &mut W,
+    entity: W::Entity,
+) {
+    world.remove::<C>(entity);
+    world.remove::<Authoritative<C>>(entity);
+    world.remove::<Dirty<C>>(entity);
+}
+
+fn update_component<W, C>(world: &mut W, entity: W::Entity, update: Box<dyn Any>)
+where
+    W: EcsWorld,
+    C: Component + Send + Sync + 'static,
+    C::Update: 'static,
+{
+    if let (Ok(update), Some(component)) =
+        (update.downcast::<C::Update>(), world.get_mut::<C>(entity))
+    {
+        component.apply_update(&update);
+    }
+}
+
+fn change_authority<W: EcsWorld, C: Component + Send + Sync + 'static>(
+    world: &mut W,
+    entity: W::Entity,
+    authority: &Authority,
+) {
+    match authority {
+        Authority::Authoritative | Authority::AuthorityLossImminent => {
+            world.insert(entity, Authoritative::<C>::default());
+        }
+        Authority::NotAuthoritative => {
+            world.remove::<Authoritative<C>>(entity);
+        }
+    }
+}
+
+fn gather_update<W: EcsWorld, C: Component + Send + Sync + 'static>(
+    world: &mut W,
+    entity: W::Entity,
+) -> Option<schema::ComponentUpdate> {
+    world.get_mut::<Dirty<C>>(entity)?;
+    world.remove::<Dirty<C>>(entity);
+    world.get_mut::<Authoritative<C>>(entity)?;
+    world
+        .get_mut::<C>(entity)
+        .map(|component| component.to_data().into_update())
+}
+
+fn send_update<W: EcsWorld, C: Component + Send + Sync + 'static>(
+    world: &mut W,
+    entity: W::Entity,
+    entity_id: EntityId,
+    connection: &dyn UpdateSender,
+) {
+    if world.get_mut::<Dirty<C>>(entity).is_none() {
+        return;
+    }
+    world.remove::<Dirty<C>>(entity);
+    if let (Ok(writer), Some(component)) =
+        (connection.write::<C>(entity_id), world.get_mut::<C>(entity))
+    {
+        writer.send(component);
+    }
+}
+
+#[cfg(all(test, feature = "hecs"))]
+mod tests {
+    use super::*;
+    use crate::improbable::{Metadata, MetadataUpdate};
+    use crate::schema::Update;
+    use crate::worker::connection::WorkerConnection;
+    use crate::worker::mock::MockConnection;
+    use crate::worker::test_util::metadata;
+
+    fn entity_type(world: &mut hecs::World, entity: hecs::Entity) -> Option<String> {
+        world
+            .get_mut::<Metadata>(entity)
+            .map(|metadata| metadata.entity_type.clone())
+    }
+
+    #[test]
+    fn ops_are_mirrored_into_a_hecs_world() {
+        let mut world = hecs::World::new();
+        let mut adapter = EcsAdapter::<hecs::World>::new();
+        adapter.register::<Metadata>();
+        let mut connection = MockConnection::new("worker");
+        connection.add_entity(1);
+        connection.add_component(1, metadata("tree").to_component_data());
+        connection.component_update(
+            1,
+            worker::ComponentUpdate::new(
+                Metadata::ID,
+                MetadataUpdate {
+                    entity_type: Some("rock".to_owned()),
+                }
+                .to_update(),
+            ),
+        );
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+
+        let entity = adapter.entity(1).unwrap();
+        assert_eq!(
+            world.get_mut::<SpatialEntityId>(entity).copied(),
+            Some(SpatialEntityId(1))
+        );
+        assert_eq!(entity_type(&mut world, entity), Some("rock".to_owned()));
+
+        connection.remove_component(1, Metadata::ID);
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        assert_eq!(entity_type(&mut world, entity), None);
+
+        connection.remove_entity(1);
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        assert_eq!(adapter.entity(1), None);
+        assert!(!world.contains(entity));
+    }
+
+    #[test]
+    fn only_dirty_authoritative_components_are_gathered() {
+        let mut world = hecs::World::new();
+        let mut adapter = EcsAdapter::<hecs::World>::new();
+        adapter.register::<Metadata>();
+        let mut connection = MockConnection::new("worker");
+        connection.add_entity(1);
+        connection.add_component(1, metadata("tree").to_component_data());
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        let entity = adapter.entity(1).unwrap();
+
+        world.insert(entity, Dirty::<Metadata>::default());
+        assert!(adapter.gather_updates(&mut world).is_empty());
+        assert!(world.get_mut::<Dirty<Metadata>>(entity).is_none());
+
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        world.get_mut::<Metadata>(entity).unwrap().entity_type = "rock".to_owned();
+        world.insert(entity, Dirty::<Metadata>::default());
+        let updates = adapter.gather_updates(&mut world);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, 1);
+        assert_eq!(updates[0].1.component_id, Metadata::ID);
+        assert!(adapter.gather_updates(&mut world).is_empty());
+    }
+
+    #[test]
+    fn only_dirty_authoritative_components_are_sent() {
+        let mut world = hecs::World::new();
+        let mut adapter = EcsAdapter::<hecs::World>::new();
+        adapter.register::<Metadata>();
+        let mut connection = MockConnection::new("worker");
+        connection.add_entity(1);
+        connection.add_component(1, metadata("tree").to_component_data());
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        let entity = adapter.entity(1).unwrap();
+
+        world.insert(entity, Dirty::<Metadata>::default());
+        adapter.send_updates(&mut world, &connection);
+        assert!(connection.take_component_updates().is_empty());
+        assert!(world.get_mut::<Dirty<Metadata>>(entity).is_none());
+
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        adapter.process_ops(&mut world, &connection.get_op_list(0));
+        world.get_mut::<Metadata>(entity).unwrap().entity_type = "rock".to_owned();
+        world.insert(entity, Dirty::<Metadata>::default());
+        adapter.send_updates(&mut world, &connection);
+        let updates = connection.take_component_updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, 1);
+        let update = MetadataUpdate::from_update(updates[0].1.schema_type.as_ref().unwrap());
+        assert_eq!(update.entity_type, Some("rock".to_owned()));
+
+        adapter.send_updates(&mut world, &connection);
+        assert!(connection.take_component_updates().is_empty());
+    }
+}
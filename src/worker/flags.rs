@@ -0,0 +1,224 @@
+use crate::worker::connection::WorkerConnection;
+use crate::worker::op::FlagUpdateOp;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+type FlagCallback = Box<dyn FnMut(Option<&str>) + Send>;
+
+/// A local copy of the worker flags, kept up to date by applying the FlagUpdate ops received on the
+/// connection.
+///
+/// The SDK cannot enumerate the flags of a worker, so flags set before the connection was made are
+/// only known once loaded by name.
+#[derive(Default)]
+pub struct WorkerFlags {
+    values: HashMap<String, String>,
+    defaults: HashMap<String, String>,
+    callbacks: HashMap<String, Vec<FlagCallback>>,
+}
+
+impl WorkerFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a flag store holding the current values of the given flags.
+    pub fn from_connection<C, S>(connection: &C, names: &[S]) -> Self
+    where
+        C: WorkerConnection + ?Sized,
+        S: AsRef<str>,
+    {
+        let mut flags = Self::new();
+        for name in names {
+            flags.load(connection, name);
+        }
+        flags
+    }
+
+    /// Fetches the current value of a flag from the connection.
+    pub fn load<C, S>(&mut self, connection: &C, name: S)
+    where
+        C: WorkerConnection + ?Sized,
+        S: AsRef<str>,
+    {
+        let value = connection.flag(name.as_ref());
+        self.set(name.as_ref(), value);
+    }
+
+    /// Sets the value returned for a flag while it is not set on the worker.
+    pub fn set_default<S: Into<String>, T: ToString>(&mut self, name: S, value: T) {
+        self.defaults.insert(name.into(), value.to_string());
+    }
+
+    /// Registers a callback invoked with the new value of a flag every time it changes. A None value
+    /// indicates that the flag has been deleted.
+    pub fn on_change<S, F>(&mut self, name: S, callback: F)
+    where
+        S: Into<String>,
+        F: FnMut(Option<&str>) + Send + 'static,
+    {
+        self.callbacks
+            .entry(name.into())
+            .or_default()
+            .push(Box::new(callback));
+    }
+
+    /// Returns the raw value of a flag, or its default value if it is not set.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.values
+            .get(name)
+            .or_else(|| self.defaults.get(name))
+            .map(String::as_str)
+    }
+
+    /// Returns the value of a flag parsed as T. Falls back to the default value if the flag is not
+    /// set or cannot be parsed, and returns None if neither is available. Values which cannot be
+    /// parsed are logged as warnings through the enabled logging crates.
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.values
+            .get(name)
+            .and_then(|value| parse_value(name, value))
+            .or_else(|| {
+                self.defaults
+                    .get(name)
+                    .and_then(|value| parse_value(name, value))
+            })
+    }
+
+    /// Returns the value of a flag parsed as T, or the error returned by the parser. Returns None
+    /// if the flag is not set, ignoring default values.
+    pub fn try_get<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.values.get(name).map(|value| value.parse())
+    }
+
+    /// Returns the value of a flag parsed as T, or the given value if it is unavailable.
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> T {
+        self.get(name).unwrap_or(default)
+    }
+
+    /// Returns true if the flag is set on the worker, ignoring default values.
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Applies a FlagUpdate op, notifying the callbacks registered for the flag if its value
+    /// changed.
+    pub fn apply(&mut self, op: &FlagUpdateOp) {
+        self.set(&op.name, op.value.clone());
+    }
+
+    fn set(&mut self, name: &str, value: Option<String>) {
+        let previous = match &value {
+            Some(value) => self.values.insert(name.to_owned(), value.clone()),
+            None => self.values.remove(name),
+        };
+        if previous == value {
+            return;
+        }
+        if let Some(callbacks) = self.callbacks.get_mut(name) {
+            for callback in callbacks {
+                callback(value.as_deref());
+            }
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Option<T> {
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        #[cfg(feature = "log")]
+        log::warn!("worker flag {} has an invalid value: {:?}", name, value);
+        #[cfg(feature = "tracing")]
+        tracing::warn!(flag = name, value, "worker flag has an invalid value");
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        let _ = (name, value);
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::mock::MockConnection;
+    use std::sync::{Arc, Mutex};
+
+    fn update(name: &str, value: Option<&str>) -> FlagUpdateOp {
+        FlagUpdateOp {
+            name: name.to_owned(),
+            value: value.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn get_parses_the_value_of_the_flag() {
+        let mut flags = WorkerFlags::new();
+        flags.apply(&update("tick_rate", Some("30")));
+        assert_eq!(flags.get::<u32>("tick_rate"), Some(30));
+        assert_eq!(flags.get_str("tick_rate"), Some("30"));
+        assert!(flags.contains("tick_rate"));
+    }
+
+    #[test]
+    fn get_falls_back_to_the_default_value() {
+        let mut flags = WorkerFlags::new();
+        flags.set_default("tick_rate", 60);
+        assert_eq!(flags.get::<u32>("tick_rate"), Some(60));
+        assert!(!flags.contains("tick_rate"));
+
+        flags.apply(&update("tick_rate", Some("fast")));
+        assert_eq!(flags.get::<u32>("tick_rate"), Some(60));
+        assert_eq!(flags.get_or::<u32>("missing", 5), 5);
+        assert_eq!(flags.get::<u32>("missing"), None);
+    }
+
+    #[test]
+    fn try_get_returns_parse_errors() {
+        let mut flags = WorkerFlags::new();
+        flags.set_default("tick_rate", 60);
+        assert!(flags.try_get::<u32>("tick_rate").is_none());
+
+        flags.apply(&update("tick_rate", Some("fast")));
+        assert!(matches!(flags.try_get::<u32>("tick_rate"), Some(Err(_))));
+    }
+
+    #[test]
+    fn flags_are_loaded_from_the_connection() {
+        let mut connection = MockConnection::new("worker");
+        connection.set_flag("tick_rate", Some("30".to_owned()));
+
+        let mut flags = WorkerFlags::from_connection(&connection, &["tick_rate", "mode"]);
+        assert_eq!(flags.get::<u32>("tick_rate"), Some(30));
+        assert!(!flags.contains("mode"));
+
+        connection.set_flag("mode", Some("busy".to_owned()));
+        flags.load(&connection, "mode");
+        assert_eq!(flags.get_str("mode"), Some("busy"));
+    }
+
+    #[test]
+    fn deleted_flags_use_the_default_value() {
+        let mut flags = WorkerFlags::new();
+        flags.set_default("mode", "idle");
+        flags.apply(&update("mode", Some("busy")));
+        flags.apply(&update("mode", None));
+        assert_eq!(flags.get_str("mode"), Some("idle"));
+        assert!(!flags.contains("mode"));
+    }
+
+    #[test]
+    fn callbacks_are_notified_of_changes_only() {
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let mut flags = WorkerFlags::new();
+        let recorded = values.clone();
+        flags.on_change("mode", move |value| {
+            recorded.lock().unwrap().push(value.map(str::to_owned))
+        });
+
+        flags.apply(&update("mode", Some("busy")));
+        flags.apply(&update("mode", Some("busy")));
+        flags.apply(&update("other", Some("busy")));
+        flags.apply(&update("mode", None));
+
+        assert_eq!(*values.lock().unwrap(), vec![Some("busy".to_owned()), None]);
+    }
+}
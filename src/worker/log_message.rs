@@ -1,5 +1,6 @@
 use spatialos_sys::Worker_LogMessage;
 
+use crate::to_c_string;
 use crate::worker::EntityId;
 use crate::worker::LogLevel;
 use std::ffi::CStr;
@@ -20,13 +21,11 @@ pub struct LogMessage {
 impl From<Worker_LogMessage> for LogMessage {
     fn from(log_message: Worker_LogMessage) -> Self {
         let logger_name = unsafe { CStr::from_ptr(log_message.logger_name) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         let message = unsafe { CStr::from_ptr(log_message.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             level: log_message.level.into(),
             entity_id: if log_message.entity_id.is_null() {
@@ -40,24 +39,35 @@ impl From<Worker_LogMessage> for LogMessage {
     }
 }
 
-impl Into<Worker_LogMessage> for LogMessage {
-    fn into(self) -> Worker_LogMessage {
-        let message = CString::new(self.message).unwrap();
-        let logger_name = CString::new(self.logger_name).unwrap();
-        if let Some(entity_id) = self.entity_id {
-            Worker_LogMessage {
-                level: self.level.into(),
-                message: message.into_raw(),
-                logger_name: logger_name.into_raw(),
-                entity_id: &entity_id as *const EntityId,
-            }
-        } else {
-            Worker_LogMessage {
-                level: self.level.into(),
-                message: message.into_raw(),
-                logger_name: logger_name.into_raw(),
-                entity_id: std::ptr::null(),
-            }
+/// The SDK representation of a LogMessage, pointing into storage owned by this struct so that it
+/// is freed once the message has been sent. NUL bytes are removed from the strings.
+pub(crate) struct RawLogMessage {
+    level: u8,
+    logger_name: CString,
+    message: CString,
+    entity_id: Option<EntityId>,
+}
+
+impl RawLogMessage {
+    pub(crate) fn new(log_message: LogMessage) -> Self {
+        Self {
+            level: log_message.level.into(),
+            logger_name: to_c_string(log_message.logger_name),
+            message: to_c_string(log_message.message),
+            entity_id: log_message.entity_id,
+        }
+    }
+
+    /// Returns the SDK log message, which is only valid as long as this struct is alive.
+    pub(crate) fn as_raw(&self) -> Worker_LogMessage {
+        Worker_LogMessage {
+            level: self.level,
+            logger_name: self.logger_name.as_ptr(),
+            message: self.message.as_ptr(),
+            entity_id: self
+                .entity_id
+                .as_ref()
+                .map_or(std::ptr::null(), |entity_id| entity_id as *const EntityId),
         }
     }
 }
@@ -77,3 +87,26 @@ impl LogMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_log_message_points_to_owned_storage() {
+        let log_message = LogMessage::new(LogLevel::Warn, "physics", "bad\0 state", Some(7));
+        let raw_log_message = RawLogMessage::new(log_message);
+        let converted = LogMessage::from(raw_log_message.as_raw());
+        assert_eq!(converted.logger_name, "physics");
+        assert_eq!(converted.message, "bad state");
+        assert_eq!(converted.entity_id, Some(7));
+        assert!(matches!(converted.level, LogLevel::Warn));
+    }
+
+    #[test]
+    fn raw_log_message_without_entity() {
+        let log_message = LogMessage::new(LogLevel::Info, "", "", None);
+        let raw_log_message = RawLogMessage::new(log_message);
+        assert!(raw_log_message.as_raw().entity_id.is_null());
+    }
+}
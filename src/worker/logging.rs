@@ -0,0 +1,437 @@
+//! Integration of SpatialOS log messages with the `log` and `tracing` crates.
+//!
+//! Records emitted through either crate can be sent to SpatialOS, using their target as the logger
+//! name and an `entity_id` field as the ID of the entity they relate to. In the other direction,
+//! the LogMessage ops sent by the SDK can be re-emitted as records, under the `spatialos` target.
+
+use crate::worker::connection::ConnectionSender;
+use crate::worker::log_message::LogMessage;
+use crate::worker::op::LogMessageOp;
+use crate::worker::{EntityId, LogLevel};
+
+/// The target of the records emitted for the LogMessage ops sent by the SDK.
+pub const SDK_LOG_TARGET: &str = "spatialos";
+
+/// Re-emits a LogMessage op sent by the SDK through every enabled logging crate.
+pub fn emit_log_message(op: &LogMessageOp) {
+    #[cfg(feature = "log")]
+    log::log!(target: SDK_LOG_TARGET, log::Level::from(&op.level), "{}", op.message);
+    #[cfg(feature = "tracing")]
+    match op.level {
+        LogLevel::Debug => tracing::debug!(target: SDK_LOG_TARGET, "{}", op.message),
+        LogLevel::Info => tracing::info!(target: SDK_LOG_TARGET, "{}", op.message),
+        LogLevel::Warn => tracing::warn!(target: SDK_LOG_TARGET, "{}", op.message),
+        LogLevel::Error | LogLevel::Fatal => {
+            tracing::error!(target: SDK_LOG_TARGET, "{}", op.message)
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+mod log_support {
+    use super::*;
+    use log::kv::{Key, Value, VisitSource};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    impl From<Level> for LogLevel {
+        fn from(level: Level) -> Self {
+            match level {
+                Level::Trace | Level::Debug => Self::Debug,
+                Level::Info => Self::Info,
+                Level::Warn => Self::Warn,
+                Level::Error => Self::Error,
+            }
+        }
+    }
+
+    impl From<&LogLevel> for Level {
+        fn from(level: &LogLevel) -> Self {
+            match level {
+                LogLevel::Debug => Self::Debug,
+                LogLevel::Info => Self::Info,
+                LogLevel::Warn => Self::Warn,
+                LogLevel::Error | LogLevel::Fatal => Self::Error,
+            }
+        }
+    }
+
+    /// A logger sending the records of the `log` crate to SpatialOS.
+    pub struct SpatialLogger {
+        connection: ConnectionSender,
+        level: LevelFilter,
+    }
+
+    impl SpatialLogger {
+        pub fn new(connection: ConnectionSender, level: LevelFilter) -> Self {
+            Self { connection, level }
+        }
+
+        /// Installs the logger as the global logger of the `log` crate.
+        pub fn init(self) -> Result<(), log::SetLoggerError> {
+            log::set_max_level(self.level);
+            log::set_boxed_logger(Box::new(self))
+        }
+    }
+
+    struct EntityIdVisitor(Option<EntityId>);
+
+    impl<'kvs> VisitSource<'kvs> for EntityIdVisitor {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+            if key.as_str() == "entity_id" {
+                self.0 = value.to_i64();
+            }
+            Ok(())
+        }
+    }
+
+    /// Converts a record to the log message sent to SpatialOS for it.
+    pub(super) fn log_message(record: &Record) -> LogMessage {
+        let mut visitor = EntityIdVisitor(None);
+        let _ = record.key_values().visit(&mut visitor);
+        LogMessage {
+            level: record.level().into(),
+            logger_name: record.target().to_owned(),
+            message: record.args().to_string(),
+            entity_id: visitor.0,
+        }
+    }
+
+    impl Log for SpatialLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            // Records of the SDK itself are not sent back to it.
+            metadata.level() <= self.level && metadata.target() != SDK_LOG_TARGET
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            self.connection.send_log_message(log_message(record))
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(feature = "log")]
+pub use log_support::SpatialLogger;
+
+#[cfg(feature = "tracing")]
+mod tracing_support {
+    use super::*;
+    use std::fmt::Debug;
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer};
+
+    impl From<&Level> for LogLevel {
+        fn from(level: &Level) -> Self {
+            match *level {
+                Level::TRACE | Level::DEBUG => Self::Debug,
+                Level::INFO => Self::Info,
+                Level::WARN => Self::Warn,
+                Level::ERROR => Self::Error,
+            }
+        }
+    }
+
+    /// A layer sending the events recorded through the `tracing` crate to SpatialOS.
+    pub struct SpatialLayer {
+        connection: ConnectionSender,
+    }
+
+    impl SpatialLayer {
+        pub fn new(connection: ConnectionSender) -> Self {
+            Self { connection }
+        }
+    }
+
+    #[derive(Default)]
+    struct EventVisitor {
+        message: String,
+        fields: String,
+        entity_id: Option<EntityId>,
+    }
+
+    impl Visit for EventVisitor {
+        fn record_i64(&mut self, field: &Field, value: i64) {
+            if field.name() == "entity_id" {
+                self.entity_id = Some(value);
+            } else {
+                self.record_debug(field, &value);
+            }
+        }
+
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "entity_id" {
+                self.entity_id = Some(value as EntityId);
+            } else {
+                self.record_debug(field, &value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "message" {
+                self.message = format!("{:?}", value);
+            } else {
+                self.fields += &format!(" {}={:?}", field.name(), value);
+            }
+        }
+    }
+
+    /// Converts an event to the log message sent to SpatialOS for it. Fields other than the message
+    /// and the entity ID are appended to the message.
+    pub(super) fn log_message(event: &Event<'_>) -> LogMessage {
+        let metadata = event.metadata();
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+        LogMessage {
+            level: metadata.level().into(),
+            logger_name: metadata.target().to_owned(),
+            message: visitor.message + &visitor.fields,
+            entity_id: visitor.entity_id,
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for SpatialLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            // Events of the SDK itself are not sent back to it.
+            if event.metadata().target() == SDK_LOG_TARGET {
+                return;
+            }
+            self.connection.send_log_message(log_message(event))
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub use tracing_support::SpatialLayer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(log_message: &LogMessage) -> String {
+        format!(
+            "{:?} {} {:?} {:?}",
+            log_message.level, log_message.logger_name, log_message.message, log_message.entity_id
+        )
+    }
+
+    fn sdk_ops() -> Vec<LogMessageOp> {
+        vec![
+            (LogLevel::Debug, "debug"),
+            (LogLevel::Info, "info"),
+            (LogLevel::Warn, "warn"),
+            (LogLevel::Error, "error"),
+            (LogLevel::Fatal, "fatal"),
+        ]
+        .into_iter()
+        .map(|(level, message)| LogMessageOp {
+            level,
+            message: message.to_owned(),
+        })
+        .collect()
+    }
+
+    #[cfg(feature = "log")]
+    mod log_tests {
+        use super::*;
+        use crate::worker::logging::log_support::log_message;
+        use log::{Level, LevelFilter, Log, Metadata, Record};
+        use std::sync::Mutex;
+
+        static RECORDS: Mutex<Vec<(Level, String, String)>> = Mutex::new(Vec::new());
+
+        struct CapturingLogger;
+
+        impl Log for CapturingLogger {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                RECORDS.lock().unwrap().push((
+                    record.level(),
+                    record.target().to_owned(),
+                    record.args().to_string(),
+                ));
+            }
+
+            fn flush(&self) {}
+        }
+
+        #[test]
+        fn levels_are_mapped_in_both_directions() {
+            let levels = [
+                Level::Trace,
+                Level::Debug,
+                Level::Info,
+                Level::Warn,
+                Level::Error,
+            ];
+            let mapped = levels
+                .iter()
+                .map(|level| format!("{:?}", LogLevel::from(*level)))
+                .collect::<Vec<_>>();
+            assert_eq!(mapped, vec!["Debug", "Debug", "Info", "Warn", "Error"]);
+
+            let levels = sdk_ops()
+                .iter()
+                .map(|op| Level::from(&op.level))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                levels,
+                vec![
+                    Level::Debug,
+                    Level::Info,
+                    Level::Warn,
+                    Level::Error,
+                    Level::Error
+                ]
+            );
+        }
+
+        #[test]
+        fn entity_id_is_extracted_from_the_record() {
+            let key_values = [("entity_id", 7i64)];
+            let with_entity = log_message(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("game")
+                    .args(format_args!("hit {}", 3))
+                    .key_values(&key_values)
+                    .build(),
+            );
+            assert_eq!(describe(&with_entity), "Warn game \"hit 3\" Some(7)");
+
+            let without_entity = log_message(
+                &Record::builder()
+                    .level(Level::Trace)
+                    .target("game")
+                    .args(format_args!("tick"))
+                    .build(),
+            );
+            assert_eq!(describe(&without_entity), "Debug game \"tick\" None");
+        }
+
+        #[test]
+        fn sdk_messages_are_emitted_at_their_level() {
+            let _ = log::set_logger(&CapturingLogger);
+            log::set_max_level(LevelFilter::Trace);
+            for op in sdk_ops() {
+                emit_log_message(&op);
+            }
+            let records = RECORDS
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, target, _)| target == SDK_LOG_TARGET)
+                .map(|(level, _, message)| format!("{} {}", level, message))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                records,
+                vec![
+                    "DEBUG debug",
+                    "INFO info",
+                    "WARN warn",
+                    "ERROR error",
+                    "ERROR fatal"
+                ]
+            );
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    mod tracing_tests {
+        use super::*;
+        use crate::worker::logging::tracing_support::log_message;
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata, Subscriber};
+
+        #[derive(Clone, Default)]
+        struct CapturingSubscriber(Arc<Mutex<Vec<LogMessage>>>);
+
+        impl Subscriber for CapturingSubscriber {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                self.0.lock().unwrap().push(log_message(event));
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        fn capture<F: FnOnce()>(f: F) -> Vec<String> {
+            let subscriber = CapturingSubscriber::default();
+            tracing::subscriber::with_default(subscriber.clone(), f);
+            let messages = subscriber.0.lock().unwrap();
+            messages.iter().map(describe).collect()
+        }
+
+        #[test]
+        fn levels_are_mapped() {
+            let levels = [
+                Level::TRACE,
+                Level::DEBUG,
+                Level::INFO,
+                Level::WARN,
+                Level::ERROR,
+            ];
+            let mapped = levels
+                .iter()
+                .map(|level| format!("{:?}", LogLevel::from(level)))
+                .collect::<Vec<_>>();
+            assert_eq!(mapped, vec!["Debug", "Debug", "Info", "Warn", "Error"]);
+        }
+
+        #[test]
+        fn entity_id_is_extracted_from_the_event() {
+            let messages = capture(|| {
+                tracing::warn!(target: "game", entity_id = 7, speed = 2, "hit {}", 3);
+                tracing::trace!(target: "game", entity_id = 8u64, "tick");
+                tracing::info!(target: "game", "idle");
+            });
+            assert_eq!(
+                messages,
+                vec![
+                    "Warn game \"hit 3 speed=2\" Some(7)",
+                    "Debug game \"tick\" Some(8)",
+                    "Info game \"idle\" None"
+                ]
+            );
+        }
+
+        #[test]
+        fn sdk_messages_are_emitted_at_their_level() {
+            let messages = capture(|| {
+                for op in sdk_ops() {
+                    emit_log_message(&op);
+                }
+            });
+            assert_eq!(
+                messages,
+                vec![
+                    "Debug spatialos \"debug\" None",
+                    "Info spatialos \"info\" None",
+                    "Warn spatialos \"warn\" None",
+                    "Error spatialos \"error\" None",
+                    "Error spatialos \"fatal\" None"
+                ]
+            );
+        }
+    }
+}
@@ -2,11 +2,19 @@ use spatialos_sys::{
     Worker_GaugeMetric, Worker_HistogramMetric, Worker_HistogramMetricBucket, Worker_Metrics,
 };
 
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
 use std::ffi::CString;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use crate::{const_to_string, const_to_vector, vector_to_owned_array};
+use crate::worker::connection::ConnectionSender;
+use crate::{const_to_string, const_to_vector, to_c_string};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HistogramMetricBucket {
     pub upper_bound: f64,
     pub samples: u32,
@@ -21,16 +29,7 @@ impl From<Worker_HistogramMetricBucket> for HistogramMetricBucket {
     }
 }
 
-impl Into<Worker_HistogramMetricBucket> for HistogramMetricBucket {
-    fn into(self) -> Worker_HistogramMetricBucket {
-        Worker_HistogramMetricBucket {
-            upper_bound: self.upper_bound,
-            samples: self.samples,
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HistogramMetric {
     pub key: String,
     pub sum: f64,
@@ -52,26 +51,7 @@ impl From<Worker_HistogramMetric> for HistogramMetric {
     }
 }
 
-impl Into<Worker_HistogramMetric> for HistogramMetric {
-    fn into(self) -> Worker_HistogramMetric {
-        let key = CString::new(self.key).unwrap().into_raw();
-        let buckets = self
-            .buckets
-            .into_iter()
-            .map(|b| b.into())
-            .collect::<Vec<_>>();
-
-        let (buckets, bucket_count) = vector_to_owned_array(buckets);
-        Worker_HistogramMetric {
-            sum: self.sum,
-            bucket_count: bucket_count as u32,
-            key,
-            buckets,
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Parameters for a gauge metric.
 pub struct GaugeMetric {
     pub key: String,
@@ -88,17 +68,7 @@ impl From<Worker_GaugeMetric> for GaugeMetric {
     }
 }
 
-impl Into<Worker_GaugeMetric> for GaugeMetric {
-    fn into(self) -> Worker_GaugeMetric {
-        let key = CString::new(self.key).unwrap().into_raw();
-        Worker_GaugeMetric {
-            value: self.value,
-            key,
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Parameters for sending metrics to SpatialOS.
 pub struct Metrics {
     /// The load value of this worker. If NULL, do not report load.
@@ -135,30 +105,457 @@ impl From<Worker_Metrics> for Metrics {
     }
 }
 
-impl Into<Worker_Metrics> for Metrics {
-    fn into(self) -> Worker_Metrics {
-        let histogram_metrics = self
+/// The SDK representation of Metrics, pointing into storage owned by this struct so that it is
+/// freed once the metrics have been sent.
+pub(crate) struct RawMetrics {
+    load: Option<Box<f64>>,
+    _keys: Vec<CString>,
+    _buckets: Vec<Vec<Worker_HistogramMetricBucket>>,
+    gauge_metrics: Vec<Worker_GaugeMetric>,
+    histogram_metrics: Vec<Worker_HistogramMetric>,
+}
+
+impl RawMetrics {
+    pub(crate) fn new(metrics: &Metrics) -> Self {
+        let mut keys = Vec::new();
+        let mut buckets = Vec::new();
+        let gauge_metrics = metrics
+            .gauge_metrics
+            .iter()
+            .map(|metric| {
+                let key = to_c_string(metric.key.as_str());
+                let raw = Worker_GaugeMetric {
+                    key: key.as_ptr(),
+                    value: metric.value,
+                };
+                keys.push(key);
+                raw
+            })
+            .collect();
+        let histogram_metrics = metrics
             .histogram_metrics
-            .into_iter()
-            .map(|h| h.into())
+            .iter()
+            .map(|metric| {
+                let key = to_c_string(metric.key.as_str());
+                let metric_buckets = metric
+                    .buckets
+                    .iter()
+                    .map(|bucket| Worker_HistogramMetricBucket {
+                        upper_bound: bucket.upper_bound,
+                        samples: bucket.samples,
+                    })
+                    .collect::<Vec<_>>();
+                let raw = Worker_HistogramMetric {
+                    key: key.as_ptr(),
+                    sum: metric.sum,
+                    bucket_count: metric_buckets.len() as u32,
+                    buckets: metric_buckets.as_ptr(),
+                };
+                keys.push(key);
+                buckets.push(metric_buckets);
+                raw
+            })
+            .collect();
+        Self {
+            load: metrics.load.map(Box::new),
+            _keys: keys,
+            _buckets: buckets,
+            gauge_metrics,
+            histogram_metrics,
+        }
+    }
+
+    /// Returns the SDK metrics, which are only valid as long as this struct is alive.
+    pub(crate) fn as_raw(&self) -> Worker_Metrics {
+        Worker_Metrics {
+            load: self
+                .load
+                .as_deref()
+                .map_or(std::ptr::null(), |load| load as *const f64),
+            gauge_metric_count: self.gauge_metrics.len() as u32,
+            gauge_metrics: self.gauge_metrics.as_ptr(),
+            histogram_metric_count: self.histogram_metrics.len() as u32,
+            histogram_metrics: self.histogram_metrics.as_ptr(),
+        }
+    }
+}
+
+/// A gauge registered in a MetricsRegistry. It can be cloned and updated from any thread.
+#[derive(Debug, Clone)]
+pub struct Gauge {
+    bits: Arc<AtomicU64>,
+}
+
+impl Gauge {
+    fn new() -> Self {
+        Self {
+            bits: Arc::new(AtomicU64::new(0f64.to_bits())),
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f64) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed)
+    }
+
+    pub fn add(&self, delta: f64) {
+        let _ = self
+            .bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + delta).to_bits())
+            });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The error returned when registering a histogram with a NaN bucket upper bound.
+pub struct InvalidUpperBound;
+
+impl Display for InvalidUpperBound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "histogram bucket upper bounds must not be NaN")
+    }
+}
+
+impl std::error::Error for InvalidUpperBound {}
+
+#[derive(Debug)]
+struct HistogramState {
+    upper_bounds: Vec<f64>,
+    samples: Vec<u32>,
+    sum: f64,
+    reported_samples: Vec<u32>,
+    reported_sum: f64,
+}
+
+/// A histogram registered in a MetricsRegistry. It can be cloned and observed from any thread.
+///
+/// Snapshots of the registry only report the observations made since the previous snapshot, while
+/// totals report every observation.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    state: Arc<Mutex<HistogramState>>,
+}
+
+impl Histogram {
+    fn new(upper_bounds: &[f64]) -> Result<Self, InvalidUpperBound> {
+        if upper_bounds.iter().any(|upper_bound| upper_bound.is_nan()) {
+            return Err(InvalidUpperBound);
+        }
+        let mut upper_bounds = upper_bounds.to_vec();
+        upper_bounds.sort_by(f64::total_cmp);
+        if upper_bounds.last() != Some(&f64::INFINITY) {
+            upper_bounds.push(f64::INFINITY);
+        }
+        let samples = vec![0; upper_bounds.len()];
+        Ok(Self {
+            state: Arc::new(Mutex::new(HistogramState {
+                upper_bounds,
+                reported_samples: samples.clone(),
+                samples,
+                sum: 0.0,
+                reported_sum: 0.0,
+            })),
+        })
+    }
+
+    /// Records a single observation in the first bucket whose upper bound is not lower than the
+    /// value. The sample count of a bucket stops increasing once it reaches u32::MAX.
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.lock().unwrap();
+        let index = state
+            .upper_bounds
+            .iter()
+            .position(|upper_bound| value <= *upper_bound)
+            .unwrap_or(state.upper_bounds.len() - 1);
+        state.samples[index] = state.samples[index].saturating_add(1);
+        state.sum += value;
+    }
+
+    fn take(&self, key: &str) -> HistogramMetric {
+        let mut state = self.state.lock().unwrap();
+        let samples = state
+            .samples
+            .iter()
+            .zip(state.reported_samples.iter())
+            .map(|(samples, reported_samples)| samples - reported_samples)
             .collect::<Vec<_>>();
-        let gauge_metrics = self
-            .gauge_metrics
-            .into_iter()
-            .map(|g| g.into())
+        let metric = to_metric(
+            key,
+            &state.upper_bounds,
+            &samples,
+            state.sum - state.reported_sum,
+        );
+        state.reported_samples = state.samples.clone();
+        state.reported_sum = state.sum;
+        metric
+    }
+
+    fn totals(&self, key: &str) -> HistogramMetric {
+        let state = self.state.lock().unwrap();
+        to_metric(key, &state.upper_bounds, &state.samples, state.sum)
+    }
+}
+
+fn to_metric(key: &str, upper_bounds: &[f64], samples: &[u32], sum: f64) -> HistogramMetric {
+    let mut cumulative_samples = 0;
+    let buckets = upper_bounds
+        .iter()
+        .zip(samples.iter())
+        .map(|(upper_bound, samples)| {
+            cumulative_samples = samples.saturating_add(cumulative_samples);
+            HistogramMetricBucket {
+                upper_bound: *upper_bound,
+                samples: cumulative_samples,
+            }
+        })
+        .collect();
+    HistogramMetric {
+        key: key.to_owned(),
+        sum,
+        buckets,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    load: Option<f64>,
+    gauges: BTreeMap<String, Gauge>,
+    histograms: BTreeMap<String, Histogram>,
+}
+
+/// A set of named gauges and histograms, along with the load of the worker, which can be turned
+/// into Metrics to be sent to SpatialOS. It can be cloned and shared between threads.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    inner: Arc<Mutex<Registry>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the gauge with the given key, registering it if needed.
+    pub fn gauge<S: Into<String>>(&self, key: S) -> Gauge {
+        self.inner
+            .lock()
+            .unwrap()
+            .gauges
+            .entry(key.into())
+            .or_insert_with(Gauge::new)
+            .clone()
+    }
+
+    /// Returns the histogram with the given key, registering it with the given bucket upper bounds
+    /// if needed. A bucket without upper bound is always added to hold the largest observations.
+    /// Returns an error if the histogram has to be registered and an upper bound is NaN.
+    pub fn histogram<S: Into<String>>(
+        &self,
+        key: S,
+        upper_bounds: &[f64],
+    ) -> Result<Histogram, InvalidUpperBound> {
+        let mut registry = self.inner.lock().unwrap();
+        match registry.histograms.entry(key.into()) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => Ok(entry.insert(Histogram::new(upper_bounds)?).clone()),
+        }
+    }
+
+    /// Sets the load reported for this worker, used by SpatialOS for load balancing. A None value
+    /// stops reporting load.
+    pub fn set_load(&self, load: Option<f64>) {
+        self.inner.lock().unwrap().load = load;
+    }
+
+    /// Returns the current value of every metric. Histograms only contain the observations made
+    /// since the previous snapshot, so that every observation is only sent once.
+    pub fn snapshot(&self) -> Metrics {
+        self.collect(Histogram::take)
+    }
+
+    /// Returns the current value of every metric, with histograms containing every observation
+    /// made since they were registered. This does not affect snapshots.
+    pub fn totals(&self) -> Metrics {
+        self.collect(Histogram::totals)
+    }
+
+    fn collect<F: Fn(&Histogram, &str) -> HistogramMetric>(&self, read_histogram: F) -> Metrics {
+        let registry = self.inner.lock().unwrap();
+        Metrics {
+            load: registry.load,
+            gauge_metrics: registry
+                .gauges
+                .iter()
+                .map(|(key, gauge)| GaugeMetric {
+                    key: key.clone(),
+                    value: gauge.get(),
+                })
+                .collect(),
+            histogram_metrics: registry
+                .histograms
+                .iter()
+                .map(|(key, histogram)| read_histogram(histogram, key))
+                .collect(),
+        }
+    }
+}
+
+/// Periodically sends a snapshot of a MetricsRegistry to SpatialOS from a dedicated thread. The
+/// thread is stopped when the reporter is dropped.
+pub struct MetricsReporter {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsReporter {
+    pub fn spawn(
+        registry: MetricsRegistry,
+        connection: ConnectionSender,
+        interval: Duration,
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let thread = std::thread::spawn(move || {
+            let mut next_report = Instant::now() + interval;
+            while !thread_stopped.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now < next_report {
+                    std::thread::park_timeout(next_report - now);
+                    continue;
+                }
+                connection.send_metrics(registry.snapshot());
+                next_report += interval;
+            }
+        });
+        Self {
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for MetricsReporter {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn samples(metric: &HistogramMetric) -> Vec<u32> {
+        metric.buckets.iter().map(|bucket| bucket.samples).collect()
+    }
+
+    #[test]
+    fn gauges_are_shared_by_key() {
+        let registry = MetricsRegistry::new();
+        registry.gauge("entities").set(2.0);
+        registry.gauge("entities").add(1.5);
+        let metrics = registry.snapshot();
+        assert_eq!(metrics.gauge_metrics.len(), 1);
+        assert_eq!(metrics.gauge_metrics[0].key, "entities");
+        assert_eq!(metrics.gauge_metrics[0].value, 3.5);
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let registry = MetricsRegistry::new();
+        let histogram = registry.histogram("latency", &[10.0, 1.0]).unwrap();
+        histogram.observe(0.5);
+        histogram.observe(1.0);
+        histogram.observe(5.0);
+        histogram.observe(100.0);
+        let metric = histogram.totals("latency");
+        let upper_bounds = metric
+            .buckets
+            .iter()
+            .map(|bucket| bucket.upper_bound)
             .collect::<Vec<_>>();
-        let (histogram_metrics, histogram_metric_count) = vector_to_owned_array(histogram_metrics);
-        let (gauge_metrics, gauge_metric_count) = vector_to_owned_array(gauge_metrics);
-        Worker_Metrics {
-            load: if let Some(load) = self.load {
-                &load as *const f64
-            } else {
-                std::ptr::null()
-            },
-            gauge_metric_count: gauge_metric_count as u32,
-            histogram_metric_count: histogram_metric_count as u32,
-            histogram_metrics,
-            gauge_metrics,
+        assert_eq!(upper_bounds, vec![1.0, 10.0, f64::INFINITY]);
+        assert_eq!(samples(&metric), vec![2, 3, 4]);
+        assert_eq!(metric.sum, 106.5);
+    }
+
+    #[test]
+    fn snapshots_only_report_new_observations() {
+        let registry = MetricsRegistry::new();
+        let histogram = registry.histogram("latency", &[1.0]).unwrap();
+        histogram.observe(0.5);
+        histogram.observe(2.0);
+        let first = registry.snapshot();
+        assert_eq!(samples(&first.histogram_metrics[0]), vec![1, 2]);
+
+        histogram.observe(0.5);
+        let second = registry.snapshot();
+        assert_eq!(samples(&second.histogram_metrics[0]), vec![1, 1]);
+        assert_eq!(second.histogram_metrics[0].sum, 0.5);
+        assert_eq!(samples(&registry.totals().histogram_metrics[0]), vec![2, 3]);
+    }
+
+    #[test]
+    fn histogram_rejects_nan_upper_bounds() {
+        let registry = MetricsRegistry::new();
+        assert_eq!(
+            registry.histogram("latency", &[1.0, f64::NAN]).unwrap_err(),
+            InvalidUpperBound
+        );
+        assert!(registry.snapshot().histogram_metrics.is_empty());
+    }
+
+    #[test]
+    fn sample_counts_saturate() {
+        let histogram = Histogram::new(&[1.0]).unwrap();
+        histogram.state.lock().unwrap().samples = vec![u32::MAX, u32::MAX];
+        histogram.observe(0.5);
+        assert_eq!(
+            samples(&histogram.totals("latency")),
+            vec![u32::MAX, u32::MAX]
+        );
+    }
+
+    #[test]
+    fn raw_metrics_point_to_owned_storage() {
+        let metrics = Metrics {
+            load: Some(0.5),
+            gauge_metrics: vec![GaugeMetric {
+                key: "entit\0ies".to_owned(),
+                value: 3.0,
+            }],
+            histogram_metrics: vec![HistogramMetric {
+                key: "latency".to_owned(),
+                sum: 2.0,
+                buckets: vec![HistogramMetricBucket {
+                    upper_bound: f64::INFINITY,
+                    samples: 4,
+                }],
+            }],
+        };
+        let raw_metrics = RawMetrics::new(&metrics);
+        let raw = raw_metrics.as_raw();
+        unsafe {
+            assert_eq!(*raw.load, 0.5);
+            assert_eq!(raw.gauge_metric_count, 1);
+            let gauge = &*raw.gauge_metrics;
+            assert_eq!(CStr::from_ptr(gauge.key).to_str(), Ok("entities"));
+            assert_eq!(raw.histogram_metric_count, 1);
+            let histogram = &*raw.histogram_metrics;
+            assert_eq!(CStr::from_ptr(histogram.key).to_str(), Ok("latency"));
+            assert_eq!(histogram.bucket_count, 1);
+            assert_eq!((*histogram.buckets).samples, 4);
         }
+        let converted = Metrics::from(raw);
+        assert_eq!(converted.load, Some(0.5));
+        assert_eq!(converted.histogram_metrics[0].buckets[0].samples, 4);
     }
 }
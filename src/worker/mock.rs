@@ -0,0 +1,794 @@
+//! An in-process stand-in for a connection, to unit test worker logic without a SpatialOS runtime
+//! or network access.
+//!
+//! Tests enqueue the ops the worker should receive, run the worker against the WorkerConnection
+//! trait, and inspect the messages it sent.
+//!
+//! Ops may hold data backed by the user handles of a ComponentHandler, which is serialized through
+//! the vtables given to set_component_vtables. Enqueuing such data for a component without a vtable
+//! panics.
+
+use crate::worker::authority::{AuthorityLossHandlers, AuthorityTracker};
+use crate::worker::component_registry::ComponentRegistry;
+use crate::worker::component_vtable::ComponentVtable;
+use crate::worker::connection::{CommandParameters, UpdateParameters, WorkerConnection};
+use crate::worker::log_message::LogMessage;
+use crate::worker::metrics::Metrics;
+use crate::worker::op::{
+    AddComponentOp, AddEntityOp, AuthorityChangeOp, CommandRequestOp, CommandResponseOp,
+    ComponentUpdateOp, CreateEntityResponseOp, DisconnectOp, FlagUpdateOp, OpCommandRequest,
+    OpCommandResponse, OpComponentData, OpComponentUpdate, OpList, OwnedSchemaData,
+    RemoveComponentOp, RemoveEntityOp, WorkerOp,
+};
+use crate::worker::{
+    Authority, CommandRequest, CommandResponse, ComponentData, ComponentId, ComponentUpdate,
+    ConnectionStatusCode, EntityId, EntityQuery, RequestId, StatusCode, WorkerAttributes,
+};
+use spatialos_sys::Worker_CommandResponse;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+const UNSERIALIZABLE_HANDLE: &str =
+    "enqueued data is backed by a user handle of a component without a vtable, see \
+     MockConnection::set_component_vtables";
+
+/// A message sent by a worker through a MockConnection.
+pub enum SentMessage {
+    LogMessage(LogMessage),
+    Metrics(Metrics),
+    ReserveEntityIdsRequest {
+        request_id: RequestId,
+        number_of_entity_ids: u32,
+    },
+    CreateEntityRequest {
+        request_id: RequestId,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+    },
+    DeleteEntityRequest {
+        request_id: RequestId,
+        entity_id: EntityId,
+    },
+    EntityQueryRequest {
+        request_id: RequestId,
+        entity_query: EntityQuery,
+    },
+    ComponentUpdate {
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: Option<UpdateParameters>,
+    },
+    AddComponent {
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: Option<UpdateParameters>,
+    },
+    RemoveComponent {
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: Option<UpdateParameters>,
+    },
+    CommandRequest {
+        request_id: RequestId,
+        entity_id: EntityId,
+        request: CommandRequest,
+        parameters: Option<CommandParameters>,
+    },
+    CommandResponse {
+        request_id: RequestId,
+        response: CommandResponse,
+    },
+    CommandFailure {
+        request_id: RequestId,
+        message: String,
+    },
+    AuthorityLossImminentAcknowledgement {
+        entity_id: EntityId,
+        component_id: ComponentId,
+    },
+}
+
+/// A connection which returns the ops enqueued by a test and records every message sent through
+/// it. Request IDs are allocated sequentially, starting at 1, and shared between the requests sent
+/// by the worker and the command requests enqueued by the test.
+pub struct MockConnection {
+    worker_id: String,
+    attributes: Vec<String>,
+    flags: HashMap<String, String>,
+    connected: bool,
+    ops: Vec<WorkerOp<'static>>,
+    schema_data: Vec<OwnedSchemaData>,
+    sent: RefCell<Vec<SentMessage>>,
+    next_request_id: Cell<RequestId>,
+    authority: AuthorityTracker,
+    authority_loss_handlers: AuthorityLossHandlers,
+    vtables: Vec<ComponentVtable>,
+}
+
+impl MockConnection {
+    pub fn new<S: Into<String>>(worker_id: S) -> Self {
+        Self {
+            worker_id: worker_id.into(),
+            attributes: Vec::new(),
+            flags: HashMap::new(),
+            connected: true,
+            ops: Vec::new(),
+            schema_data: Vec::new(),
+            sent: RefCell::new(Vec::new()),
+            next_request_id: Cell::new(1),
+            authority: AuthorityTracker::new(),
+            authority_loss_handlers: AuthorityLossHandlers::new(),
+            vtables: Vec::new(),
+        }
+    }
+
+    /// Sets the component vtables to those of every component registered with a handler. The
+    /// ops enqueued by a test may then hold data backed by the user handles of those components,
+    /// which is serialized through the vtables as the SDK would.
+    pub fn set_component_vtables(&mut self, registry: &ComponentRegistry) {
+        self.vtables = registry.vtables();
+    }
+
+    pub fn set_attributes(&mut self, attributes: Vec<String>) {
+        self.attributes = attributes;
+    }
+
+    /// Sets the value of a worker flag, and enqueues the matching FlagUpdate op. A None value
+    /// deletes the flag.
+    pub fn set_flag<S: Into<String>>(&mut self, name: S, value: Option<String>) {
+        let name = name.into();
+        match &value {
+            Some(value) => self.flags.insert(name.clone(), value.clone()),
+            None => self.flags.remove(&name),
+        };
+        self.push_op(WorkerOp::FlagUpdate(FlagUpdateOp { name, value }));
+    }
+
+    /// Enqueues an op, to be returned by the next call to get_op_list. Any schema data referenced
+    /// by the op remains owned by the caller.
+    pub fn push_op(&mut self, op: WorkerOp<'static>) {
+        self.ops.push(op);
+    }
+
+    pub fn add_entity(&mut self, entity_id: EntityId) {
+        self.push_op(WorkerOp::AddEntity(AddEntityOp { entity_id }));
+    }
+
+    pub fn remove_entity(&mut self, entity_id: EntityId) {
+        self.push_op(WorkerOp::RemoveEntity(RemoveEntityOp { entity_id }));
+    }
+
+    pub fn add_component(&mut self, entity_id: EntityId, data: ComponentData) {
+        let data = data
+            .into_schema_data(&self.vtables)
+            .unwrap_or_else(|| panic!("{}", UNSERIALIZABLE_HANDLE));
+        self.schema_data
+            .push(OwnedSchemaData::ComponentData(data.schema_type));
+        self.push_op(WorkerOp::AddComponent(AddComponentOp {
+            entity_id,
+            data: unsafe { OpComponentData::from_raw(data) },
+        }));
+    }
+
+    pub fn remove_component(&mut self, entity_id: EntityId, component_id: ComponentId) {
+        self.push_op(WorkerOp::RemoveComponent(RemoveComponentOp {
+            entity_id,
+            component_id,
+        }));
+    }
+
+    pub fn authority_change(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        authority: Authority,
+    ) {
+        self.push_op(WorkerOp::AuthorityChange(AuthorityChangeOp {
+            entity_id,
+            component_id,
+            authority,
+        }));
+    }
+
+    pub fn component_update(&mut self, entity_id: EntityId, update: ComponentUpdate) {
+        let update = update
+            .into_schema_data(&self.vtables)
+            .unwrap_or_else(|| panic!("{}", UNSERIALIZABLE_HANDLE));
+        self.schema_data
+            .push(OwnedSchemaData::ComponentUpdate(update.schema_type));
+        self.push_op(WorkerOp::ComponentUpdate(ComponentUpdateOp {
+            entity_id,
+            update: unsafe { OpComponentUpdate::from_raw(update) },
+        }));
+    }
+
+    /// Enqueues a command request sent to the worker by another worker, and returns the ID of the
+    /// request.
+    pub fn command_request<S: Into<String>>(
+        &mut self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        caller_worker_id: S,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        let request = request
+            .into_schema_data(&self.vtables)
+            .unwrap_or_else(|| panic!("{}", UNSERIALIZABLE_HANDLE));
+        self.schema_data
+            .push(OwnedSchemaData::CommandRequest(request.schema_type));
+        self.push_op(WorkerOp::CommandRequest(CommandRequestOp {
+            request_id,
+            entity_id,
+            timeout_millis: 0,
+            caller_worker_id: caller_worker_id.into(),
+            caller_attribute_set: WorkerAttributes {
+                attribute_count: 0,
+                attributes: Vec::new(),
+            },
+            request: unsafe { OpCommandRequest::from_raw(request) },
+        }));
+        request_id
+    }
+
+    /// Enqueues the response to a command request sent by the worker. A None response reports a
+    /// failure with the given status code.
+    pub fn command_response(
+        &mut self,
+        request_id: RequestId,
+        entity_id: EntityId,
+        status_code: StatusCode,
+        response: Option<CommandResponse>,
+    ) {
+        let response = match response {
+            Some(response) => {
+                let response = response
+                    .into_schema_data(&self.vtables)
+                    .unwrap_or_else(|| panic!("{}", UNSERIALIZABLE_HANDLE));
+                self.schema_data
+                    .push(OwnedSchemaData::CommandResponse(response.schema_type));
+                response
+            }
+            None => Worker_CommandResponse {
+                reserved: std::ptr::null_mut(),
+                component_id: 0,
+                command_index: 0,
+                schema_type: std::ptr::null_mut(),
+                user_handle: std::ptr::null_mut(),
+            },
+        };
+        self.push_op(WorkerOp::CommandResponse(CommandResponseOp {
+            request_id,
+            entity_id,
+            status_code,
+            message: String::new(),
+            response: unsafe { OpCommandResponse::from_raw(response) },
+        }));
+    }
+
+    /// Enqueues the response to a create entity request sent by the worker.
+    pub fn create_entity_response(
+        &mut self,
+        request_id: RequestId,
+        status_code: StatusCode,
+        entity_id: EntityId,
+    ) {
+        self.push_op(WorkerOp::CreateEntityResponse(CreateEntityResponseOp {
+            request_id,
+            status_code,
+            message: String::new(),
+            entity_id,
+        }));
+    }
+
+    /// Enqueues a Disconnect op. The connection reports itself as disconnected from then on.
+    pub fn disconnect<S: Into<String>>(&mut self, status_code: ConnectionStatusCode, reason: S) {
+        self.connected = false;
+        self.push_op(WorkerOp::Disconnect(DisconnectOp {
+            status_code,
+            reason: reason.into(),
+        }));
+    }
+
+    /// Returns every message sent since the previous call, in the order they were sent.
+    pub fn take_sent(&self) -> Vec<SentMessage> {
+        std::mem::take(&mut *self.sent.borrow_mut())
+    }
+
+    /// Returns the updates sent since the previous call to take_sent, discarding other messages.
+    pub fn take_component_updates(&self) -> Vec<(EntityId, ComponentUpdate)> {
+        self.take_sent()
+            .into_iter()
+            .filter_map(|message| match message {
+                SentMessage::ComponentUpdate {
+                    entity_id, update, ..
+                } => Some((entity_id, update)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the log messages sent since the previous call to take_sent, discarding other
+    /// messages.
+    pub fn take_log_messages(&self) -> Vec<LogMessage> {
+        self.take_sent()
+            .into_iter()
+            .filter_map(|message| match message {
+                SentMessage::LogMessage(log_message) => Some(log_message),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn allocate_request_id(&self) -> RequestId {
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+        request_id
+    }
+
+    fn record(&self, message: SentMessage) {
+        self.sent.borrow_mut().push(message);
+    }
+
+    fn record_update(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: Option<UpdateParameters>,
+    ) -> bool {
+        self.authority.check_update(entity_id, update.component_id);
+        self.record(SentMessage::ComponentUpdate {
+            entity_id,
+            update,
+            parameters,
+        });
+        true
+    }
+
+    fn record_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        parameters: Option<CommandParameters>,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        self.record(SentMessage::CommandRequest {
+            request_id,
+            entity_id,
+            request,
+            parameters,
+        });
+        request_id
+    }
+}
+
+impl WorkerConnection for MockConnection {
+    /// Returns every op enqueued since the previous call, regardless of the timeout.
+    fn get_op_list(&mut self, _timeout_millis: u32) -> OpList {
+        let op_list = OpList::with_schema_data(
+            std::mem::take(&mut self.ops),
+            std::mem::take(&mut self.schema_data),
+        );
+        self.authority.process_ops(&op_list);
+        let mut authority_loss_handlers = std::mem::take(&mut self.authority_loss_handlers);
+        authority_loss_handlers.process_ops(&op_list, self);
+        self.authority_loss_handlers = authority_loss_handlers;
+        op_list
+    }
+
+    fn authority_tracker(&self) -> &AuthorityTracker {
+        &self.authority
+    }
+
+    fn authority_loss_handlers(&mut self) -> &mut AuthorityLossHandlers {
+        &mut self.authority_loss_handlers
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn worker_id(&self) -> String {
+        self.worker_id.clone()
+    }
+
+    fn attributes(&self) -> WorkerAttributes {
+        WorkerAttributes {
+            attribute_count: self.attributes.len() as u32,
+            attributes: self.attributes.clone(),
+        }
+    }
+
+    fn flag(&self, name: &str) -> Option<String> {
+        self.flags.get(name).cloned()
+    }
+
+    fn send_log_message(&self, log_message: LogMessage) {
+        self.record(SentMessage::LogMessage(log_message))
+    }
+
+    fn send_metrics(&self, metrics: Metrics) {
+        self.record(SentMessage::Metrics(metrics))
+    }
+
+    fn send_reserve_entity_ids_request(
+        &self,
+        number_of_entity_ids: u32,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        self.record(SentMessage::ReserveEntityIdsRequest {
+            request_id,
+            number_of_entity_ids,
+        });
+        request_id
+    }
+
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        self.record(SentMessage::CreateEntityRequest {
+            request_id,
+            components,
+            entity_id,
+        });
+        request_id
+    }
+
+    fn send_delete_entity_request(
+        &self,
+        entity_id: EntityId,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        self.record(SentMessage::DeleteEntityRequest {
+            request_id,
+            entity_id,
+        });
+        request_id
+    }
+
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let request_id = self.allocate_request_id();
+        self.record(SentMessage::EntityQueryRequest {
+            request_id,
+            entity_query,
+        });
+        request_id
+    }
+
+    fn send_component_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.record_update(entity_id, update, None)
+    }
+
+    fn send_component_update_with_parameters(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.record_update(entity_id, update, Some(parameters))
+    }
+
+    fn send_add_component(&self, entity_id: EntityId, component_data: ComponentData) -> bool {
+        self.record(SentMessage::AddComponent {
+            entity_id,
+            component_data,
+            parameters: None,
+        });
+        true
+    }
+
+    fn send_add_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.record(SentMessage::AddComponent {
+            entity_id,
+            component_data,
+            parameters: Some(parameters),
+        });
+        true
+    }
+
+    fn send_remove_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.record(SentMessage::RemoveComponent {
+            entity_id,
+            component_id,
+            parameters: None,
+        });
+        true
+    }
+
+    fn send_remove_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.record(SentMessage::RemoveComponent {
+            entity_id,
+            component_id,
+            parameters: Some(parameters),
+        });
+        true
+    }
+
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.record_command_request(entity_id, request, None)
+    }
+
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        _timeout_millis: Option<u32>,
+        parameters: CommandParameters,
+    ) -> RequestId {
+        self.record_command_request(entity_id, request, Some(parameters))
+    }
+
+    fn send_command_response(&self, request_id: RequestId, response: CommandResponse) -> bool {
+        self.record(SentMessage::CommandResponse {
+            request_id,
+            response,
+        });
+        true
+    }
+
+    fn send_command_failure(&self, request_id: RequestId, message: &str) -> bool {
+        self.record(SentMessage::CommandFailure {
+            request_id,
+            message: message.to_owned(),
+        });
+        true
+    }
+
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        self.record(SentMessage::AuthorityLossImminentAcknowledgement {
+            entity_id,
+            component_id,
+        })
+    }
+}
+
+impl Default for MockConnection {
+    fn default() -> Self {
+        Self::new("MockWorker")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Metadata, MetadataUpdate, Position};
+    use crate::schema::{self, Component};
+    use crate::worker::connection::UpdateLoopback;
+    use crate::worker::test_util::MetadataHandler;
+
+    fn describe(op: &WorkerOp) -> String {
+        match op {
+            WorkerOp::AddEntity(op) => format!("AddEntity {}", op.entity_id),
+            WorkerOp::AddComponent(op) => format!("AddComponent {}", op.entity_id),
+            WorkerOp::AuthorityChange(op) => format!(
+                "AuthorityChange {} {} {:?}",
+                op.entity_id, op.component_id, op.authority
+            ),
+            WorkerOp::CommandRequest(op) => format!(
+                "CommandRequest {} {} {}",
+                op.request_id, op.entity_id, op.caller_worker_id
+            ),
+            WorkerOp::FlagUpdate(op) => format!("FlagUpdate {} {:?}", op.name, op.value),
+            op => panic!("unexpected op {:?}", op),
+        }
+    }
+
+    #[test]
+    fn enqueued_ops_are_returned_in_order() {
+        let mut connection = MockConnection::new("worker");
+        connection.add_entity(1);
+        connection.add_component(
+            1,
+            Metadata {
+                entity_type: "tree".to_owned(),
+            }
+            .to_component_data(),
+        );
+        connection.authority_change(1, Metadata::ID, Authority::Authoritative);
+        let request = CommandRequest::new(1000, 1, schema::CommandRequest::new());
+        let request_id = connection.command_request(1, request, "client");
+        connection.set_flag("speed", Some("2".to_owned()));
+
+        let op_list = connection.get_op_list(0);
+        assert_eq!(
+            op_list.iter().map(describe).collect::<Vec<_>>(),
+            vec![
+                "AddEntity 1".to_owned(),
+                "AddComponent 1".to_owned(),
+                "AuthorityChange 1 53 Authoritative".to_owned(),
+                format!("CommandRequest {} 1 client", request_id),
+                "FlagUpdate speed Some(\"2\")".to_owned(),
+            ]
+        );
+        assert!(connection.get_op_list(0).is_empty());
+        assert_eq!(connection.flag("speed"), Some("2".to_owned()));
+    }
+
+    #[test]
+    fn sends_are_recorded_with_sequential_request_ids() {
+        let mut connection = MockConnection::new("worker");
+        let incoming = connection.command_request(
+            1,
+            CommandRequest::new(1000, 1, schema::CommandRequest::new()),
+            "client",
+        );
+        let reserve = connection.send_reserve_entity_ids_request(5, None);
+        let delete = connection.send_delete_entity_request(3, None);
+        assert_eq!((incoming, reserve, delete), (1, 2, 3));
+        assert!(connection.send_command_failure(incoming, "failed"));
+        assert!(connection.send_remove_component(3, Metadata::ID));
+
+        let sent: Vec<_> = connection
+            .take_sent()
+            .into_iter()
+            .map(|message| match message {
+                SentMessage::ReserveEntityIdsRequest {
+                    request_id,
+                    number_of_entity_ids,
+                } => format!("reserve {} {}", request_id, number_of_entity_ids),
+                SentMessage::DeleteEntityRequest {
+                    request_id,
+                    entity_id,
+                } => format!("delete {} {}", request_id, entity_id),
+                SentMessage::CommandFailure {
+                    request_id,
+                    message,
+                } => format!("failure {} {}", request_id, message),
+                SentMessage::RemoveComponent {
+                    entity_id,
+                    component_id,
+                    ..
+                } => format!("remove {} {}", entity_id, component_id),
+                _ => panic!("unexpected message"),
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                "reserve 2 5".to_owned(),
+                "delete 3 3".to_owned(),
+                "failure 1 failed".to_owned(),
+                "remove 3 53".to_owned(),
+            ]
+        );
+        assert!(connection.take_sent().is_empty());
+    }
+
+    #[test]
+    fn sent_messages_record_their_parameters() {
+        let connection = MockConnection::new("worker");
+        let parameters = UpdateParameters {
+            loopback: UpdateLoopback::None,
+        };
+        let metadata = Metadata {
+            entity_type: "tree".to_owned(),
+        };
+        connection.send_add_component(1, metadata.to_component_data());
+        connection.send_add_component_with_parameters(1, metadata.to_component_data(), parameters);
+        connection.send_remove_component_with_parameters(1, Position::ID, parameters);
+        connection.send_component_update_with_parameters(
+            1,
+            ComponentUpdate::new(Metadata::ID, metadata.to_data().into_update()),
+            parameters,
+        );
+        let request_id = connection.send_command_request_with_parameters(
+            1,
+            CommandRequest::new(Metadata::ID, 1, schema::CommandRequest::new()),
+            None,
+            CommandParameters {
+                allow_short_circuit: true,
+            },
+        );
+
+        let recorded: Vec<_> = connection
+            .take_sent()
+            .into_iter()
+            .map(|message| match message {
+                SentMessage::AddComponent { parameters, .. }
+                | SentMessage::RemoveComponent { parameters, .. }
+                | SentMessage::ComponentUpdate { parameters, .. } => {
+                    format!("{:?}", parameters)
+                }
+                SentMessage::CommandRequest {
+                    request_id: id,
+                    parameters,
+                    ..
+                } => {
+                    assert_eq!(id, request_id);
+                    format!("{:?}", parameters)
+                }
+                _ => panic!("unexpected message"),
+            })
+            .collect();
+        let none = "Some(UpdateParameters { loopback: None })".to_owned();
+        assert_eq!(
+            recorded,
+            vec![
+                "None".to_owned(),
+                none.clone(),
+                none.clone(),
+                none,
+                "Some(CommandParameters { allow_short_circuit: true })".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn enqueued_user_handles_are_serialized_through_the_vtables() {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<Metadata>();
+        registry.register_handler::<MetadataHandler>().unwrap();
+        let mut connection = MockConnection::new("worker");
+        connection.set_component_vtables(&registry);
+        connection.add_component(
+            1,
+            ComponentData::from_handle::<MetadataHandler>(Metadata {
+                entity_type: "tree".to_owned(),
+            }),
+        );
+        connection.component_update(
+            1,
+            ComponentUpdate::from_handle::<MetadataHandler>(MetadataUpdate {
+                entity_type: Some("rock".to_owned()),
+            }),
+        );
+        connection.command_request(
+            1,
+            CommandRequest::from_handle::<MetadataHandler>(1, "ping".to_owned()),
+            "client",
+        );
+
+        let op_list = connection.get_op_list(0);
+        let ops: Vec<_> = op_list.iter().collect();
+        match ops.as_slice() {
+            [WorkerOp::AddComponent(add), WorkerOp::ComponentUpdate(update), WorkerOp::CommandRequest(request)] =>
+            {
+                assert_eq!(add.data.read::<Metadata>().unwrap().entity_type, "tree");
+                assert_eq!(
+                    update.update.read::<Metadata>().unwrap().entity_type,
+                    Some("rock".to_owned())
+                );
+                assert_eq!(request.request.get_object().unwrap().get_string(1), "ping");
+            }
+            ops => panic!("unexpected ops {:?}", ops),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "set_component_vtables")]
+    fn enqueued_user_handles_need_a_vtable() {
+        let mut connection = MockConnection::new("worker");
+        connection.add_component(
+            1,
+            ComponentData::from_handle::<MetadataHandler>(Metadata::default()),
+        );
+    }
+}
@@ -1,22 +1,43 @@
 use spatialos_sys::{
     Worker_ApiVersion, Worker_ApiVersionStr, Worker_Authority, Worker_CommandIndex,
-    Worker_CommandRequestHandle, Worker_CommandResponseHandle, Worker_ComponentData,
-    Worker_ComponentDataHandle, Worker_ComponentId, Worker_ComponentUpdateHandle,
+    Worker_CommandRequest, Worker_CommandRequestHandle, Worker_CommandResponse,
+    Worker_CommandResponseHandle, Worker_ComponentData, Worker_ComponentDataHandle,
+    Worker_ComponentId, Worker_ComponentUpdate, Worker_ComponentUpdateHandle,
     Worker_ConnectionStatusCode, Worker_Entity, Worker_EntityId, Worker_EntityQuery,
     Worker_LogLevel, Worker_RequestId, Worker_ResultType, Worker_StatusCode,
     Worker_WorkerAttributes,
 };
 
+#[cfg(feature = "tokio")]
+pub mod async_connection;
+pub mod authority;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+pub mod component_registry;
 pub mod component_vtable;
 pub mod connection;
 pub mod constraint;
+pub mod dispatcher;
+pub mod ecs;
+pub mod flags;
 pub mod log_message;
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub mod logging;
 pub mod metrics;
+pub mod mock;
 pub mod op;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod replay;
+pub mod shared;
+pub mod simulation;
+#[cfg(test)]
+mod test_util;
 
 use crate::{const_to_string, worker::constraint::EntityIdConstraint};
 use crate::{const_to_vector, schema};
 use crate::{vector_to_owned_array, worker::constraint::Constraint};
+use component_vtable::{ComponentHandler, ComponentVtable, UserHandle};
 use std::ffi::CStr;
 use std::os::raw::c_void;
 
@@ -87,7 +108,7 @@ impl Into<u8> for LogLevel {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Enum defining the possible authority states for an entity component.
 pub enum Authority {
     NotAuthoritative,
@@ -249,23 +270,13 @@ impl From<Worker_WorkerAttributes> for WorkerAttributes {
                 attributes: Vec::new(),
             }
         } else {
-            let attributes = unsafe {
-                let index = 0;
-                let mut attributes = Vec::new();
-                loop {
-                    let char_ptr = worker_attributes.attributes.offset(index);
-                    if (*char_ptr).is_null() {
-                        break;
-                    } else {
-                        let attribute = CStr::from_ptr(*char_ptr)
-                            .to_str()
-                            .map(|s| s.to_owned())
-                            .unwrap();
-                        attributes.push(attribute);
-                    }
-                }
-                attributes
-            };
+            let attributes = (0..worker_attributes.attribute_count as isize)
+                .map(|index| unsafe {
+                    CStr::from_ptr(*worker_attributes.attributes.offset(index))
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
             Self {
                 attribute_count: worker_attributes.attribute_count,
                 attributes,
@@ -405,17 +416,19 @@ impl Into<Worker_Entity> for Entity {
 pub struct ComponentData {
     pub reserved: *mut c_void,
     pub component_id: ComponentId,
-    pub schema_type: schema::ComponentData,
-    pub user_handle: *mut ComponentDataHandle,
+    pub schema_type: Option<schema::ComponentData>,
+    pub user_handle: Option<UserHandle>,
 }
 
+/// Takes ownership of the schema data and user handle, which must not be released elsewhere. The
+/// user handle must have been created by UserHandle or by the vtable of a ComponentHandler.
 impl From<Worker_ComponentData> for ComponentData {
     fn from(data: Worker_ComponentData) -> Self {
         Self {
             reserved: data.reserved,
             component_id: data.component_id,
-            schema_type: data.schema_type.into(),
-            user_handle: data.user_handle,
+            schema_type: unsafe { schema_from_raw(data.schema_type) },
+            user_handle: unsafe { handle_from_raw(data.user_handle) },
         }
     }
 }
@@ -425,8 +438,379 @@ impl Into<Worker_ComponentData> for ComponentData {
         Worker_ComponentData {
             reserved: self.reserved,
             component_id: self.component_id,
-            schema_type: self.schema_type.into(),
-            user_handle: self.user_handle,
+            schema_type: self.schema_type.map_or(std::ptr::null_mut(), Into::into),
+            user_handle: self
+                .user_handle
+                .map_or(std::ptr::null_mut(), UserHandle::into_raw),
+        }
+    }
+}
+
+impl ComponentData {
+    /// Creates component data backed by the given schema data, without a user handle.
+    pub fn new(component_id: ComponentId, schema_type: schema::ComponentData) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id,
+            schema_type: Some(schema_type),
+            user_handle: None,
+        }
+    }
+
+    /// Creates component data backed by a user handle of the given handler, which the SDK
+    /// serializes once sent.
+    pub fn from_handle<H: ComponentHandler>(data: H::Data) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id: H::ID,
+            schema_type: None,
+            user_handle: Some(UserHandle::new(data)),
+        }
+    }
+
+    /// Converts the data for connections which are not backed by the SDK. A user handle is
+    /// serialized through the vtable of its component, and None is returned if there is none.
+    pub(crate) fn into_schema_data(
+        mut self,
+        vtables: &[ComponentVtable],
+    ) -> Option<Worker_ComponentData> {
+        if self.schema_type.is_none() {
+            let handle = self.user_handle.as_ref()?;
+            let vtable = find_vtable(vtables, self.component_id)?;
+            let serialize = vtable.component_data_serialize?;
+            let mut schema_type = std::ptr::null_mut();
+            unsafe {
+                serialize(
+                    self.component_id,
+                    vtable.user_data,
+                    handle.as_ptr(),
+                    &mut schema_type,
+                )
+            };
+            self.schema_type = unsafe { schema_from_raw(schema_type) };
+        }
+        self.user_handle = None;
+        Some(self.into())
+    }
+}
+
+/// An object used to represent a component update by either raw schema data or some user-defined
+/// handle type.
+#[derive(Debug, Clone)]
+pub struct ComponentUpdate {
+    pub reserved: *mut c_void,
+    pub component_id: ComponentId,
+    pub schema_type: Option<schema::ComponentUpdate>,
+    pub user_handle: Option<UserHandle>,
+}
+
+/// Takes ownership of the schema data and user handle, which must not be released elsewhere. The
+/// user handle must have been created by UserHandle or by the vtable of a ComponentHandler.
+impl From<Worker_ComponentUpdate> for ComponentUpdate {
+    fn from(update: Worker_ComponentUpdate) -> Self {
+        Self {
+            reserved: update.reserved,
+            component_id: update.component_id,
+            schema_type: unsafe { schema_from_raw(update.schema_type) },
+            user_handle: unsafe { handle_from_raw(update.user_handle) },
+        }
+    }
+}
+
+impl From<ComponentUpdate> for Worker_ComponentUpdate {
+    fn from(update: ComponentUpdate) -> Self {
+        Worker_ComponentUpdate {
+            reserved: update.reserved,
+            component_id: update.component_id,
+            schema_type: update.schema_type.map_or(std::ptr::null_mut(), Into::into),
+            user_handle: update
+                .user_handle
+                .map_or(std::ptr::null_mut(), UserHandle::into_raw),
+        }
+    }
+}
+
+impl ComponentUpdate {
+    /// Creates a component update backed by the given schema data, without a user handle.
+    pub fn new(component_id: ComponentId, schema_type: schema::ComponentUpdate) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id,
+            schema_type: Some(schema_type),
+            user_handle: None,
+        }
+    }
+
+    /// Creates a component update backed by a user handle of the given handler, which the SDK
+    /// serializes once sent.
+    pub fn from_handle<H: ComponentHandler>(update: H::Update) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id: H::ID,
+            schema_type: None,
+            user_handle: Some(UserHandle::new(update)),
+        }
+    }
+
+    /// Converts the update for connections which are not backed by the SDK. A user handle is
+    /// serialized through the vtable of its component, and None is returned if there is none.
+    pub(crate) fn into_schema_data(
+        mut self,
+        vtables: &[ComponentVtable],
+    ) -> Option<Worker_ComponentUpdate> {
+        if self.schema_type.is_none() {
+            let handle = self.user_handle.as_ref()?;
+            let vtable = find_vtable(vtables, self.component_id)?;
+            let serialize = vtable.component_update_serialize?;
+            let mut schema_type = std::ptr::null_mut();
+            unsafe {
+                serialize(
+                    self.component_id,
+                    vtable.user_data,
+                    handle.as_ptr(),
+                    &mut schema_type,
+                )
+            };
+            self.schema_type = unsafe { schema_from_raw(schema_type) };
+        }
+        self.user_handle = None;
+        Some(self.into())
+    }
+}
+
+/// An object used to represent a command request by either raw schema data or some user-defined
+/// handle type.
+#[derive(Debug, Clone)]
+pub struct CommandRequest {
+    pub reserved: *mut c_void,
+    pub component_id: ComponentId,
+    pub command_index: CommandIndex,
+    pub schema_type: Option<schema::CommandRequest>,
+    pub user_handle: Option<UserHandle>,
+}
+
+/// Takes ownership of the schema data and user handle, which must not be released elsewhere. The
+/// user handle must have been created by UserHandle or by the vtable of a ComponentHandler.
+impl From<Worker_CommandRequest> for CommandRequest {
+    fn from(request: Worker_CommandRequest) -> Self {
+        Self {
+            reserved: request.reserved,
+            component_id: request.component_id,
+            command_index: request.command_index,
+            schema_type: unsafe { schema_from_raw(request.schema_type) },
+            user_handle: unsafe { handle_from_raw(request.user_handle) },
+        }
+    }
+}
+
+impl From<CommandRequest> for Worker_CommandRequest {
+    fn from(request: CommandRequest) -> Self {
+        Worker_CommandRequest {
+            reserved: request.reserved,
+            component_id: request.component_id,
+            command_index: request.command_index,
+            schema_type: request.schema_type.map_or(std::ptr::null_mut(), Into::into),
+            user_handle: request
+                .user_handle
+                .map_or(std::ptr::null_mut(), UserHandle::into_raw),
+        }
+    }
+}
+
+impl CommandRequest {
+    /// Creates a command request backed by the given schema data, without a user handle.
+    pub fn new(
+        component_id: ComponentId,
+        command_index: CommandIndex,
+        schema_type: schema::CommandRequest,
+    ) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id,
+            command_index,
+            schema_type: Some(schema_type),
+            user_handle: None,
+        }
+    }
+
+    /// Creates a command request backed by a user handle of the given handler, which the SDK
+    /// serializes once sent.
+    pub fn from_handle<H: ComponentHandler>(
+        command_index: CommandIndex,
+        request: H::CommandRequest,
+    ) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id: H::ID,
+            command_index,
+            schema_type: None,
+            user_handle: Some(UserHandle::new(request)),
+        }
+    }
+
+    /// Converts the request for connections which are not backed by the SDK. A user handle is
+    /// serialized through the vtable of its component, and None is returned if there is none.
+    pub(crate) fn into_schema_data(
+        mut self,
+        vtables: &[ComponentVtable],
+    ) -> Option<Worker_CommandRequest> {
+        if self.schema_type.is_none() {
+            let handle = self.user_handle.as_ref()?;
+            let vtable = find_vtable(vtables, self.component_id)?;
+            let serialize = vtable.command_request_serialize?;
+            let mut schema_type = std::ptr::null_mut();
+            unsafe {
+                serialize(
+                    self.component_id,
+                    self.command_index,
+                    vtable.user_data,
+                    handle.as_ptr(),
+                    &mut schema_type,
+                )
+            };
+            self.schema_type = unsafe { schema_from_raw(schema_type) };
+        }
+        self.user_handle = None;
+        Some(self.into())
+    }
+}
+
+/// An object used to represent a command response by either raw schema data or some user-defined
+/// handle type.
+#[derive(Debug, Clone)]
+pub struct CommandResponse {
+    pub reserved: *mut c_void,
+    pub component_id: ComponentId,
+    pub command_index: CommandIndex,
+    pub schema_type: Option<schema::CommandResponse>,
+    pub user_handle: Option<UserHandle>,
+}
+
+// The reserved pointers are never dereferenced, and the schema data and user handles are owned by
+// the values, so they can be moved to other threads.
+unsafe impl Send for ComponentData {}
+unsafe impl Send for ComponentUpdate {}
+unsafe impl Send for CommandRequest {}
+unsafe impl Send for CommandResponse {}
+
+/// Takes ownership of the schema data and user handle, which must not be released elsewhere. The
+/// user handle must have been created by UserHandle or by the vtable of a ComponentHandler.
+impl From<Worker_CommandResponse> for CommandResponse {
+    fn from(response: Worker_CommandResponse) -> Self {
+        Self {
+            reserved: response.reserved,
+            component_id: response.component_id,
+            command_index: response.command_index,
+            schema_type: unsafe { schema_from_raw(response.schema_type) },
+            user_handle: unsafe { handle_from_raw(response.user_handle) },
         }
     }
 }
+
+impl From<CommandResponse> for Worker_CommandResponse {
+    fn from(response: CommandResponse) -> Self {
+        Worker_CommandResponse {
+            reserved: response.reserved,
+            component_id: response.component_id,
+            command_index: response.command_index,
+            schema_type: response
+                .schema_type
+                .map_or(std::ptr::null_mut(), Into::into),
+            user_handle: response
+                .user_handle
+                .map_or(std::ptr::null_mut(), UserHandle::into_raw),
+        }
+    }
+}
+
+impl CommandResponse {
+    /// Creates a command response backed by the given schema data, without a user handle.
+    pub fn new(
+        component_id: ComponentId,
+        command_index: CommandIndex,
+        schema_type: schema::CommandResponse,
+    ) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id,
+            command_index,
+            schema_type: Some(schema_type),
+            user_handle: None,
+        }
+    }
+
+    /// Creates a command response backed by a user handle of the given handler, which the SDK
+    /// serializes once sent.
+    pub fn from_handle<H: ComponentHandler>(
+        command_index: CommandIndex,
+        response: H::CommandResponse,
+    ) -> Self {
+        Self {
+            reserved: std::ptr::null_mut(),
+            component_id: H::ID,
+            command_index,
+            schema_type: None,
+            user_handle: Some(UserHandle::new(response)),
+        }
+    }
+
+    /// Converts the response for connections which are not backed by the SDK. A user handle is
+    /// serialized through the vtable of its component, and None is returned if there is none.
+    pub(crate) fn into_schema_data(
+        mut self,
+        vtables: &[ComponentVtable],
+    ) -> Option<Worker_CommandResponse> {
+        if self.schema_type.is_none() {
+            let handle = self.user_handle.as_ref()?;
+            let vtable = find_vtable(vtables, self.component_id)?;
+            let serialize = vtable.command_response_serialize?;
+            let mut schema_type = std::ptr::null_mut();
+            unsafe {
+                serialize(
+                    self.component_id,
+                    self.command_index,
+                    vtable.user_data,
+                    handle.as_ptr(),
+                    &mut schema_type,
+                )
+            };
+            self.schema_type = unsafe { schema_from_raw(schema_type) };
+        }
+        self.user_handle = None;
+        Some(self.into())
+    }
+}
+
+/// Returns the vtable of a component, among the vtables given to a connection which is not backed
+/// by the SDK.
+fn find_vtable(vtables: &[ComponentVtable], component_id: ComponentId) -> Option<&ComponentVtable> {
+    vtables
+        .iter()
+        .find(|vtable| vtable.component_id == component_id)
+}
+
+/// Takes ownership of schema data passed by the SDK, if any.
+///
+/// # Safety
+///
+/// The data must not be destroyed elsewhere.
+unsafe fn schema_from_raw<R, W: From<*mut R>>(schema_type: *mut R) -> Option<W> {
+    if schema_type.is_null() {
+        None
+    } else {
+        Some(W::from(schema_type))
+    }
+}
+
+/// Takes ownership of a user handle passed by the SDK, if any.
+///
+/// # Safety
+///
+/// See UserHandle::from_raw.
+unsafe fn handle_from_raw(user_handle: *mut c_void) -> Option<UserHandle> {
+    if user_handle.is_null() {
+        None
+    } else {
+        Some(UserHandle::from_raw(user_handle))
+    }
+}
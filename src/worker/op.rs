@@ -7,28 +7,105 @@ use spatialos_sys::{
     Worker_ReserveEntityIdsResponseOp,
 };
 
+use spatialos_sys::{
+    Schema_CommandRequest, Schema_CommandResponse, Schema_ComponentData, Schema_ComponentUpdate,
+    Schema_DestroyCommandRequest, Schema_DestroyCommandResponse, Schema_DestroyComponentData,
+    Schema_DestroyComponentUpdate,
+};
+
 use spatialos_sys::{
     Worker_CommandRequest, Worker_CommandRequestOp, Worker_CommandResponse, Worker_ComponentData,
-    Worker_ComponentUpdate,
+    Worker_ComponentUpdate, Worker_Entity,
 };
 
 use crate::const_to_vector;
+use crate::schema::{Component, ObjectRef};
+use crate::worker::component_vtable::downcast_handle;
 use crate::worker::metrics::Metrics;
+use crate::worker::shared::{
+    self, SharedCommandRequest, SharedCommandResponse, SharedComponentData, SharedComponentUpdate,
+};
 use crate::worker::Authority;
+use crate::worker::CommandIndex;
+use crate::worker::CommandRequestHandle;
+use crate::worker::CommandResponseHandle;
+use crate::worker::ComponentDataHandle;
 use crate::worker::ComponentId;
+use crate::worker::ComponentUpdateHandle;
 use crate::worker::ConnectionStatusCode;
-use crate::worker::Entity;
 use crate::worker::EntityId;
 use crate::worker::LogLevel;
 use crate::worker::RequestId;
 use crate::worker::StatusCode;
 use crate::worker::WorkerAttributes;
 use std::ffi::CStr;
+use std::marker::PhantomData;
 
-/// An op list, usually returned by Connectio::get_op_list.
+/// An op list, usually returned by Connection::get_op_list. The data of the ops is owned by the
+/// list, and can only be kept beyond its lifetime through WorkerOp::into_owned.
 pub struct OpList {
-    pub ops: Vec<WorkerOp>,
+    ops: Vec<WorkerOp<'static>>,
     inner: *mut Worker_OpList,
+    schema_data: Vec<OwnedSchemaData>,
+}
+
+/// Schema data referenced by the ops of a list which is not backed by the SDK. It is destroyed
+/// along with the list, like the data of an SDK op list.
+pub(crate) enum OwnedSchemaData {
+    ComponentData(*mut Schema_ComponentData),
+    ComponentUpdate(*mut Schema_ComponentUpdate),
+    CommandRequest(*mut Schema_CommandRequest),
+    CommandResponse(*mut Schema_CommandResponse),
+}
+
+/// The schema data is owned exclusively by the list holding it.
+unsafe impl Send for OwnedSchemaData {}
+
+impl OpList {
+    /// Creates an op list which is not backed by the SDK. Any schema data referenced by the ops
+    /// remains owned by the caller, which must keep it alive and unused by other threads for as long
+    /// as the ops borrow it, as required by the from_raw functions creating them.
+    pub fn new(ops: Vec<WorkerOp<'static>>) -> Self {
+        Self::with_schema_data(ops, Vec::new())
+    }
+
+    pub(crate) fn with_schema_data(
+        ops: Vec<WorkerOp<'static>>,
+        schema_data: Vec<OwnedSchemaData>,
+    ) -> Self {
+        Self {
+            ops,
+            inner: std::ptr::null_mut(),
+            schema_data,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, WorkerOp<'_>> {
+        self.ops.iter()
+    }
+
+    /// Takes the ops out of the list. The ops still borrow the data of the list, which must
+    /// outlive them unless they are converted with WorkerOp::into_owned.
+    pub fn drain(&mut self) -> std::vec::Drain<'_, WorkerOp<'_>> {
+        self.ops.drain(..)
+    }
+}
+
+impl<'a> IntoIterator for &'a OpList {
+    type Item = &'a WorkerOp<'a>;
+    type IntoIter = std::slice::Iter<'a, WorkerOp<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 impl From<*mut Worker_OpList> for OpList {
@@ -37,26 +114,45 @@ impl From<*mut Worker_OpList> for OpList {
             let mut ops = Vec::new();
             for index in 0..(*op_list).op_count as isize {
                 let op_ptr = (*op_list).ops.offset(index as isize);
-                ops.push(WorkerOp::from(*op_ptr));
+                ops.push(WorkerOp::from_raw(*op_ptr));
             }
             ops
         };
         Self {
             ops,
             inner: op_list,
+            schema_data: Vec::new(),
         }
     }
 }
 
 impl Drop for OpList {
     fn drop(&mut self) {
-        unsafe { Worker_OpList_Destroy(self.inner) }
+        if !self.inner.is_null() {
+            unsafe { Worker_OpList_Destroy(self.inner) }
+        }
+        for schema_data in self.schema_data.drain(..) {
+            unsafe {
+                match schema_data {
+                    OwnedSchemaData::ComponentData(data) => Schema_DestroyComponentData(data),
+                    OwnedSchemaData::ComponentUpdate(update) => {
+                        Schema_DestroyComponentUpdate(update)
+                    }
+                    OwnedSchemaData::CommandRequest(request) => {
+                        Schema_DestroyCommandRequest(request)
+                    }
+                    OwnedSchemaData::CommandResponse(response) => {
+                        Schema_DestroyCommandResponse(response)
+                    }
+                }
+            }
+        }
     }
 }
 
 #[derive(Debug)]
 /// Data for a single op contained within an op list.
-pub enum WorkerOp {
+pub enum WorkerOp<'a> {
     Disconnect(DisconnectOp),
     FlagUpdate(FlagUpdateOp),
     LogMessage(LogMessageOp),
@@ -67,79 +163,103 @@ pub enum WorkerOp {
     ReserveEntityIdsResponse(ReserveEntityIdsResponseOp),
     CreateEntityResponse(CreateEntityResponseOp),
     DeleteEntityResponse(DeleteEntityResponseOp),
-    EntityQueryResponse(EntityQueryResponseOp),
-    AddComponent(AddComponentOp),
+    EntityQueryResponse(EntityQueryResponseOp<'a>),
+    AddComponent(AddComponentOp<'a>),
     RemoveComponent(RemoveComponentOp),
     AuthorityChange(AuthorityChangeOp),
-    ComponentUpdate(ComponentUpdateOp),
-    CommandRequest(CommandRequestOp),
-    CommandResponse(CommandResponseOp),
+    ComponentUpdate(ComponentUpdateOp<'a>),
+    CommandRequest(CommandRequestOp<'a>),
+    CommandResponse(CommandResponseOp<'a>),
 }
 
-impl From<Worker_Op> for WorkerOp {
-    fn from(op: Worker_Op) -> Self {
+impl<'a> WorkerOp<'a> {
+    /// Creates an op borrowing the data referenced by a raw op.
+    ///
+    /// # Safety
+    ///
+    /// The op must be valid, and the data it references must outlive the returned op, as it does
+    /// when the op belongs to an op list which outlives it. Ops created with a 'static lifetime can
+    /// be sent to another thread, so their data must not be used by any other thread until they
+    /// are dropped.
+    pub unsafe fn from_raw(op: Worker_Op) -> Self {
         match Worker_OpType::from(op.op_type as u8) {
             Worker_OpType::WORKER_OP_TYPE_DISCONNECT => {
-                Self::Disconnect(DisconnectOp::from(unsafe { op.op.disconnect }))
+                Self::Disconnect(DisconnectOp::from(op.op.disconnect))
             }
             Worker_OpType::WORKER_OP_TYPE_FLAG_UPDATE => {
-                Self::FlagUpdate(FlagUpdateOp::from(unsafe { op.op.flag_update }))
+                Self::FlagUpdate(FlagUpdateOp::from(op.op.flag_update))
             }
             Worker_OpType::WORKER_OP_TYPE_LOG_MESSAGE => {
-                Self::LogMessage(LogMessageOp::from(unsafe { op.op.log_message }))
-            }
-            Worker_OpType::WORKER_OP_TYPE_METRICS => {
-                Self::Metrics(MetricsOp::from(unsafe { op.op.metrics }))
+                Self::LogMessage(LogMessageOp::from(op.op.log_message))
             }
+            Worker_OpType::WORKER_OP_TYPE_METRICS => Self::Metrics(MetricsOp::from(op.op.metrics)),
             Worker_OpType::WORKER_OP_TYPE_CRITICAL_SECTION => {
-                Self::CriticalSection(CriticalSectionOp::from(unsafe { op.op.critical_section }))
+                Self::CriticalSection(CriticalSectionOp::from(op.op.critical_section))
             }
             Worker_OpType::WORKER_OP_TYPE_ADD_ENTITY => {
-                Self::AddEntity(AddEntityOp::from(unsafe { op.op.add_entity }))
+                Self::AddEntity(AddEntityOp::from(op.op.add_entity))
             }
             Worker_OpType::WORKER_OP_TYPE_REMOVE_ENTITY => {
-                Self::RemoveEntity(RemoveEntityOp::from(unsafe { op.op.remove_entity }))
+                Self::RemoveEntity(RemoveEntityOp::from(op.op.remove_entity))
             }
             Worker_OpType::WORKER_OP_TYPE_RESERVE_ENTITY_IDS_RESPONSE => {
-                Self::ReserveEntityIdsResponse(ReserveEntityIdsResponseOp::from(unsafe {
-                    op.op.reserve_entity_ids_response
-                }))
-            }
-            Worker_OpType::WORKER_OP_TYPE_CREATE_ENTITY_RESPONSE => {
-                Self::CreateEntityResponse(CreateEntityResponseOp::from(unsafe {
-                    op.op.create_entity_response
-                }))
-            }
-            Worker_OpType::WORKER_OP_TYPE_DELETE_ENTITY_RESPONSE => {
-                Self::DeleteEntityResponse(DeleteEntityResponseOp::from(unsafe {
-                    op.op.delete_entity_response
-                }))
-            }
-            Worker_OpType::WORKER_OP_TYPE_ENTITY_QUERY_RESPONSE => {
-                Self::EntityQueryResponse(EntityQueryResponseOp::from(unsafe {
-                    op.op.entity_query_response
-                }))
+                Self::ReserveEntityIdsResponse(ReserveEntityIdsResponseOp::from(
+                    op.op.reserve_entity_ids_response,
+                ))
             }
+            Worker_OpType::WORKER_OP_TYPE_CREATE_ENTITY_RESPONSE => Self::CreateEntityResponse(
+                CreateEntityResponseOp::from(op.op.create_entity_response),
+            ),
+            Worker_OpType::WORKER_OP_TYPE_DELETE_ENTITY_RESPONSE => Self::DeleteEntityResponse(
+                DeleteEntityResponseOp::from(op.op.delete_entity_response),
+            ),
+            Worker_OpType::WORKER_OP_TYPE_ENTITY_QUERY_RESPONSE => Self::EntityQueryResponse(
+                EntityQueryResponseOp::from_raw(op.op.entity_query_response),
+            ),
             Worker_OpType::WORKER_OP_TYPE_ADD_COMPONENT => {
-                Self::AddComponent(AddComponentOp::from(unsafe { op.op.add_component }))
+                Self::AddComponent(AddComponentOp::from_raw(op.op.add_component))
             }
             Worker_OpType::WORKER_OP_TYPE_REMOVE_COMPONENT => {
-                Self::RemoveComponent(RemoveComponentOp::from(unsafe { op.op.remove_component }))
+                Self::RemoveComponent(RemoveComponentOp::from(op.op.remove_component))
             }
             Worker_OpType::WORKER_OP_TYPE_AUTHORITY_CHANGE => {
-                Self::AuthorityChange(AuthorityChangeOp::from(unsafe { op.op.authority_change }))
+                Self::AuthorityChange(AuthorityChangeOp::from(op.op.authority_change))
             }
             Worker_OpType::WORKER_OP_TYPE_COMPONENT_UPDATE => {
-                Self::ComponentUpdate(ComponentUpdateOp::from(unsafe { op.op.component_update }))
+                Self::ComponentUpdate(ComponentUpdateOp::from_raw(op.op.component_update))
             }
             Worker_OpType::WORKER_OP_TYPE_COMMAND_REQUEST => {
-                Self::CommandRequest(CommandRequestOp::from(unsafe { op.op.command_request }))
+                Self::CommandRequest(CommandRequestOp::from_raw(op.op.command_request))
             }
             Worker_OpType::WORKER_OP_TYPE_COMMAND_RESPONSE => {
-                Self::CommandResponse(CommandResponseOp::from(unsafe { op.op.command_response }))
+                Self::CommandResponse(CommandResponseOp::from_raw(op.op.command_response))
             }
         }
     }
+
+    /// Converts the op into one which no longer borrows its op list, acquiring a reference to the
+    /// data of the op.
+    pub fn into_owned(self) -> WorkerOp<'static> {
+        match self {
+            Self::Disconnect(op) => WorkerOp::Disconnect(op),
+            Self::FlagUpdate(op) => WorkerOp::FlagUpdate(op),
+            Self::LogMessage(op) => WorkerOp::LogMessage(op),
+            Self::Metrics(op) => WorkerOp::Metrics(op),
+            Self::CriticalSection(op) => WorkerOp::CriticalSection(op),
+            Self::AddEntity(op) => WorkerOp::AddEntity(op),
+            Self::RemoveEntity(op) => WorkerOp::RemoveEntity(op),
+            Self::ReserveEntityIdsResponse(op) => WorkerOp::ReserveEntityIdsResponse(op),
+            Self::CreateEntityResponse(op) => WorkerOp::CreateEntityResponse(op),
+            Self::DeleteEntityResponse(op) => WorkerOp::DeleteEntityResponse(op),
+            Self::EntityQueryResponse(op) => WorkerOp::EntityQueryResponse(op.into_owned()),
+            Self::AddComponent(op) => WorkerOp::AddComponent(op.into_owned()),
+            Self::RemoveComponent(op) => WorkerOp::RemoveComponent(op),
+            Self::AuthorityChange(op) => WorkerOp::AuthorityChange(op),
+            Self::ComponentUpdate(op) => WorkerOp::ComponentUpdate(op.into_owned()),
+            Self::CommandRequest(op) => WorkerOp::CommandRequest(op.into_owned()),
+            Self::CommandResponse(op) => WorkerOp::CommandResponse(op.into_owned()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -154,9 +274,8 @@ pub struct DisconnectOp {
 impl From<Worker_DisconnectOp> for DisconnectOp {
     fn from(op: Worker_DisconnectOp) -> Self {
         let reason = unsafe { CStr::from_ptr(op.reason) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             status_code: ConnectionStatusCode::from(op.connection_status_code),
             reason,
@@ -178,9 +297,8 @@ pub struct LogMessageOp {
 impl From<Worker_LogMessageOp> for LogMessageOp {
     fn from(op: Worker_LogMessageOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             level: LogLevel::from(op.level),
             message,
@@ -190,19 +308,31 @@ impl From<Worker_LogMessageOp> for LogMessageOp {
 
 #[derive(Debug)]
 /// Data for an AddComponent operation.
-pub struct AddComponentOp {
+pub struct AddComponentOp<'a> {
     /// The ID of the entity for which a component was added.
     pub entity_id: EntityId,
     /// The initial data for the new component. Deserialized with the corresponding vtable deserialize
     /// function and freed with the vtable free function when the OpList is destroyed.
-    pub data: Worker_ComponentData,
+    pub data: OpComponentData<'a>,
 }
 
-impl From<Worker_AddComponentOp> for AddComponentOp {
-    fn from(op: Worker_AddComponentOp) -> Self {
+impl<'a> AddComponentOp<'a> {
+    /// Creates an op borrowing the data referenced by the raw op.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the op must outlive the returned op.
+    pub unsafe fn from_raw(op: Worker_AddComponentOp) -> Self {
         Self {
             entity_id: op.entity_id,
-            data: op.data,
+            data: OpComponentData::from_raw(op.data),
+        }
+    }
+
+    pub fn into_owned(self) -> AddComponentOp<'static> {
+        AddComponentOp {
+            entity_id: self.entity_id,
+            data: self.data.into_owned(),
         }
     }
 }
@@ -245,7 +375,7 @@ impl From<Worker_AuthorityChangeOp> for AuthorityChangeOp {
 
 #[derive(Debug)]
 /// Data for a CommandRequest operation.
-pub struct CommandRequestOp {
+pub struct CommandRequestOp<'a> {
     /// The incoming command request ID.
     pub request_id: RequestId,
     /// The ID of the entity for which there was a command request.
@@ -258,29 +388,44 @@ pub struct CommandRequestOp {
     pub caller_attribute_set: WorkerAttributes,
     /// The command request data. Deserialized with the corresponding vtable deserialize function and
     /// freed with the vtable free function when the OpList is destroyed.
-    pub request: Worker_CommandRequest,
+    pub request: OpCommandRequest<'a>,
 }
 
-impl From<Worker_CommandRequestOp> for CommandRequestOp {
-    fn from(op: Worker_CommandRequestOp) -> Self {
+impl<'a> CommandRequestOp<'a> {
+    /// Creates an op borrowing the data referenced by the raw op.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the op must outlive the returned op.
+    pub unsafe fn from_raw(op: Worker_CommandRequestOp) -> Self {
         let caller_worker_id = unsafe { CStr::from_ptr(op.caller_worker_id) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             request_id: op.request_id,
             entity_id: op.entity_id,
             timeout_millis: op.timeout_millis,
             caller_attribute_set: WorkerAttributes::from(op.caller_attribute_set),
-            request: op.request,
+            request: OpCommandRequest::from_raw(op.request),
             caller_worker_id,
         }
     }
+
+    pub fn into_owned(self) -> CommandRequestOp<'static> {
+        CommandRequestOp {
+            request_id: self.request_id,
+            entity_id: self.entity_id,
+            timeout_millis: self.timeout_millis,
+            caller_worker_id: self.caller_worker_id,
+            caller_attribute_set: self.caller_attribute_set,
+            request: self.request.into_owned(),
+        }
+    }
 }
 
 #[derive(Debug)]
 /// Data for a CommandResponse operation.
-pub struct CommandResponseOp {
+pub struct CommandResponseOp<'a> {
     /// The ID of the command request for which there was a command response.
     pub request_id: RequestId,
     /// The ID of the entity originally targeted by the command request.
@@ -291,40 +436,66 @@ pub struct CommandResponseOp {
     pub message: String,
     /// The command response data. Deserialized with the corresponding vtable deserialize function and
     /// freed with the vtable free function when the OpList is destroyed.
-    pub response: Worker_CommandResponse,
+    pub response: OpCommandResponse<'a>,
 }
 
-impl From<Worker_CommandResponseOp> for CommandResponseOp {
-    fn from(op: Worker_CommandResponseOp) -> Self {
+impl<'a> CommandResponseOp<'a> {
+    /// Creates an op borrowing the data referenced by the raw op.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the op must outlive the returned op.
+    pub unsafe fn from_raw(op: Worker_CommandResponseOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             request_id: op.request_id,
             entity_id: op.entity_id,
             status_code: StatusCode::from(op.status_code),
-            response: op.response,
+            response: OpCommandResponse::from_raw(op.response),
             message,
         }
     }
+
+    pub fn into_owned(self) -> CommandResponseOp<'static> {
+        CommandResponseOp {
+            request_id: self.request_id,
+            entity_id: self.entity_id,
+            status_code: self.status_code,
+            message: self.message,
+            response: self.response.into_owned(),
+        }
+    }
 }
 
 #[derive(Debug)]
 /// Data for a ComponentUpdate operation.
-pub struct ComponentUpdateOp {
+pub struct ComponentUpdateOp<'a> {
     /// The ID of the entity for which there was a component update.
     pub entity_id: EntityId,
     /// The new component data for the updated entity. Deserialized with the corresponding vtable
     /// deserialize function and freed with the vtable free function when the OpList is destroyed.
-    pub update: Worker_ComponentUpdate,
+    pub update: OpComponentUpdate<'a>,
 }
 
-impl From<Worker_ComponentUpdateOp> for ComponentUpdateOp {
-    fn from(op: Worker_ComponentUpdateOp) -> Self {
+impl<'a> ComponentUpdateOp<'a> {
+    /// Creates an op borrowing the data referenced by the raw op.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the op must outlive the returned op.
+    pub unsafe fn from_raw(op: Worker_ComponentUpdateOp) -> Self {
         Self {
             entity_id: op.entity_id,
-            update: op.update,
+            update: OpComponentUpdate::from_raw(op.update),
+        }
+    }
+
+    pub fn into_owned(self) -> ComponentUpdateOp<'static> {
+        ComponentUpdateOp {
+            entity_id: self.entity_id,
+            update: self.update.into_owned(),
         }
     }
 }
@@ -345,9 +516,8 @@ pub struct CreateEntityResponseOp {
 impl From<Worker_CreateEntityResponseOp> for CreateEntityResponseOp {
     fn from(op: Worker_CreateEntityResponseOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             request_id: op.request_id,
             status_code: StatusCode::from(op.status_code),
@@ -392,9 +562,8 @@ pub struct DeleteEntityResponseOp {
 impl From<Worker_DeleteEntityResponseOp> for DeleteEntityResponseOp {
     fn from(op: Worker_DeleteEntityResponseOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             request_id: op.request_id,
             status_code: StatusCode::from(op.status_code),
@@ -406,7 +575,7 @@ impl From<Worker_DeleteEntityResponseOp> for DeleteEntityResponseOp {
 
 #[derive(Debug)]
 /// A response indicating the result of an entity query request.
-pub struct EntityQueryResponseOp {
+pub struct EntityQueryResponseOp<'a> {
     /// The ID of the entity query request for which there was a response.
     pub request_id: RequestId,
     /// Status code of the response, using StatusCode.
@@ -419,18 +588,22 @@ pub struct EntityQueryResponseOp {
     /// Array of entities in the result set. Will be NULL if the query was a count query. Snapshot data
     /// in the result is deserialized with the corresponding vtable deserialize function and freed with
     /// the vtable free function when the OpList is destroyed.
-    pub results: Vec<Entity>,
+    pub results: Vec<EntitySnapshot<'a>>,
 }
 
-impl From<Worker_EntityQueryResponseOp> for EntityQueryResponseOp {
-    fn from(op: Worker_EntityQueryResponseOp) -> Self {
+impl<'a> EntityQueryResponseOp<'a> {
+    /// Creates an op borrowing the data referenced by the raw op.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the op must outlive the returned op.
+    pub unsafe fn from_raw(op: Worker_EntityQueryResponseOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         let results = const_to_vector(op.results, op.result_count as isize)
             .into_iter()
-            .map(|e| e.into())
+            .map(|entity| EntitySnapshot::from_raw(entity))
             .collect();
         Self {
             request_id: op.request_id,
@@ -440,6 +613,58 @@ impl From<Worker_EntityQueryResponseOp> for EntityQueryResponseOp {
             message,
         }
     }
+
+    pub fn into_owned(self) -> EntityQueryResponseOp<'static> {
+        EntityQueryResponseOp {
+            request_id: self.request_id,
+            status_code: self.status_code,
+            message: self.message,
+            result_count: self.result_count,
+            results: self
+                .results
+                .into_iter()
+                .map(EntitySnapshot::into_owned)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// An entity in the result set of a snapshot entity query.
+pub struct EntitySnapshot<'a> {
+    /// The ID of the entity.
+    pub entity_id: EntityId,
+    /// The data of the components of the entity included in the result set.
+    pub components: Vec<OpComponentData<'a>>,
+}
+
+impl<'a> EntitySnapshot<'a> {
+    /// Creates an entity borrowing the data referenced by the raw entity.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the entity must outlive the returned entity.
+    pub unsafe fn from_raw(entity: Worker_Entity) -> Self {
+        let components = const_to_vector(entity.components, entity.component_count as isize)
+            .into_iter()
+            .map(|data| OpComponentData::from_raw(data))
+            .collect();
+        Self {
+            entity_id: entity.entity_id,
+            components,
+        }
+    }
+
+    pub fn into_owned(self) -> EntitySnapshot<'static> {
+        EntitySnapshot {
+            entity_id: self.entity_id,
+            components: self
+                .components
+                .into_iter()
+                .map(OpComponentData::into_owned)
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -455,16 +680,14 @@ pub struct FlagUpdateOp {
 impl From<Worker_FlagUpdateOp> for FlagUpdateOp {
     fn from(op: Worker_FlagUpdateOp) -> Self {
         let name = unsafe { CStr::from_ptr(op.name) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         if op.value.is_null() {
             Self { name, value: None }
         } else {
             let value = unsafe { CStr::from_ptr(op.value) }
-                .to_str()
-                .map(|s| s.to_owned())
-                .unwrap();
+                .to_string_lossy()
+                .into_owned();
             Self {
                 name,
                 value: Some(value),
@@ -539,9 +762,8 @@ pub struct ReserveEntityIdsResponseOp {
 impl From<Worker_ReserveEntityIdsResponseOp> for ReserveEntityIdsResponseOp {
     fn from(op: Worker_ReserveEntityIdsResponseOp) -> Self {
         let message = unsafe { CStr::from_ptr(op.message) }
-            .to_str()
-            .map(|s| s.to_owned())
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
         Self {
             request_id: op.request_id,
             status_code: StatusCode::from(op.status_code),
@@ -551,3 +773,370 @@ impl From<Worker_ReserveEntityIdsResponseOp> for ReserveEntityIdsResponseOp {
         }
     }
 }
+
+#[derive(Debug)]
+/// Schema data held by an op.
+enum OpData<T, S> {
+    /// A copy of the struct held by the op list, referencing data owned by the list.
+    Borrowed(T),
+    /// A reference acquired from the SDK.
+    Acquired(S),
+}
+
+#[derive(Debug)]
+/// Component data received in an op. It borrows the data of its op list, unless it was acquired
+/// with OpComponentData::into_owned.
+pub struct OpComponentData<'a> {
+    data: OpData<Worker_ComponentData, SharedComponentData>,
+    _op_list: PhantomData<&'a OpList>,
+}
+
+/// Component data with a 'static lifetime either holds an acquired reference, or borrows data which
+/// the caller of from_raw guaranteed can be used from other threads.
+unsafe impl Send for OpComponentData<'static> {}
+
+impl<'a> OpComponentData<'a> {
+    /// Creates component data borrowing the schema data referenced by the raw data.
+    ///
+    /// # Safety
+    ///
+    /// The schema data referenced by the raw data must outlive the returned component data. If the
+    /// component data is created with a 'static lifetime, it can be sent to another thread, so the
+    /// schema data must not be used by any other thread until the component data is dropped.
+    pub unsafe fn from_raw(data: Worker_ComponentData) -> Self {
+        Self {
+            data: OpData::Borrowed(data),
+            _op_list: PhantomData,
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    /// Returns the fields of the component, or None if the data is only available through a user
+    /// handle.
+    pub fn get_fields(&self) -> Option<ObjectRef<'_>> {
+        shared::component_data_fields(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut ComponentDataHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the data, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Deserializes the data into a component. Returns None if the data belongs to another
+    /// component, or is only available through a user handle.
+    pub fn read<C: Component>(&self) -> Option<C> {
+        shared::read_component_data(self.raw())
+    }
+
+    /// Acquires a reference to the data, which can be kept after the op list is destroyed.
+    pub fn share(&self) -> SharedComponentData {
+        match &self.data {
+            OpData::Borrowed(data) => SharedComponentData::acquire(data),
+            OpData::Acquired(data) => data.clone(),
+        }
+    }
+
+    /// Acquires a reference to the data, which is no longer tied to the lifetime of the op list.
+    pub fn into_owned(self) -> OpComponentData<'static> {
+        let data = match self.data {
+            OpData::Borrowed(data) => SharedComponentData::acquire(&data),
+            OpData::Acquired(data) => data,
+        };
+        OpComponentData {
+            data: OpData::Acquired(data),
+            _op_list: PhantomData,
+        }
+    }
+
+    fn raw(&self) -> &Worker_ComponentData {
+        match &self.data {
+            OpData::Borrowed(data) => data,
+            OpData::Acquired(data) => data.raw(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A component update received in an op. It borrows the data of its op list, unless it was
+/// acquired with OpComponentUpdate::into_owned.
+pub struct OpComponentUpdate<'a> {
+    update: OpData<Worker_ComponentUpdate, SharedComponentUpdate>,
+    _op_list: PhantomData<&'a OpList>,
+}
+
+/// Updates with a 'static lifetime either hold an acquired reference, or borrow data which the
+/// caller of from_raw guaranteed can be used from other threads.
+unsafe impl Send for OpComponentUpdate<'static> {}
+
+impl<'a> OpComponentUpdate<'a> {
+    /// Creates an update borrowing the data referenced by the raw update.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the update must outlive the returned update. If the update is
+    /// created with a 'static lifetime, it can be sent to another thread, so the data must not be
+    /// used by any other thread until the update is dropped.
+    pub unsafe fn from_raw(update: Worker_ComponentUpdate) -> Self {
+        Self {
+            update: OpData::Borrowed(update),
+            _op_list: PhantomData,
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    /// Returns the fields set by the update, or None if the update is only available through a
+    /// user handle.
+    pub fn get_fields(&self) -> Option<ObjectRef<'_>> {
+        shared::component_update_fields(self.raw())
+    }
+
+    /// Returns the events of the update, or None if the update is only available through a user
+    /// handle.
+    pub fn get_events(&self) -> Option<ObjectRef<'_>> {
+        shared::component_update_events(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut ComponentUpdateHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the update, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Deserializes the update of a component. Returns None if the update belongs to another
+    /// component, or is only available through a user handle.
+    pub fn read<C: Component>(&self) -> Option<C::Update> {
+        shared::read_component_update::<C>(self.raw())
+    }
+
+    /// Acquires a reference to the update, which can be kept after the op list is destroyed.
+    pub fn share(&self) -> SharedComponentUpdate {
+        match &self.update {
+            OpData::Borrowed(update) => SharedComponentUpdate::acquire(update),
+            OpData::Acquired(update) => update.clone(),
+        }
+    }
+
+    /// Acquires a reference to the update, which is no longer tied to the lifetime of the op list.
+    pub fn into_owned(self) -> OpComponentUpdate<'static> {
+        let update = match self.update {
+            OpData::Borrowed(update) => SharedComponentUpdate::acquire(&update),
+            OpData::Acquired(update) => update,
+        };
+        OpComponentUpdate {
+            update: OpData::Acquired(update),
+            _op_list: PhantomData,
+        }
+    }
+
+    pub(crate) fn raw(&self) -> &Worker_ComponentUpdate {
+        match &self.update {
+            OpData::Borrowed(update) => update,
+            OpData::Acquired(update) => update.raw(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A command request received in an op. It borrows the data of its op list, unless it was
+/// acquired with OpCommandRequest::into_owned.
+pub struct OpCommandRequest<'a> {
+    request: OpData<Worker_CommandRequest, SharedCommandRequest>,
+    _op_list: PhantomData<&'a OpList>,
+}
+
+/// Requests with a 'static lifetime either hold an acquired reference, or borrow data which the
+/// caller of from_raw guaranteed can be used from other threads.
+unsafe impl Send for OpCommandRequest<'static> {}
+
+impl<'a> OpCommandRequest<'a> {
+    /// Creates a request borrowing the data referenced by the raw request.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the request must outlive the returned request. If the request is
+    /// created with a 'static lifetime, it can be sent to another thread, so the data must not be
+    /// used by any other thread until the request is dropped.
+    pub unsafe fn from_raw(request: Worker_CommandRequest) -> Self {
+        Self {
+            request: OpData::Borrowed(request),
+            _op_list: PhantomData,
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    pub fn command_index(&self) -> CommandIndex {
+        self.raw().command_index
+    }
+
+    /// Returns the request object, or None if the request is only available through a user
+    /// handle.
+    pub fn get_object(&self) -> Option<ObjectRef<'_>> {
+        shared::command_request_object(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut CommandRequestHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the request, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Acquires a reference to the request, which can be kept after the op list is destroyed.
+    pub fn share(&self) -> SharedCommandRequest {
+        match &self.request {
+            OpData::Borrowed(request) => SharedCommandRequest::acquire(request),
+            OpData::Acquired(request) => request.clone(),
+        }
+    }
+
+    /// Acquires a reference to the request, which is no longer tied to the lifetime of the op list.
+    pub fn into_owned(self) -> OpCommandRequest<'static> {
+        let request = match self.request {
+            OpData::Borrowed(request) => SharedCommandRequest::acquire(&request),
+            OpData::Acquired(request) => request,
+        };
+        OpCommandRequest {
+            request: OpData::Acquired(request),
+            _op_list: PhantomData,
+        }
+    }
+
+    fn raw(&self) -> &Worker_CommandRequest {
+        match &self.request {
+            OpData::Borrowed(request) => request,
+            OpData::Acquired(request) => request.raw(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A command response received in an op. It borrows the data of its op list, unless it was
+/// acquired with OpCommandResponse::into_owned.
+pub struct OpCommandResponse<'a> {
+    response: OpData<Worker_CommandResponse, SharedCommandResponse>,
+    _op_list: PhantomData<&'a OpList>,
+}
+
+/// Responses with a 'static lifetime either hold an acquired reference, hold no data at all, or
+/// borrow data which the caller of from_raw guaranteed can be used from other threads.
+unsafe impl Send for OpCommandResponse<'static> {}
+
+impl<'a> OpCommandResponse<'a> {
+    /// Creates a response borrowing the data referenced by the raw response.
+    ///
+    /// # Safety
+    ///
+    /// The data referenced by the response must outlive the returned response. If the response is
+    /// created with a 'static lifetime, it can be sent to another thread, so the data must not be
+    /// used by any other thread until the response is dropped.
+    pub unsafe fn from_raw(response: Worker_CommandResponse) -> Self {
+        Self {
+            response: OpData::Borrowed(response),
+            _op_list: PhantomData,
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    pub fn command_index(&self) -> CommandIndex {
+        self.raw().command_index
+    }
+
+    /// Returns the response object, or None if the command failed or if the response is only
+    /// available through a user handle.
+    pub fn get_object(&self) -> Option<ObjectRef<'_>> {
+        shared::command_response_object(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut CommandResponseHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the response, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Acquires a reference to the response, which can be kept after the op list is destroyed.
+    pub fn share(&self) -> SharedCommandResponse {
+        match &self.response {
+            OpData::Borrowed(response) => SharedCommandResponse::acquire(response),
+            OpData::Acquired(response) => response.clone(),
+        }
+    }
+
+    /// Acquires a reference to the response, which is no longer tied to the lifetime of the op
+    /// list. The response of a failed command holds no data, and is kept as is.
+    pub fn into_owned(self) -> OpCommandResponse<'static> {
+        let response = match self.response {
+            OpData::Borrowed(response)
+                if response.schema_type.is_null() && response.user_handle.is_null() =>
+            {
+                OpData::Borrowed(response)
+            }
+            OpData::Borrowed(response) => {
+                OpData::Acquired(SharedCommandResponse::acquire(&response))
+            }
+            OpData::Acquired(response) => OpData::Acquired(response),
+        };
+        OpCommandResponse {
+            response,
+            _op_list: PhantomData,
+        }
+    }
+
+    fn raw(&self) -> &Worker_CommandResponse {
+        match &self.response {
+            OpData::Borrowed(response) => response,
+            OpData::Acquired(response) => response.raw(),
+        }
+    }
+}
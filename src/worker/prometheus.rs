@@ -0,0 +1,261 @@
+//! Exposes worker metrics in the Prometheus text exposition format, so that workers can be scraped
+//! directly by a Prometheus server.
+
+use crate::worker::metrics::{Metrics, MetricsRegistry};
+use crate::worker::op::MetricsOp;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How long a scrape may take to send its request or receive the response before it is dropped.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const LOAD_NAME: &str = "spatialos_worker_load";
+
+/// Collects the built-in metrics reported by the SDK through MetricsOp, as well as the metrics of a
+/// MetricsRegistry, and renders them for Prometheus. It can be cloned and shared between threads.
+///
+/// Built-in metrics are only reported if built_in_metrics_report_period_millis is set in the
+/// ConnectionParameters. Each metric name is only rendered once: built-in metrics take precedence
+/// over registry metrics with the same name, and of the registry keys which only differ by the
+/// characters replaced in names, only the first is rendered. The keys which are not rendered are
+/// logged as warnings through the enabled logging crates, once per key.
+#[derive(Clone)]
+pub struct PrometheusExporter {
+    registry: MetricsRegistry,
+    built_in: Arc<Mutex<Option<Metrics>>>,
+    collisions: Arc<Mutex<HashSet<String>>>,
+}
+
+impl PrometheusExporter {
+    pub fn new(registry: MetricsRegistry) -> Self {
+        Self {
+            registry,
+            built_in: Arc::new(Mutex::new(None)),
+            collisions: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Replaces the exported built-in metrics with the ones reported by the given op.
+    pub fn apply(&self, op: &MetricsOp) {
+        *self.built_in.lock().unwrap() = Some(op.metrics.clone());
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let mut names = HashSet::new();
+        let mut skipped = Vec::new();
+        if let Some(metrics) = &*self.built_in.lock().unwrap() {
+            render_metrics(&mut text, &mut names, &mut skipped, metrics);
+        }
+        render_metrics(&mut text, &mut names, &mut skipped, &self.registry.totals());
+        let mut collisions = self.collisions.lock().unwrap();
+        for key in skipped {
+            if collisions.insert(key.clone()) {
+                warn_collision(&key);
+            }
+        }
+        text
+    }
+
+    /// Serves the metrics over HTTP on the given address from a dedicated thread. Every request is
+    /// answered with the rendered metrics, regardless of its path.
+    pub fn serve<A: ToSocketAddrs>(&self, address: A) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(address)?;
+        let exporter = self.clone();
+        Ok(std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = exporter.respond(stream);
+            }
+        }))
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+        stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        let body = self.render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+}
+
+/// Renders the metrics whose names are not in the given set, and adds their names to it. The keys of
+/// the metrics whose names are already in the set are added to skipped.
+fn render_metrics(
+    text: &mut String,
+    names: &mut HashSet<String>,
+    skipped: &mut Vec<String>,
+    metrics: &Metrics,
+) {
+    if let Some(load) = metrics.load.filter(|_| names.insert(LOAD_NAME.to_owned())) {
+        let _ = writeln!(text, "# TYPE {} gauge", LOAD_NAME);
+        let _ = writeln!(text, "{} {}", LOAD_NAME, format_value(load));
+    }
+    for gauge in &metrics.gauge_metrics {
+        let name = sanitize_name(&gauge.key);
+        if !names.insert(name.clone()) {
+            skipped.push(gauge.key.clone());
+            continue;
+        }
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        let _ = writeln!(text, "{} {}", name, format_value(gauge.value));
+    }
+    for histogram in &metrics.histogram_metrics {
+        let name = sanitize_name(&histogram.key);
+        if !names.insert(name.clone()) {
+            skipped.push(histogram.key.clone());
+            continue;
+        }
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        for bucket in &histogram.buckets {
+            let _ = writeln!(
+                text,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                format_value(bucket.upper_bound),
+                bucket.samples
+            );
+        }
+        let count = histogram.buckets.last().map_or(0, |bucket| bucket.samples);
+        let _ = writeln!(text, "{}_sum {}", name, format_value(histogram.sum));
+        let _ = writeln!(text, "{}_count {}", name, count);
+    }
+}
+
+fn warn_collision(key: &str) {
+    #[cfg(feature = "log")]
+    log::warn!(
+        "metric {} is not exported, as another metric has the same Prometheus name",
+        key
+    );
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        metric = key,
+        "metric is not exported, as another metric has the same Prometheus name"
+    );
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    let _ = key;
+}
+
+/// Replaces the characters which are not allowed in Prometheus metric names with underscores. Names
+/// cannot start with a digit, so an underscore is prepended to those.
+fn sanitize_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 1);
+    if key.starts_with(|c: char| c.is_ascii_digit()) {
+        name.push('_');
+    }
+    name.extend(key.chars().map(|c| {
+        if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+            c
+        } else {
+            '_'
+        }
+    }));
+    name
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_owned()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_owned()
+    } else if value.is_nan() {
+        "NaN".to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::metrics::GaugeMetric;
+
+    fn built_in(key: &str, value: f64) -> MetricsOp {
+        MetricsOp {
+            metrics: Metrics {
+                load: Some(0.25),
+                gauge_metrics: vec![GaugeMetric {
+                    key: key.to_owned(),
+                    value,
+                }],
+                histogram_metrics: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn renders_gauges_and_histograms() {
+        let registry = MetricsRegistry::new();
+        registry.gauge("entity.count").set(3.0);
+        let histogram = registry.histogram("latency", &[1.0]).unwrap();
+        histogram.observe(0.5);
+        histogram.observe(2.0);
+        let text = PrometheusExporter::new(registry).render();
+        assert_eq!(
+            text,
+            "# TYPE entity_count gauge\n\
+             entity_count 3\n\
+             # TYPE latency histogram\n\
+             latency_bucket{le=\"1\"} 1\n\
+             latency_bucket{le=\"+Inf\"} 2\n\
+             latency_sum 2.5\n\
+             latency_count 2\n"
+        );
+    }
+
+    #[test]
+    fn renders_each_name_once() {
+        let registry = MetricsRegistry::new();
+        registry.set_load(Some(0.5));
+        registry.gauge("queue.length").set(1.0);
+        registry.gauge("queue_length").set(2.0);
+        registry.gauge("frame_time").set(3.0);
+        let exporter = PrometheusExporter::new(registry);
+        exporter.apply(&built_in("frame_time", 4.0));
+        assert_eq!(
+            exporter.render(),
+            "# TYPE spatialos_worker_load gauge\n\
+             spatialos_worker_load 0.25\n\
+             # TYPE frame_time gauge\n\
+             frame_time 4\n\
+             # TYPE queue_length gauge\n\
+             queue_length 1\n"
+        );
+        let mut collisions = exporter
+            .collisions
+            .lock()
+            .unwrap()
+            .drain()
+            .collect::<Vec<_>>();
+        collisions.sort();
+        assert_eq!(collisions, vec!["frame_time", "queue_length"]);
+    }
+
+    #[test]
+    fn sanitizes_names_and_formats_values() {
+        assert_eq!(sanitize_name("1st metric:total"), "_1st_metric:total");
+        assert_eq!(sanitize_name("queue.length2"), "queue_length2");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(1.5), "1.5");
+    }
+}
@@ -0,0 +1,1018 @@
+//! Recording of the op lists received on a connection, and replay of those recordings through the
+//! same OpList and WorkerOp types, to debug workers without a running deployment.
+//!
+//! A recording starts with a header, followed by one entry per op list, made of the time elapsed
+//! since the recording started and the ops of the list. Schema payloads are stored in the
+//! SpatialOS wire format. Payloads only available through a user handle are not recorded, and are
+//! replayed without schema data.
+
+use crate::schema;
+use crate::worker::metrics::{GaugeMetric, HistogramMetric, HistogramMetricBucket, Metrics};
+use crate::worker::op::{
+    AddComponentOp, AddEntityOp, AuthorityChangeOp, CommandRequestOp, CommandResponseOp,
+    ComponentUpdateOp, CreateEntityResponseOp, CriticalSectionOp, DeleteEntityResponseOp,
+    DisconnectOp, EntityQueryResponseOp, EntitySnapshot, FlagUpdateOp, LogMessageOp, MetricsOp,
+    OpCommandRequest, OpCommandResponse, OpComponentData, OpComponentUpdate, OpList,
+    OwnedSchemaData, RemoveComponentOp, RemoveEntityOp, ReserveEntityIdsResponseOp, WorkerOp,
+};
+use crate::worker::{Authority, ConnectionStatusCode, LogLevel, StatusCode, WorkerAttributes};
+use spatialos_sys::{
+    Worker_CommandRequest, Worker_CommandResponse, Worker_ComponentData, Worker_ComponentUpdate,
+};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem::ManuallyDrop;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"SOPR";
+const VERSION: u16 = 1;
+
+/// Writes the op lists received on a connection to a recording.
+pub struct OpRecorder<W: Write> {
+    writer: W,
+    start: Instant,
+}
+
+impl OpRecorder<BufWriter<File>> {
+    /// Creates a recording in a new file at the given path, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> OpRecorder<W> {
+    /// Starts a recording in the given writer. Timestamps are relative to the creation of the
+    /// recorder.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        write_u16(&mut writer, VERSION)?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    /// Appends an op list to the recording. This must be called before the ops are drained from
+    /// the list.
+    pub fn record(&mut self, op_list: &OpList) -> io::Result<()> {
+        write_u64(&mut self.writer, self.start.elapsed().as_micros() as u64)?;
+        write_len(&mut self.writer, op_list.len())?;
+        for op in op_list {
+            write_op(&mut self.writer, op)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// An op list read from a recording.
+pub struct RecordedOpList {
+    /// The time elapsed between the start of the recording and the moment the op list was
+    /// recorded.
+    pub timestamp: Duration,
+    pub op_list: OpList,
+}
+
+/// Reads back the op lists of a recording, in the order they were recorded.
+pub struct OpReplayer<R: Read> {
+    reader: R,
+}
+
+impl OpReplayer<BufReader<File>> {
+    /// Opens the recording in the file at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> OpReplayer<R> {
+    /// Reads a recording from the given reader, failing if it does not start with a valid header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an op recording"));
+        }
+        if read_u16(&mut reader)? != VERSION {
+            return Err(invalid_data("unsupported op recording version"));
+        }
+        Ok(Self { reader })
+    }
+
+    /// Returns the next op list of the recording, or None once every op list has been read.
+    pub fn next_op_list(&mut self) -> io::Result<Option<RecordedOpList>> {
+        let timestamp = match read_u64(&mut self.reader) {
+            Ok(timestamp) => Duration::from_micros(timestamp),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let op_count = read_u32(&mut self.reader)?;
+        let mut schema_data = Vec::new();
+        let ops = (0..op_count)
+            .map(|_| read_op(&mut self.reader, &mut schema_data))
+            .collect::<io::Result<Vec<_>>>();
+        let ops = match ops {
+            Ok(ops) => ops,
+            Err(error) => {
+                // The schema data of the ops read so far is destroyed along with the list.
+                OpList::with_schema_data(Vec::new(), schema_data);
+                return Err(error);
+            }
+        };
+        Ok(Some(RecordedOpList {
+            timestamp,
+            op_list: OpList::with_schema_data(ops, schema_data),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for OpReplayer<R> {
+    type Item = io::Result<RecordedOpList>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_op_list().transpose()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_op<W: Write>(writer: &mut W, op: &WorkerOp) -> io::Result<()> {
+    match op {
+        WorkerOp::Disconnect(op) => {
+            write_u8(writer, 0)?;
+            write_u8(writer, connection_status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.reason)
+        }
+        WorkerOp::FlagUpdate(op) => {
+            write_u8(writer, 1)?;
+            write_string(writer, &op.name)?;
+            write_option(writer, op.value.as_ref(), |writer, value| {
+                write_string(writer, value)
+            })
+        }
+        WorkerOp::LogMessage(op) => {
+            write_u8(writer, 2)?;
+            write_u8(writer, log_level_to_u8(&op.level))?;
+            write_string(writer, &op.message)
+        }
+        WorkerOp::Metrics(op) => {
+            write_u8(writer, 3)?;
+            write_metrics(writer, &op.metrics)
+        }
+        WorkerOp::CriticalSection(op) => {
+            write_u8(writer, 4)?;
+            write_u8(writer, op.in_critical_section as u8)
+        }
+        WorkerOp::AddEntity(op) => {
+            write_u8(writer, 5)?;
+            write_i64(writer, op.entity_id)
+        }
+        WorkerOp::RemoveEntity(op) => {
+            write_u8(writer, 6)?;
+            write_i64(writer, op.entity_id)
+        }
+        WorkerOp::ReserveEntityIdsResponse(op) => {
+            write_u8(writer, 7)?;
+            write_i64(writer, op.request_id)?;
+            write_u8(writer, status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.message)?;
+            write_i64(writer, op.first_entity_id)?;
+            write_u32(writer, op.number_of_entity_ids)
+        }
+        WorkerOp::CreateEntityResponse(op) => {
+            write_u8(writer, 8)?;
+            write_i64(writer, op.request_id)?;
+            write_u8(writer, status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.message)?;
+            write_i64(writer, op.entity_id)
+        }
+        WorkerOp::DeleteEntityResponse(op) => {
+            write_u8(writer, 9)?;
+            write_i64(writer, op.request_id)?;
+            write_i64(writer, op.entity_id)?;
+            write_u8(writer, status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.message)
+        }
+        WorkerOp::EntityQueryResponse(op) => {
+            write_u8(writer, 10)?;
+            write_i64(writer, op.request_id)?;
+            write_u8(writer, status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.message)?;
+            write_u32(writer, op.result_count)?;
+            write_len(writer, op.results.len())?;
+            for entity in &op.results {
+                write_i64(writer, entity.entity_id)?;
+                write_len(writer, entity.components.len())?;
+                for component in &entity.components {
+                    write_u32(writer, component.component_id())?;
+                    let data = component.get_fields().map(|fields| fields.serialize());
+                    write_option(writer, data.as_ref(), |writer, data| {
+                        write_bytes(writer, data)
+                    })?;
+                }
+            }
+            Ok(())
+        }
+        WorkerOp::AddComponent(op) => {
+            write_u8(writer, 11)?;
+            write_i64(writer, op.entity_id)?;
+            write_u32(writer, op.data.component_id())?;
+            let data = op.data.get_fields().map(|fields| fields.serialize());
+            write_option(writer, data.as_ref(), |writer, data| {
+                write_bytes(writer, data)
+            })
+        }
+        WorkerOp::RemoveComponent(op) => {
+            write_u8(writer, 12)?;
+            write_i64(writer, op.entity_id)?;
+            write_u32(writer, op.component_id)
+        }
+        WorkerOp::AuthorityChange(op) => {
+            write_u8(writer, 13)?;
+            write_i64(writer, op.entity_id)?;
+            write_u32(writer, op.component_id)?;
+            write_u8(writer, authority_to_u8(&op.authority))
+        }
+        WorkerOp::ComponentUpdate(op) => {
+            write_u8(writer, 14)?;
+            write_i64(writer, op.entity_id)?;
+            write_u32(writer, op.update.component_id())?;
+            let schema_type = op.update.raw().schema_type;
+            let update = if schema_type.is_null() {
+                None
+            } else {
+                Some(ManuallyDrop::new(schema::ComponentUpdate::from(schema_type)).serialize())
+            };
+            write_option(writer, update.as_ref(), |writer, update| {
+                write_bytes(writer, update)
+            })
+        }
+        WorkerOp::CommandRequest(op) => {
+            write_u8(writer, 15)?;
+            write_i64(writer, op.request_id)?;
+            write_i64(writer, op.entity_id)?;
+            write_u32(writer, op.timeout_millis)?;
+            write_string(writer, &op.caller_worker_id)?;
+            write_len(writer, op.caller_attribute_set.attributes.len())?;
+            for attribute in &op.caller_attribute_set.attributes {
+                write_string(writer, attribute)?;
+            }
+            write_u32(writer, op.request.component_id())?;
+            write_u32(writer, op.request.command_index())?;
+            let request = op.request.get_object().map(|object| object.serialize());
+            write_option(writer, request.as_ref(), |writer, request| {
+                write_bytes(writer, request)
+            })
+        }
+        WorkerOp::CommandResponse(op) => {
+            write_u8(writer, 16)?;
+            write_i64(writer, op.request_id)?;
+            write_i64(writer, op.entity_id)?;
+            write_u8(writer, status_code_to_u8(&op.status_code))?;
+            write_string(writer, &op.message)?;
+            write_u32(writer, op.response.component_id())?;
+            write_u32(writer, op.response.command_index())?;
+            let response = op.response.get_object().map(|object| object.serialize());
+            write_option(writer, response.as_ref(), |writer, response| {
+                write_bytes(writer, response)
+            })
+        }
+    }
+}
+
+fn read_op<R: Read>(
+    reader: &mut R,
+    schema_data: &mut Vec<OwnedSchemaData>,
+) -> io::Result<WorkerOp<'static>> {
+    let op = match read_u8(reader)? {
+        0 => WorkerOp::Disconnect(DisconnectOp {
+            status_code: connection_status_code_from_u8(read_u8(reader)?)?,
+            reason: read_string(reader)?,
+        }),
+        1 => WorkerOp::FlagUpdate(FlagUpdateOp {
+            name: read_string(reader)?,
+            value: read_option(reader, read_string)?,
+        }),
+        2 => WorkerOp::LogMessage(LogMessageOp {
+            level: log_level_from_u8(read_u8(reader)?)?,
+            message: read_string(reader)?,
+        }),
+        3 => WorkerOp::Metrics(MetricsOp {
+            metrics: read_metrics(reader)?,
+        }),
+        4 => WorkerOp::CriticalSection(CriticalSectionOp {
+            in_critical_section: read_u8(reader)? != 0,
+        }),
+        5 => WorkerOp::AddEntity(AddEntityOp {
+            entity_id: read_i64(reader)?,
+        }),
+        6 => WorkerOp::RemoveEntity(RemoveEntityOp {
+            entity_id: read_i64(reader)?,
+        }),
+        7 => WorkerOp::ReserveEntityIdsResponse(ReserveEntityIdsResponseOp {
+            request_id: read_i64(reader)?,
+            status_code: status_code_from_u8(read_u8(reader)?)?,
+            message: read_string(reader)?,
+            first_entity_id: read_i64(reader)?,
+            number_of_entity_ids: read_u32(reader)?,
+        }),
+        8 => WorkerOp::CreateEntityResponse(CreateEntityResponseOp {
+            request_id: read_i64(reader)?,
+            status_code: status_code_from_u8(read_u8(reader)?)?,
+            message: read_string(reader)?,
+            entity_id: read_i64(reader)?,
+        }),
+        9 => WorkerOp::DeleteEntityResponse(DeleteEntityResponseOp {
+            request_id: read_i64(reader)?,
+            entity_id: read_i64(reader)?,
+            status_code: status_code_from_u8(read_u8(reader)?)?,
+            message: read_string(reader)?,
+        }),
+        10 => {
+            let request_id = read_i64(reader)?;
+            let status_code = status_code_from_u8(read_u8(reader)?)?;
+            let message = read_string(reader)?;
+            let result_count = read_u32(reader)?;
+            let results = (0..read_u32(reader)?)
+                .map(|_| {
+                    let entity_id = read_i64(reader)?;
+                    let components = (0..read_u32(reader)?)
+                        .map(|_| {
+                            let component_id = read_u32(reader)?;
+                            let data = match read_option(reader, read_bytes)? {
+                                Some(buffer) => {
+                                    let data: *mut schema::ffi::ComponentData =
+                                        schema::ComponentData::deserialize(&buffer)
+                                            .ok_or_else(|| invalid_data("invalid component data"))?
+                                            .into();
+                                    schema_data.push(OwnedSchemaData::ComponentData(data));
+                                    data
+                                }
+                                None => std::ptr::null_mut(),
+                            };
+                            Ok(unsafe {
+                                OpComponentData::from_raw(Worker_ComponentData {
+                                    reserved: std::ptr::null_mut(),
+                                    component_id,
+                                    schema_type: data,
+                                    user_handle: std::ptr::null_mut(),
+                                })
+                            })
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+                    Ok(EntitySnapshot {
+                        entity_id,
+                        components,
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            WorkerOp::EntityQueryResponse(EntityQueryResponseOp {
+                request_id,
+                status_code,
+                message,
+                result_count,
+                results,
+            })
+        }
+        11 => {
+            let entity_id = read_i64(reader)?;
+            let component_id = read_u32(reader)?;
+            let schema_type = match read_option(reader, read_bytes)? {
+                Some(buffer) => {
+                    let data: *mut schema::ffi::ComponentData =
+                        schema::ComponentData::deserialize(&buffer)
+                            .ok_or_else(|| invalid_data("invalid component data"))?
+                            .into();
+                    schema_data.push(OwnedSchemaData::ComponentData(data));
+                    data
+                }
+                None => std::ptr::null_mut(),
+            };
+            WorkerOp::AddComponent(AddComponentOp {
+                entity_id,
+                data: unsafe {
+                    OpComponentData::from_raw(Worker_ComponentData {
+                        reserved: std::ptr::null_mut(),
+                        component_id,
+                        schema_type,
+                        user_handle: std::ptr::null_mut(),
+                    })
+                },
+            })
+        }
+        12 => WorkerOp::RemoveComponent(RemoveComponentOp {
+            entity_id: read_i64(reader)?,
+            component_id: read_u32(reader)?,
+        }),
+        13 => WorkerOp::AuthorityChange(AuthorityChangeOp {
+            entity_id: read_i64(reader)?,
+            component_id: read_u32(reader)?,
+            authority: authority_from_u8(read_u8(reader)?)?,
+        }),
+        14 => {
+            let entity_id = read_i64(reader)?;
+            let component_id = read_u32(reader)?;
+            let schema_type = match read_option(reader, read_bytes)? {
+                Some(buffer) => {
+                    let update: *mut schema::ffi::ComponentUpdate =
+                        schema::ComponentUpdate::deserialize(&buffer)
+                            .ok_or_else(|| invalid_data("invalid component update"))?
+                            .into();
+                    schema_data.push(OwnedSchemaData::ComponentUpdate(update));
+                    update
+                }
+                None => std::ptr::null_mut(),
+            };
+            WorkerOp::ComponentUpdate(ComponentUpdateOp {
+                entity_id,
+                update: unsafe {
+                    OpComponentUpdate::from_raw(Worker_ComponentUpdate {
+                        reserved: std::ptr::null_mut(),
+                        component_id,
+                        schema_type,
+                        user_handle: std::ptr::null_mut(),
+                    })
+                },
+            })
+        }
+        15 => {
+            let request_id = read_i64(reader)?;
+            let entity_id = read_i64(reader)?;
+            let timeout_millis = read_u32(reader)?;
+            let caller_worker_id = read_string(reader)?;
+            let attributes = (0..read_u32(reader)?)
+                .map(|_| read_string(reader))
+                .collect::<io::Result<Vec<_>>>()?;
+            let component_id = read_u32(reader)?;
+            let command_index = read_u32(reader)?;
+            let schema_type = match read_option(reader, read_bytes)? {
+                Some(buffer) => {
+                    let request: *mut schema::ffi::CommandRequest =
+                        schema::CommandRequest::deserialize(&buffer)
+                            .ok_or_else(|| invalid_data("invalid command request"))?
+                            .into();
+                    schema_data.push(OwnedSchemaData::CommandRequest(request));
+                    request
+                }
+                None => std::ptr::null_mut(),
+            };
+            WorkerOp::CommandRequest(CommandRequestOp {
+                request_id,
+                entity_id,
+                timeout_millis,
+                caller_worker_id,
+                caller_attribute_set: WorkerAttributes {
+                    attribute_count: attributes.len() as u32,
+                    attributes,
+                },
+                request: unsafe {
+                    OpCommandRequest::from_raw(Worker_CommandRequest {
+                        reserved: std::ptr::null_mut(),
+                        component_id,
+                        command_index,
+                        schema_type,
+                        user_handle: std::ptr::null_mut(),
+                    })
+                },
+            })
+        }
+        16 => {
+            let request_id = read_i64(reader)?;
+            let entity_id = read_i64(reader)?;
+            let status_code = status_code_from_u8(read_u8(reader)?)?;
+            let message = read_string(reader)?;
+            let component_id = read_u32(reader)?;
+            let command_index = read_u32(reader)?;
+            let schema_type = match read_option(reader, read_bytes)? {
+                Some(buffer) => {
+                    let response: *mut schema::ffi::CommandResponse =
+                        schema::CommandResponse::deserialize(&buffer)
+                            .ok_or_else(|| invalid_data("invalid command response"))?
+                            .into();
+                    schema_data.push(OwnedSchemaData::CommandResponse(response));
+                    response
+                }
+                None => std::ptr::null_mut(),
+            };
+            WorkerOp::CommandResponse(CommandResponseOp {
+                request_id,
+                entity_id,
+                status_code,
+                message,
+                response: unsafe {
+                    OpCommandResponse::from_raw(Worker_CommandResponse {
+                        reserved: std::ptr::null_mut(),
+                        component_id,
+                        command_index,
+                        schema_type,
+                        user_handle: std::ptr::null_mut(),
+                    })
+                },
+            })
+        }
+        _ => return Err(invalid_data("invalid op type")),
+    };
+    Ok(op)
+}
+
+fn write_metrics<W: Write>(writer: &mut W, metrics: &Metrics) -> io::Result<()> {
+    write_option(writer, metrics.load.as_ref(), |writer, load| {
+        write_f64(writer, *load)
+    })?;
+    write_len(writer, metrics.gauge_metrics.len())?;
+    for gauge in &metrics.gauge_metrics {
+        write_string(writer, &gauge.key)?;
+        write_f64(writer, gauge.value)?;
+    }
+    write_len(writer, metrics.histogram_metrics.len())?;
+    for histogram in &metrics.histogram_metrics {
+        write_string(writer, &histogram.key)?;
+        write_f64(writer, histogram.sum)?;
+        write_len(writer, histogram.buckets.len())?;
+        for bucket in &histogram.buckets {
+            write_f64(writer, bucket.upper_bound)?;
+            write_u32(writer, bucket.samples)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_metrics<R: Read>(reader: &mut R) -> io::Result<Metrics> {
+    let load = read_option(reader, read_f64)?;
+    let gauge_metrics = (0..read_u32(reader)?)
+        .map(|_| {
+            Ok(GaugeMetric {
+                key: read_string(reader)?,
+                value: read_f64(reader)?,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let histogram_metrics = (0..read_u32(reader)?)
+        .map(|_| {
+            let key = read_string(reader)?;
+            let sum = read_f64(reader)?;
+            let buckets = (0..read_u32(reader)?)
+                .map(|_| {
+                    Ok(HistogramMetricBucket {
+                        upper_bound: read_f64(reader)?,
+                        samples: read_u32(reader)?,
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            Ok(HistogramMetric { key, sum, buckets })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(Metrics {
+        load,
+        gauge_metrics,
+        histogram_metrics,
+    })
+}
+
+fn status_code_to_u8(status_code: &StatusCode) -> u8 {
+    match status_code {
+        StatusCode::Success => 0,
+        StatusCode::Timeout => 1,
+        StatusCode::NotFound => 2,
+        StatusCode::AuthorityLost => 3,
+        StatusCode::PermissionDenied => 4,
+        StatusCode::ApplicationError => 5,
+        StatusCode::InternalError => 6,
+    }
+}
+
+fn status_code_from_u8(value: u8) -> io::Result<StatusCode> {
+    Ok(match value {
+        0 => StatusCode::Success,
+        1 => StatusCode::Timeout,
+        2 => StatusCode::NotFound,
+        3 => StatusCode::AuthorityLost,
+        4 => StatusCode::PermissionDenied,
+        5 => StatusCode::ApplicationError,
+        6 => StatusCode::InternalError,
+        _ => return Err(invalid_data("invalid status code")),
+    })
+}
+
+fn connection_status_code_to_u8(status_code: &ConnectionStatusCode) -> u8 {
+    match status_code {
+        ConnectionStatusCode::Success => 0,
+        ConnectionStatusCode::InternalError => 1,
+        ConnectionStatusCode::InvalidArgument => 2,
+        ConnectionStatusCode::NetworkError => 3,
+        ConnectionStatusCode::Timeout => 4,
+        ConnectionStatusCode::Cancelled => 5,
+        ConnectionStatusCode::Rejected => 6,
+        ConnectionStatusCode::PlayerIdentityTokenExpired => 7,
+        ConnectionStatusCode::LoginTokenExpired => 8,
+        ConnectionStatusCode::CapacityExceeded => 9,
+        ConnectionStatusCode::RateExceeded => 10,
+        ConnectionStatusCode::ServerShutdown => 11,
+    }
+}
+
+fn connection_status_code_from_u8(value: u8) -> io::Result<ConnectionStatusCode> {
+    Ok(match value {
+        0 => ConnectionStatusCode::Success,
+        1 => ConnectionStatusCode::InternalError,
+        2 => ConnectionStatusCode::InvalidArgument,
+        3 => ConnectionStatusCode::NetworkError,
+        4 => ConnectionStatusCode::Timeout,
+        5 => ConnectionStatusCode::Cancelled,
+        6 => ConnectionStatusCode::Rejected,
+        7 => ConnectionStatusCode::PlayerIdentityTokenExpired,
+        8 => ConnectionStatusCode::LoginTokenExpired,
+        9 => ConnectionStatusCode::CapacityExceeded,
+        10 => ConnectionStatusCode::RateExceeded,
+        11 => ConnectionStatusCode::ServerShutdown,
+        _ => return Err(invalid_data("invalid connection status code")),
+    })
+}
+
+fn log_level_to_u8(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::Debug => 0,
+        LogLevel::Info => 1,
+        LogLevel::Warn => 2,
+        LogLevel::Error => 3,
+        LogLevel::Fatal => 4,
+    }
+}
+
+fn log_level_from_u8(value: u8) -> io::Result<LogLevel> {
+    Ok(match value {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Info,
+        2 => LogLevel::Warn,
+        3 => LogLevel::Error,
+        4 => LogLevel::Fatal,
+        _ => return Err(invalid_data("invalid log level")),
+    })
+}
+
+fn authority_to_u8(authority: &Authority) -> u8 {
+    match authority {
+        Authority::NotAuthoritative => 0,
+        Authority::Authoritative => 1,
+        Authority::AuthorityLossImminent => 2,
+    }
+}
+
+fn authority_from_u8(value: u8) -> io::Result<Authority> {
+    Ok(match value {
+        0 => Authority::NotAuthoritative,
+        1 => Authority::Authoritative,
+        2 => Authority::AuthorityLossImminent,
+        _ => return Err(invalid_data("invalid authority")),
+    })
+}
+
+fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_i64<W: Write>(writer: &mut W, value: i64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f64<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, value: &[u8]) -> io::Result<()> {
+    write_len(writer, value.len())?;
+    writer.write_all(value)
+}
+
+/// Writes the length of a sequence, failing if it does not fit the u32 of the recording format.
+fn write_len<W: Write>(writer: &mut W, length: usize) -> io::Result<()> {
+    let length = u32::try_from(length).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "sequence too long to be recorded",
+        )
+    })?;
+    write_u32(writer, length)
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_bytes(writer, value.as_bytes())
+}
+
+fn write_option<W: Write, T, F>(writer: &mut W, value: Option<&T>, write_value: F) -> io::Result<()>
+where
+    T: ?Sized,
+    F: FnOnce(&mut W, &T) -> io::Result<()>,
+{
+    match value {
+        Some(value) => {
+            write_u8(writer, 1)?;
+            write_value(writer, value)
+        }
+        None => write_u8(writer, 0),
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buffer = [0; 2];
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_i64<R: Read>(reader: &mut R) -> io::Result<i64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(i64::from_le_bytes(buffer))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(f64::from_le_bytes(buffer))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let length = read_u32(reader)?;
+    // The buffer grows as bytes are read, so that a corrupted length fails with an early end of
+    // file instead of allocating up to 4 GiB upfront.
+    let mut buffer = Vec::new();
+    reader.take(u64::from(length)).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 != u64::from(length) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buffer)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid_data("invalid string"))
+}
+
+fn read_option<R: Read, T, F>(reader: &mut R, read_value: F) -> io::Result<Option<T>>
+where
+    F: FnOnce(&mut R) -> io::Result<T>,
+{
+    match read_u8(reader)? {
+        0 => Ok(None),
+        1 => read_value(reader).map(Some),
+        _ => Err(invalid_data("invalid option")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Coordinates, Position, PositionUpdate};
+    use crate::schema::{Component, Update};
+    use crate::worker::{CommandRequest, CommandResponse, ComponentData, ComponentUpdate};
+
+    fn debug_ops(op_list: &OpList) -> Vec<String> {
+        op_list.iter().map(|op| format!("{:?}", op)).collect()
+    }
+
+    fn ops() -> OpList {
+        OpList::new(vec![
+            WorkerOp::CriticalSection(CriticalSectionOp {
+                in_critical_section: true,
+            }),
+            WorkerOp::AddEntity(AddEntityOp { entity_id: 3 }),
+            WorkerOp::AuthorityChange(AuthorityChangeOp {
+                entity_id: 3,
+                component_id: 54,
+                authority: Authority::AuthorityLossImminent,
+            }),
+            WorkerOp::FlagUpdate(FlagUpdateOp {
+                name: "tick_rate".to_owned(),
+                value: None,
+            }),
+            WorkerOp::LogMessage(LogMessageOp {
+                level: LogLevel::Warn,
+                message: "low memory".to_owned(),
+            }),
+            WorkerOp::Metrics(MetricsOp {
+                metrics: Metrics {
+                    load: Some(0.5),
+                    gauge_metrics: vec![GaugeMetric {
+                        key: "entities".to_owned(),
+                        value: 2.0,
+                    }],
+                    histogram_metrics: vec![HistogramMetric {
+                        key: "latency".to_owned(),
+                        sum: 1.5,
+                        buckets: vec![HistogramMetricBucket {
+                            upper_bound: f64::INFINITY,
+                            samples: 2,
+                        }],
+                    }],
+                },
+            }),
+            WorkerOp::RemoveEntity(RemoveEntityOp { entity_id: 3 }),
+            WorkerOp::Disconnect(DisconnectOp {
+                status_code: ConnectionStatusCode::Timeout,
+                reason: "timed out".to_owned(),
+            }),
+        ])
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let op_list = ops();
+        let mut recorder = OpRecorder::new(Vec::new()).unwrap();
+        recorder.record(&op_list).unwrap();
+        recorder.record(&OpList::new(Vec::new())).unwrap();
+
+        let mut replayer = OpReplayer::new(recorder.writer.as_slice()).unwrap();
+        let first = replayer.next_op_list().unwrap().unwrap();
+        assert_eq!(debug_ops(&first.op_list), debug_ops(&op_list));
+        let second = replayer.next_op_list().unwrap().unwrap();
+        assert!(second.timestamp >= first.timestamp);
+        assert_eq!(second.op_list.len(), 0);
+        assert!(replayer.next_op_list().unwrap().is_none());
+    }
+
+    fn position(x: f64) -> Position {
+        Position {
+            coords: Coordinates { x, y: 2.0, z: 3.0 },
+        }
+    }
+
+    fn component_data(
+        schema_data: &mut Vec<OwnedSchemaData>,
+        position: &Position,
+    ) -> OpComponentData<'static> {
+        let data = ComponentData::new(Position::ID, position.to_data())
+            .into_schema_data(&[])
+            .unwrap();
+        schema_data.push(OwnedSchemaData::ComponentData(data.schema_type));
+        unsafe { OpComponentData::from_raw(data) }
+    }
+
+    fn schema_ops() -> OpList {
+        let mut schema_data = Vec::new();
+        let added = component_data(&mut schema_data, &position(1.0));
+        let update = ComponentUpdate::new(
+            Position::ID,
+            PositionUpdate {
+                coords: Some(position(4.0).coords),
+            }
+            .to_update(),
+        )
+        .into_schema_data(&[])
+        .unwrap();
+        schema_data.push(OwnedSchemaData::ComponentUpdate(update.schema_type));
+        let mut request = schema::CommandRequest::new();
+        request.get_object_mut().add_string(1, "ping");
+        let request = CommandRequest::new(1000, 1, request)
+            .into_schema_data(&[])
+            .unwrap();
+        schema_data.push(OwnedSchemaData::CommandRequest(request.schema_type));
+        let mut response = schema::CommandResponse::new();
+        response.get_object_mut().add_uint32(1, 42);
+        let response = CommandResponse::new(1000, 1, response)
+            .into_schema_data(&[])
+            .unwrap();
+        schema_data.push(OwnedSchemaData::CommandResponse(response.schema_type));
+        let queried = component_data(&mut schema_data, &position(5.0));
+        // A component only available through a user handle has no schema data.
+        let handle_only = unsafe {
+            OpComponentData::from_raw(Worker_ComponentData {
+                reserved: std::ptr::null_mut(),
+                component_id: 1000,
+                schema_type: std::ptr::null_mut(),
+                user_handle: std::ptr::null_mut(),
+            })
+        };
+
+        OpList::with_schema_data(
+            vec![
+                WorkerOp::AddComponent(AddComponentOp {
+                    entity_id: 3,
+                    data: added,
+                }),
+                WorkerOp::ComponentUpdate(ComponentUpdateOp {
+                    entity_id: 3,
+                    update: unsafe { OpComponentUpdate::from_raw(update) },
+                }),
+                WorkerOp::CommandRequest(CommandRequestOp {
+                    request_id: 1,
+                    entity_id: 3,
+                    timeout_millis: 500,
+                    caller_worker_id: "client".to_owned(),
+                    caller_attribute_set: WorkerAttributes {
+                        attribute_count: 1,
+                        attributes: vec!["client".to_owned()],
+                    },
+                    request: unsafe { OpCommandRequest::from_raw(request) },
+                }),
+                WorkerOp::CommandResponse(CommandResponseOp {
+                    request_id: 2,
+                    entity_id: 3,
+                    status_code: StatusCode::Success,
+                    message: String::new(),
+                    response: unsafe { OpCommandResponse::from_raw(response) },
+                }),
+                WorkerOp::EntityQueryResponse(EntityQueryResponseOp {
+                    request_id: 3,
+                    status_code: StatusCode::Success,
+                    message: String::new(),
+                    result_count: 1,
+                    results: vec![EntitySnapshot {
+                        entity_id: 3,
+                        components: vec![queried, handle_only],
+                    }],
+                }),
+            ],
+            schema_data,
+        )
+    }
+
+    #[test]
+    fn schema_data_round_trips() {
+        let op_list = schema_ops();
+        let mut recorder = OpRecorder::new(Vec::new()).unwrap();
+        recorder.record(&op_list).unwrap();
+
+        let mut replayer = OpReplayer::new(recorder.writer.as_slice()).unwrap();
+        let replayed = replayer.next_op_list().unwrap().unwrap().op_list;
+        let ops = replayed.iter().collect::<Vec<_>>();
+        match ops.as_slice() {
+            [WorkerOp::AddComponent(added), WorkerOp::ComponentUpdate(updated), WorkerOp::CommandRequest(request), WorkerOp::CommandResponse(response), WorkerOp::EntityQueryResponse(query)] =>
+            {
+                assert_eq!(added.data.read::<Position>(), Some(position(1.0)));
+                assert_eq!(
+                    updated.update.read::<Position>(),
+                    Some(PositionUpdate {
+                        coords: Some(position(4.0).coords)
+                    })
+                );
+                assert_eq!(request.request.get_object().unwrap().get_string(1), "ping");
+                assert_eq!(response.response.get_object().unwrap().get_uint32(1), 42);
+                let components = &query.results[0].components;
+                assert_eq!(components[0].read::<Position>(), Some(position(5.0)));
+                assert_eq!(components[1].component_id(), 1000);
+                assert!(components[1].get_fields().is_none());
+            }
+            _ => panic!("unexpected ops {:?}", debug_ops(&replayed)),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert!(OpReplayer::new(&b"SOPX\x01\x00"[..]).is_err());
+        assert!(OpReplayer::new(&b"SOPR\x02\x00"[..]).is_err());
+    }
+
+    #[test]
+    fn truncated_bytes_fail_without_allocating_their_length() {
+        let mut data = u32::MAX.to_le_bytes().to_vec();
+        data.extend_from_slice(b"abc");
+        let error = read_bytes(&mut data.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn truncated_recordings_fail() {
+        let mut recorder = OpRecorder::new(Vec::new()).unwrap();
+        recorder.record(&ops()).unwrap();
+        let mut data = recorder.writer;
+        data.truncate(data.len() - 4);
+        let mut replayer = OpReplayer::new(data.as_slice()).unwrap();
+        assert!(replayer.next_op_list().is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! Reference-counted handles to the data of ops, acquired from the SDK, so that it can be kept
+//! after the op list it was received in has been destroyed, without copying it.
+
+use crate::schema::{self, Component, ObjectRef, Update};
+use crate::worker::component_vtable::downcast_handle;
+use crate::worker::{
+    CommandIndex, CommandRequestHandle, CommandResponseHandle, ComponentDataHandle, ComponentId,
+    ComponentUpdateHandle,
+};
+use spatialos_sys::{
+    Schema_GetCommandRequestObject, Schema_GetCommandResponseObject, Schema_GetComponentDataFields,
+    Schema_GetComponentUpdateEvents, Schema_GetComponentUpdateFields,
+};
+use spatialos_sys::{
+    Worker_AcquireCommandRequest, Worker_AcquireCommandResponse, Worker_AcquireComponentData,
+    Worker_AcquireComponentUpdate, Worker_CommandRequest, Worker_CommandResponse,
+    Worker_ComponentData, Worker_ComponentUpdate, Worker_ReleaseCommandRequest,
+    Worker_ReleaseCommandResponse, Worker_ReleaseComponentData, Worker_ReleaseComponentUpdate,
+};
+use std::mem::ManuallyDrop;
+
+#[derive(Debug)]
+/// A reference to component data, obtained with OpComponentData::share or into_owned. Cloning it
+/// acquires another reference to the same data, which is freed once every reference has been
+/// dropped.
+pub struct SharedComponentData {
+    inner: *mut Worker_ComponentData,
+}
+
+#[derive(Debug)]
+/// A reference to a component update, obtained with OpComponentUpdate::share or into_owned.
+/// Cloning it acquires another reference to the same update, which is freed once every reference
+/// has been dropped.
+pub struct SharedComponentUpdate {
+    inner: *mut Worker_ComponentUpdate,
+}
+
+#[derive(Debug)]
+/// A reference to a command request, obtained with OpCommandRequest::share or into_owned. Cloning
+/// it acquires another reference to the same request, which is freed once every reference has been
+/// dropped.
+pub struct SharedCommandRequest {
+    inner: *mut Worker_CommandRequest,
+}
+
+#[derive(Debug)]
+/// A reference to a command response, obtained with OpCommandResponse::share or into_owned.
+/// Cloning it acquires another reference to the same response, which is freed once every reference
+/// has been dropped.
+pub struct SharedCommandResponse {
+    inner: *mut Worker_CommandResponse,
+}
+
+/// References are counted by the SDK, independently of the connection the data came from.
+unsafe impl Send for SharedComponentData {}
+unsafe impl Send for SharedComponentUpdate {}
+unsafe impl Send for SharedCommandRequest {}
+unsafe impl Send for SharedCommandResponse {}
+
+impl SharedComponentData {
+    /// Acquires a reference to the given data. Data which is not reference-counted yet, such as
+    /// the data of an op list, is copied.
+    pub(crate) fn acquire(data: &Worker_ComponentData) -> Self {
+        Self {
+            inner: unsafe { Worker_AcquireComponentData(data) },
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    /// Returns the fields of the component, or None if the data is only available through a user
+    /// handle.
+    pub fn get_fields(&self) -> Option<ObjectRef<'_>> {
+        component_data_fields(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut ComponentDataHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the data, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Deserializes the data into a component. Returns None if the data belongs to another
+    /// component, or is only available through a user handle.
+    pub fn read<C: Component>(&self) -> Option<C> {
+        read_component_data(self.raw())
+    }
+
+    pub(crate) fn raw(&self) -> &Worker_ComponentData {
+        unsafe { &*self.inner }
+    }
+}
+
+impl Clone for SharedComponentData {
+    fn clone(&self) -> Self {
+        Self::acquire(self.raw())
+    }
+}
+
+impl Drop for SharedComponentData {
+    fn drop(&mut self) {
+        unsafe { Worker_ReleaseComponentData(self.inner) }
+    }
+}
+
+impl SharedComponentUpdate {
+    /// Acquires a reference to the given update. Updates which are not reference-counted yet, such
+    /// as the updates of an op list, are copied.
+    pub(crate) fn acquire(update: &Worker_ComponentUpdate) -> Self {
+        Self {
+            inner: unsafe { Worker_AcquireComponentUpdate(update) },
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    /// Returns the fields set by the update, or None if the update is only available through a
+    /// user handle.
+    pub fn get_fields(&self) -> Option<ObjectRef<'_>> {
+        component_update_fields(self.raw())
+    }
+
+    /// Returns the events of the update, or None if the update is only available through a user
+    /// handle.
+    pub fn get_events(&self) -> Option<ObjectRef<'_>> {
+        component_update_events(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut ComponentUpdateHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the update, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    /// Deserializes the update of a component. Returns None if the update belongs to another
+    /// component, or is only available through a user handle.
+    pub fn read<C: Component>(&self) -> Option<C::Update> {
+        read_component_update::<C>(self.raw())
+    }
+
+    pub(crate) fn raw(&self) -> &Worker_ComponentUpdate {
+        unsafe { &*self.inner }
+    }
+}
+
+impl Clone for SharedComponentUpdate {
+    fn clone(&self) -> Self {
+        Self::acquire(self.raw())
+    }
+}
+
+impl Drop for SharedComponentUpdate {
+    fn drop(&mut self) {
+        unsafe { Worker_ReleaseComponentUpdate(self.inner) }
+    }
+}
+
+impl SharedCommandRequest {
+    /// Acquires a reference to the given request. Requests which are not reference-counted yet,
+    /// such as the requests of an op list, are copied.
+    pub(crate) fn acquire(request: &Worker_CommandRequest) -> Self {
+        Self {
+            inner: unsafe { Worker_AcquireCommandRequest(request) },
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    pub fn command_index(&self) -> CommandIndex {
+        self.raw().command_index
+    }
+
+    /// Returns the request object, or None if the request is only available through a user
+    /// handle.
+    pub fn get_object(&self) -> Option<ObjectRef<'_>> {
+        command_request_object(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut CommandRequestHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the request, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    pub(crate) fn raw(&self) -> &Worker_CommandRequest {
+        unsafe { &*self.inner }
+    }
+}
+
+impl Clone for SharedCommandRequest {
+    fn clone(&self) -> Self {
+        Self::acquire(self.raw())
+    }
+}
+
+impl Drop for SharedCommandRequest {
+    fn drop(&mut self) {
+        unsafe { Worker_ReleaseCommandRequest(self.inner) }
+    }
+}
+
+impl SharedCommandResponse {
+    /// Acquires a reference to the given response. Responses which are not reference-counted yet,
+    /// such as the responses of an op list, are copied.
+    pub(crate) fn acquire(response: &Worker_CommandResponse) -> Self {
+        Self {
+            inner: unsafe { Worker_AcquireCommandResponse(response) },
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.raw().component_id
+    }
+
+    pub fn command_index(&self) -> CommandIndex {
+        self.raw().command_index
+    }
+
+    /// Returns the response object, or None if the response is only available through a user
+    /// handle.
+    pub fn get_object(&self) -> Option<ObjectRef<'_>> {
+        command_response_object(self.raw())
+    }
+
+    pub fn user_handle(&self) -> *mut CommandResponseHandle {
+        self.raw().user_handle
+    }
+
+    /// Returns the value held by the user handle of the response, or None if there is none or it holds
+    /// a value of another type.
+    ///
+    /// # Safety
+    ///
+    /// The user handle must have been created by UserHandle or by the vtable of a
+    /// ComponentHandler, as it is when the connection only uses vtables from
+    /// ComponentVtable::from_handler.
+    pub unsafe fn get_handle<T: 'static>(&self) -> Option<&T> {
+        downcast_handle(self.user_handle())
+    }
+
+    pub(crate) fn raw(&self) -> &Worker_CommandResponse {
+        unsafe { &*self.inner }
+    }
+}
+
+impl Clone for SharedCommandResponse {
+    fn clone(&self) -> Self {
+        Self::acquire(self.raw())
+    }
+}
+
+impl Drop for SharedCommandResponse {
+    fn drop(&mut self) {
+        unsafe { Worker_ReleaseCommandResponse(self.inner) }
+    }
+}
+
+pub(crate) fn component_data_fields(data: &Worker_ComponentData) -> Option<ObjectRef<'_>> {
+    if data.schema_type.is_null() {
+        None
+    } else {
+        Some(unsafe { ObjectRef::from_raw(Schema_GetComponentDataFields(data.schema_type)) })
+    }
+}
+
+pub(crate) fn read_component_data<C: Component>(data: &Worker_ComponentData) -> Option<C> {
+    if data.component_id != C::ID || data.schema_type.is_null() {
+        return None;
+    }
+    component_data_fields(data).map(|fields| C::from_object(&fields))
+}
+
+pub(crate) fn component_update_fields(update: &Worker_ComponentUpdate) -> Option<ObjectRef<'_>> {
+    if update.schema_type.is_null() {
+        None
+    } else {
+        Some(unsafe { ObjectRef::from_raw(Schema_GetComponentUpdateFields(update.schema_type)) })
+    }
+}
+
+pub(crate) fn component_update_events(update: &Worker_ComponentUpdate) -> Option<ObjectRef<'_>> {
+    if update.schema_type.is_null() {
+        None
+    } else {
+        Some(unsafe { ObjectRef::from_raw(Schema_GetComponentUpdateEvents(update.schema_type)) })
+    }
+}
+
+pub(crate) fn read_component_update<C: Component>(
+    update: &Worker_ComponentUpdate,
+) -> Option<C::Update> {
+    if update.component_id != C::ID || update.schema_type.is_null() {
+        return None;
+    }
+    Some(C::Update::from_update(&ManuallyDrop::new(
+        schema::ComponentUpdate::from(update.schema_type),
+    )))
+}
+
+pub(crate) fn command_request_object(request: &Worker_CommandRequest) -> Option<ObjectRef<'_>> {
+    if request.schema_type.is_null() {
+        None
+    } else {
+        Some(unsafe { ObjectRef::from_raw(Schema_GetCommandRequestObject(request.schema_type)) })
+    }
+}
+
+pub(crate) fn command_response_object(response: &Worker_CommandResponse) -> Option<ObjectRef<'_>> {
+    if response.schema_type.is_null() {
+        None
+    } else {
+        Some(unsafe { ObjectRef::from_raw(Schema_GetCommandResponseObject(response.schema_type)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{Metadata, MetadataUpdate, Position};
+    use crate::worker::op::{OpComponentData, OpList, OwnedSchemaData};
+    use crate::worker::test_util::metadata;
+    use crate::worker::{ComponentData, ComponentUpdate};
+
+    #[test]
+    fn clones_outlive_the_reference_they_were_cloned_from() {
+        let data = ComponentData::new(Metadata::ID, metadata("tree").to_data())
+            .into_schema_data(&[])
+            .unwrap();
+        let op_list = OpList::with_schema_data(
+            Vec::new(),
+            vec![OwnedSchemaData::ComponentData(data.schema_type)],
+        );
+        let shared = unsafe { OpComponentData::from_raw(data) }.share();
+        drop(op_list);
+
+        let clone = shared.clone();
+        drop(shared);
+        assert_eq!(clone.component_id(), Metadata::ID);
+        assert_eq!(clone.read::<Metadata>(), Some(metadata("tree")));
+        let second = clone.clone();
+        drop(clone);
+        assert_eq!(second.read::<Metadata>(), Some(metadata("tree")));
+        assert_eq!(second.read::<Position>(), None);
+    }
+
+    #[test]
+    fn updates_are_read_for_their_component_only() {
+        let update = MetadataUpdate {
+            entity_type: Some("bush".to_owned()),
+        };
+        let raw = ComponentUpdate::new(Metadata::ID, update.to_update())
+            .into_schema_data(&[])
+            .unwrap();
+        let shared = SharedComponentUpdate::acquire(&raw);
+        let _op_list = OpList::with_schema_data(
+            Vec::new(),
+            vec![OwnedSchemaData::ComponentUpdate(raw.schema_type)],
+        );
+
+        assert_eq!(shared.clone().read::<Metadata>(), Some(update));
+        assert_eq!(shared.read::<Position>(), None);
+        assert!(shared.get_fields().is_some());
+
+        let handle_only = SharedComponentUpdate::acquire(&Worker_ComponentUpdate {
+            reserved: std::ptr::null_mut(),
+            component_id: Metadata::ID,
+            schema_type: std::ptr::null_mut(),
+            user_handle: std::ptr::null_mut(),
+        });
+        assert_eq!(handle_only.read::<Metadata>(), None);
+        assert!(handle_only.get_fields().is_none());
+        assert!(handle_only.get_events().is_none());
+    }
+}
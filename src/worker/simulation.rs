@@ -0,0 +1,1686 @@
+//! An in-memory stand-in for a SpatialOS deployment, to run integration tests involving several
+//! workers without a runtime or network access.
+//!
+//! A Simulation holds the entities of the world and hands out one SimulatedConnection per worker.
+//! It assigns authority over each entity component to a connected worker, routes component updates
+//! and commands between workers, and handles entity creation, deletion and queries. Every worker
+//! sees every entity: interest is not simulated.
+//!
+//! Authority over a component is granted to a worker satisfying the write ACL of the component, if
+//! the entity has an EntityAcl component which lists it, or else to a worker with the attribute of
+//! the first matching authority rule. Authority sticks to a worker while it remains eligible, and
+//! is handed over to the next eligible worker when it disconnects, which makes handoffs easy to
+//! test. A connected worker which is no longer eligible, for example after an EntityAcl update,
+//! is first sent AuthorityLossImminent, and keeps authority until it acknowledges the loss.
+
+use crate::improbable::{EntityAcl, Position};
+use crate::schema::{self, Component};
+use crate::worker::authority::{AuthorityLossHandlers, AuthorityTracker};
+use crate::worker::component_registry::ComponentRegistry;
+use crate::worker::component_vtable::ComponentVtable;
+use crate::worker::connection::{
+    CommandParameters, UpdateLoopback, UpdateParameters, WorkerConnection,
+};
+use crate::worker::constraint::Constraint;
+use crate::worker::log_message::LogMessage;
+use crate::worker::metrics::Metrics;
+use crate::worker::op::{
+    AddComponentOp, AddEntityOp, AuthorityChangeOp, CommandRequestOp, CommandResponseOp,
+    ComponentUpdateOp, CreateEntityResponseOp, DeleteEntityResponseOp, DisconnectOp,
+    EntityQueryResponseOp, EntitySnapshot, FlagUpdateOp, OpCommandRequest, OpCommandResponse,
+    OpComponentData, OpComponentUpdate, OpList, OwnedSchemaData, RemoveComponentOp, RemoveEntityOp,
+    ReserveEntityIdsResponseOp, WorkerOp,
+};
+use crate::worker::{
+    Authority, CommandIndex, CommandRequest, CommandResponse, ComponentData, ComponentId,
+    ComponentUpdate, ConnectionStatusCode, EntityId, EntityQuery, RequestId, ResultType,
+    StatusCode, WorkerAttributes,
+};
+use spatialos_sys::{
+    Schema_ApplyComponentUpdateToData, Schema_ComponentData, Schema_CopyComponentData,
+    Schema_CopyComponentUpdate, Schema_DestroyCommandRequest, Schema_DestroyCommandResponse,
+    Schema_DestroyComponentData, Schema_DestroyComponentUpdate, Worker_CommandResponse,
+    Worker_ComponentData, Worker_ComponentUpdate,
+};
+use std::cell::{RefCell, RefMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::mem::ManuallyDrop;
+use std::rc::Rc;
+
+type WorkerHandle = u64;
+
+/// Grants authority over a component to workers with the given attribute, for entities whose
+/// EntityAcl does not say otherwise.
+pub struct AuthorityRule {
+    pub component_id: ComponentId,
+    pub attribute: String,
+}
+
+/// An in-memory deployment. Cloning a simulation returns a handle to the same world.
+#[derive(Clone)]
+pub struct Simulation {
+    world: Rc<RefCell<World>>,
+}
+
+struct World {
+    entities: BTreeMap<EntityId, SimulatedEntity>,
+    workers: BTreeMap<WorkerHandle, SimulatedWorker>,
+    rules: Vec<AuthorityRule>,
+    flags: HashMap<String, String>,
+    commands: HashMap<RequestId, PendingCommand>,
+    log_messages: Vec<(String, LogMessage)>,
+    vtables: Vec<ComponentVtable>,
+    next_worker: WorkerHandle,
+    next_entity_id: EntityId,
+    next_request_id: RequestId,
+}
+
+#[derive(Default)]
+struct SimulatedEntity {
+    components: BTreeMap<ComponentId, *mut Schema_ComponentData>,
+    authority: BTreeMap<ComponentId, WorkerHandle>,
+    /// The components whose authoritative worker was sent AuthorityLossImminent, and has not
+    /// acknowledged it yet.
+    handoffs: BTreeSet<ComponentId>,
+}
+
+struct SimulatedWorker {
+    worker_id: String,
+    attributes: Vec<String>,
+    connected: bool,
+    ops: Vec<WorkerOp<'static>>,
+    schema_data: Vec<OwnedSchemaData>,
+}
+
+struct PendingCommand {
+    caller: WorkerHandle,
+    target: WorkerHandle,
+    entity_id: EntityId,
+    component_id: ComponentId,
+    command_index: CommandIndex,
+}
+
+/// The connection of a worker to a Simulation. Dropping it removes the worker from the
+/// simulation.
+pub struct SimulatedConnection {
+    world: Rc<RefCell<World>>,
+    handle: WorkerHandle,
+    authority: AuthorityTracker,
+    authority_loss_handlers: AuthorityLossHandlers,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self {
+            world: Rc::new(RefCell::new(World {
+                entities: BTreeMap::new(),
+                workers: BTreeMap::new(),
+                rules: Vec::new(),
+                flags: HashMap::new(),
+                commands: HashMap::new(),
+                log_messages: Vec::new(),
+                vtables: Vec::new(),
+                next_worker: 0,
+                next_entity_id: 1,
+                next_request_id: 1,
+            })),
+        }
+    }
+
+    /// Grants authority over the given component to workers with the given attribute.
+    pub fn add_authority_rule<S: Into<String>>(&self, component_id: ComponentId, attribute: S) {
+        let mut world = self.world.borrow_mut();
+        world.rules.push(AuthorityRule {
+            component_id,
+            attribute: attribute.into(),
+        });
+        world.update_authority();
+    }
+
+    /// Sets the component vtables to those of every component registered with a handler, so that
+    /// workers and tests can send data backed by the user handles of those components. The data is
+    /// serialized through the vtables as the SDK would, and workers receive it as schema data.
+    pub fn set_component_vtables(&self, registry: &ComponentRegistry) {
+        self.world.borrow_mut().vtables = registry.vtables();
+    }
+
+    /// Connects a new worker to the simulation. The first op list of the worker contains every
+    /// entity of the world.
+    pub fn connect<S: Into<String>>(
+        &self,
+        worker_id: S,
+        attributes: Vec<String>,
+    ) -> SimulatedConnection {
+        let mut world = self.world.borrow_mut();
+        let handle = world.next_worker;
+        world.next_worker += 1;
+        let mut worker = SimulatedWorker {
+            worker_id: worker_id.into(),
+            attributes,
+            connected: true,
+            ops: Vec::new(),
+            schema_data: Vec::new(),
+        };
+        for (&entity_id, entity) in &world.entities {
+            worker.add_entity(entity_id, entity);
+        }
+        world.workers.insert(handle, worker);
+        world.update_authority();
+        SimulatedConnection {
+            world: self.world.clone(),
+            handle,
+            authority: AuthorityTracker::new(),
+            authority_loss_handlers: AuthorityLossHandlers::new(),
+        }
+    }
+
+    /// Disconnects a worker, as if it had crashed or been stopped. The worker receives a
+    /// Disconnect op, and authority over its components is handed over to other workers.
+    pub fn disconnect<S: AsRef<str>>(&self, worker_id: S) {
+        let mut world = self.world.borrow_mut();
+        let handle = world
+            .workers
+            .iter()
+            .find(|(_, worker)| worker.connected && worker.worker_id == worker_id.as_ref())
+            .map(|(&handle, _)| handle);
+        if let Some(handle) = handle {
+            let worker = world.workers.get_mut(&handle).unwrap();
+            worker.connected = false;
+            worker.ops.push(WorkerOp::Disconnect(DisconnectOp {
+                status_code: ConnectionStatusCode::ServerShutdown,
+                reason: "The worker was disconnected from the simulation.".to_owned(),
+            }));
+            world.fail_commands(handle);
+            world.update_authority();
+        }
+    }
+
+    /// Adds an entity to the world, as if it had been loaded from a snapshot. Returns the ID of
+    /// the new entity.
+    ///
+    /// Panics if a component is backed by a user handle, and its vtable was not given to
+    /// set_component_vtables.
+    pub fn create_entity(&self, components: Vec<ComponentData>) -> EntityId {
+        let mut world = self.world.borrow_mut();
+        let components = world.serialize_components(components).unwrap_or_else(|| {
+            panic!("a component is backed by a user handle of a component without a vtable")
+        });
+        let entity_id = world.allocate_entity_id();
+        world.insert_entity(entity_id, components);
+        entity_id
+    }
+
+    /// Removes an entity from the world. Returns false if the entity does not exist.
+    pub fn delete_entity(&self, entity_id: EntityId) -> bool {
+        self.world.borrow_mut().remove_entity(entity_id)
+    }
+
+    /// Sets the value of a worker flag, and sends the matching FlagUpdate op to every worker. A
+    /// None value deletes the flag.
+    pub fn set_flag<S: Into<String>>(&self, name: S, value: Option<String>) {
+        let mut world = self.world.borrow_mut();
+        let name = name.into();
+        match &value {
+            Some(value) => world.flags.insert(name.clone(), value.clone()),
+            None => world.flags.remove(&name),
+        };
+        for worker in world.workers.values_mut().filter(|worker| worker.connected) {
+            worker.ops.push(WorkerOp::FlagUpdate(FlagUpdateOp {
+                name: name.clone(),
+                value: value.clone(),
+            }));
+        }
+    }
+
+    /// Returns the IDs of every entity in the world.
+    pub fn entity_ids(&self) -> Vec<EntityId> {
+        self.world.borrow().entities.keys().copied().collect()
+    }
+
+    /// Returns the ID of the worker authoritative over a component, if any.
+    pub fn authoritative_worker(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<String> {
+        let world = self.world.borrow();
+        let handle = world
+            .entities
+            .get(&entity_id)?
+            .authority
+            .get(&component_id)?;
+        Some(world.workers[handle].worker_id.clone())
+    }
+
+    /// Returns the current value of a component, if the entity has it.
+    pub fn read_component<C: Component>(&self, entity_id: EntityId) -> Option<C> {
+        let world = self.world.borrow();
+        let data = *world.entities.get(&entity_id)?.components.get(&C::ID)?;
+        Some(read_component_data(data))
+    }
+
+    /// Returns every log message sent by the workers since the previous call, along with the ID of
+    /// the worker which sent it.
+    pub fn take_log_messages(&self) -> Vec<(String, LogMessage)> {
+        std::mem::take(&mut self.world.borrow_mut().log_messages)
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    fn allocate_entity_id(&mut self) -> EntityId {
+        let entity_id = self.next_entity_id;
+        self.next_entity_id += 1;
+        entity_id
+    }
+
+    fn allocate_request_id(&mut self) -> RequestId {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        request_id
+    }
+
+    fn connected_workers(&mut self) -> impl Iterator<Item = &mut SimulatedWorker> {
+        self.workers.values_mut().filter(|worker| worker.connected)
+    }
+
+    /// Converts components into schema data owned by the caller. Returns None if a component is
+    /// backed by a user handle of a component without a vtable.
+    fn serialize_components(
+        &self,
+        components: Vec<ComponentData>,
+    ) -> Option<Vec<Worker_ComponentData>> {
+        let mut serialized = Vec::with_capacity(components.len());
+        for component in components {
+            match component.into_schema_data(&self.vtables) {
+                Some(data) => serialized.push(data),
+                None => {
+                    for data in serialized {
+                        unsafe { Schema_DestroyComponentData(data.schema_type) }
+                    }
+                    return None;
+                }
+            }
+        }
+        Some(serialized)
+    }
+
+    fn insert_entity(&mut self, entity_id: EntityId, components: Vec<Worker_ComponentData>) {
+        let mut entity = SimulatedEntity::default();
+        for data in components {
+            if let Some(previous) = entity
+                .components
+                .insert(data.component_id, data.schema_type)
+            {
+                unsafe { Schema_DestroyComponentData(previous) }
+            }
+        }
+        for worker in self.connected_workers() {
+            worker.add_entity(entity_id, &entity);
+        }
+        self.entities.insert(entity_id, entity);
+        self.next_entity_id = self.next_entity_id.max(entity_id + 1);
+        self.update_authority();
+    }
+
+    fn remove_entity(&mut self, entity_id: EntityId) -> bool {
+        let entity = match self.entities.remove(&entity_id) {
+            Some(entity) => entity,
+            None => return false,
+        };
+        for (&handle, worker) in self.workers.iter_mut().filter(|(_, w)| w.connected) {
+            for &component_id in entity.components.keys() {
+                if entity.authority.get(&component_id) == Some(&handle) {
+                    worker.authority_change(entity_id, component_id, Authority::NotAuthoritative);
+                }
+                worker
+                    .ops
+                    .push(WorkerOp::RemoveComponent(RemoveComponentOp {
+                        entity_id,
+                        component_id,
+                    }));
+            }
+            worker
+                .ops
+                .push(WorkerOp::RemoveEntity(RemoveEntityOp { entity_id }));
+        }
+        true
+    }
+
+    /// Grants authority over every component to an eligible worker, and sends AuthorityChange ops
+    /// to the workers gaining or losing authority. Connected workers which are no longer eligible
+    /// keep authority until they acknowledge its imminent loss.
+    fn update_authority(&mut self) {
+        let mut changes = Vec::new();
+        let mut handoffs = Vec::new();
+        for (&entity_id, entity) in &self.entities {
+            let acl = entity
+                .components
+                .get(&EntityAcl::ID)
+                .map(|&data| read_component_data::<EntityAcl>(data));
+            let is_eligible = |handle: &WorkerHandle, component_id: ComponentId| {
+                self.workers.get(handle).is_some_and(|worker| {
+                    worker.connected && worker.can_write(&self.rules, acl.as_ref(), component_id)
+                })
+            };
+            let is_connected = |handle: &WorkerHandle| {
+                self.workers
+                    .get(handle)
+                    .is_some_and(|worker| worker.connected)
+            };
+            for &component_id in entity.components.keys() {
+                let current = entity.authority.get(&component_id).copied();
+                let handing_off = entity.handoffs.contains(&component_id);
+                match current {
+                    Some(handle) if is_eligible(&handle, component_id) => {
+                        // A worker which becomes eligible again during a handoff keeps authority.
+                        if handing_off {
+                            handoffs.push((entity_id, component_id, handle, false));
+                        }
+                    }
+                    Some(handle) if is_connected(&handle) => {
+                        if !handing_off {
+                            handoffs.push((entity_id, component_id, handle, true));
+                        }
+                    }
+                    _ => {
+                        let authority = self
+                            .workers
+                            .keys()
+                            .find(|handle| is_eligible(handle, component_id))
+                            .copied();
+                        if authority != current {
+                            changes.push((entity_id, component_id, authority));
+                        }
+                    }
+                }
+            }
+        }
+        for (entity_id, component_id, handle, imminent) in handoffs {
+            let entity = self.entities.get_mut(&entity_id).unwrap();
+            let authority = if imminent {
+                entity.handoffs.insert(component_id);
+                Authority::AuthorityLossImminent
+            } else {
+                entity.handoffs.remove(&component_id);
+                Authority::Authoritative
+            };
+            let worker = self.workers.get_mut(&handle).unwrap();
+            worker.authority_change(entity_id, component_id, authority);
+        }
+        // The previous workers of these components are disconnected, so they are not notified.
+        for (entity_id, component_id, authority) in changes {
+            let entity = self.entities.get_mut(&entity_id).unwrap();
+            entity.handoffs.remove(&component_id);
+            match authority {
+                Some(handle) => entity.authority.insert(component_id, handle),
+                None => entity.authority.remove(&component_id),
+            };
+            if let Some(worker) = authority.and_then(|handle| self.workers.get_mut(&handle)) {
+                worker.authority_change(entity_id, component_id, Authority::Authoritative);
+            }
+        }
+    }
+
+    /// Completes the handoff of a component once the worker losing authority over it has
+    /// acknowledged the loss, and grants authority to the next eligible worker.
+    fn complete_handoff(
+        &mut self,
+        handle: WorkerHandle,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        let entity = match self.entities.get_mut(&entity_id) {
+            Some(entity) => entity,
+            None => return,
+        };
+        if entity.authority.get(&component_id) != Some(&handle)
+            || !entity.handoffs.remove(&component_id)
+        {
+            return;
+        }
+        entity.authority.remove(&component_id);
+        if let Some(worker) = self.workers.get_mut(&handle) {
+            worker.authority_change(entity_id, component_id, Authority::NotAuthoritative);
+        }
+        self.update_authority();
+    }
+
+    /// Fails the commands sent to a worker which is leaving the simulation.
+    fn fail_commands(&mut self, handle: WorkerHandle) {
+        let request_ids = self
+            .commands
+            .iter()
+            .filter(|(_, command)| command.target == handle)
+            .map(|(&request_id, _)| request_id)
+            .collect::<Vec<_>>();
+        for request_id in request_ids {
+            let command = self.commands.remove(&request_id).unwrap();
+            self.respond_to_command(
+                request_id,
+                command,
+                StatusCode::AuthorityLost,
+                "The authoritative worker disconnected while handling the request.",
+                None,
+            );
+        }
+        self.commands.retain(|_, command| command.caller != handle);
+    }
+
+    fn respond_to_command(
+        &mut self,
+        request_id: RequestId,
+        command: PendingCommand,
+        status_code: StatusCode,
+        message: &str,
+        response: Option<Worker_CommandResponse>,
+    ) {
+        let caller = match self.workers.get_mut(&command.caller) {
+            Some(caller) if caller.connected => caller,
+            _ => {
+                if let Some(response) = response {
+                    unsafe { Schema_DestroyCommandResponse(response.schema_type) }
+                }
+                return;
+            }
+        };
+        let response = match response {
+            Some(response) => {
+                caller
+                    .schema_data
+                    .push(OwnedSchemaData::CommandResponse(response.schema_type));
+                response
+            }
+            None => Worker_CommandResponse {
+                reserved: std::ptr::null_mut(),
+                component_id: command.component_id,
+                command_index: command.command_index,
+                schema_type: std::ptr::null_mut(),
+                user_handle: std::ptr::null_mut(),
+            },
+        };
+        caller
+            .ops
+            .push(WorkerOp::CommandResponse(CommandResponseOp {
+                request_id,
+                entity_id: command.entity_id,
+                status_code,
+                message: message.to_owned(),
+                response: unsafe { OpCommandResponse::from_raw(response) },
+            }));
+    }
+
+    fn matches(
+        &self,
+        entity_id: EntityId,
+        entity: &SimulatedEntity,
+        constraint: &Constraint,
+    ) -> bool {
+        match constraint {
+            Constraint::EntityId(constraint) => constraint.entity_id == entity_id,
+            Constraint::Component(constraint) => {
+                entity.components.contains_key(&constraint.component_id)
+            }
+            Constraint::Sphere(constraint) => match entity.components.get(&Position::ID) {
+                Some(&data) => {
+                    let coords = read_component_data::<Position>(data).coords;
+                    let (x, y, z) = (
+                        coords.x - constraint.x,
+                        coords.y - constraint.y,
+                        coords.z - constraint.z,
+                    );
+                    x * x + y * y + z * z <= constraint.radius * constraint.radius
+                }
+                None => false,
+            },
+            Constraint::And(constraint) => constraint
+                .constraints
+                .iter()
+                .all(|constraint| self.matches(entity_id, entity, constraint)),
+            Constraint::Or(constraint) => constraint
+                .constraints
+                .iter()
+                .any(|constraint| self.matches(entity_id, entity, constraint)),
+            Constraint::Not(constraint) => !self.matches(entity_id, entity, &constraint.constraint),
+        }
+    }
+}
+
+impl Drop for SimulatedEntity {
+    fn drop(&mut self) {
+        for &data in self.components.values() {
+            unsafe { Schema_DestroyComponentData(data) }
+        }
+    }
+}
+
+impl SimulatedWorker {
+    fn can_write(
+        &self,
+        rules: &[AuthorityRule],
+        acl: Option<&EntityAcl>,
+        component_id: ComponentId,
+    ) -> bool {
+        if let Some(requirement) = acl.and_then(|acl| acl.component_write_acl.get(&component_id)) {
+            return requirement.attribute_set.iter().any(|set| {
+                set.attribute
+                    .iter()
+                    .all(|attribute| self.attributes.contains(attribute))
+            });
+        }
+        rules
+            .iter()
+            .find(|rule| rule.component_id == component_id)
+            .is_some_and(|rule| self.attributes.contains(&rule.attribute))
+    }
+
+    fn copy_component_data(
+        &mut self,
+        component_id: ComponentId,
+        data: *mut Schema_ComponentData,
+    ) -> Worker_ComponentData {
+        let schema_type = unsafe { Schema_CopyComponentData(data) };
+        self.schema_data
+            .push(OwnedSchemaData::ComponentData(schema_type));
+        Worker_ComponentData {
+            reserved: std::ptr::null_mut(),
+            component_id,
+            schema_type,
+            user_handle: std::ptr::null_mut(),
+        }
+    }
+
+    fn add_entity(&mut self, entity_id: EntityId, entity: &SimulatedEntity) {
+        self.ops
+            .push(WorkerOp::AddEntity(AddEntityOp { entity_id }));
+        for (&component_id, &data) in &entity.components {
+            self.add_component(entity_id, component_id, data);
+        }
+    }
+
+    fn add_component(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        data: *mut Schema_ComponentData,
+    ) {
+        let data = self.copy_component_data(component_id, data);
+        self.ops.push(WorkerOp::AddComponent(AddComponentOp {
+            entity_id,
+            data: unsafe { OpComponentData::from_raw(data) },
+        }));
+    }
+
+    fn authority_change(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        authority: Authority,
+    ) {
+        self.ops.push(WorkerOp::AuthorityChange(AuthorityChangeOp {
+            entity_id,
+            component_id,
+            authority,
+        }));
+    }
+}
+
+impl Drop for SimulatedWorker {
+    fn drop(&mut self) {
+        // Destroys the schema data of the ops which were never received.
+        OpList::with_schema_data(Vec::new(), std::mem::take(&mut self.schema_data));
+    }
+}
+
+impl SimulatedConnection {
+    fn world(&self) -> RefMut<'_, World> {
+        self.world.borrow_mut()
+    }
+
+    fn respond(&self, op: WorkerOp<'static>) {
+        let mut world = self.world();
+        let worker = world.workers.get_mut(&self.handle).unwrap();
+        if worker.connected {
+            worker.ops.push(op);
+        }
+    }
+
+    /// Returns whether a change sent by this connection with the given parameters is delivered
+    /// to the worker with the given handle.
+    fn loops_back(&self, handle: WorkerHandle, parameters: UpdateParameters) -> bool {
+        handle != self.handle || parameters.loopback != UpdateLoopback::None
+    }
+}
+
+impl Drop for SimulatedConnection {
+    fn drop(&mut self) {
+        let mut world = self.world();
+        world.workers.remove(&self.handle);
+        world.fail_commands(self.handle);
+        world.update_authority();
+    }
+}
+
+impl WorkerConnection for SimulatedConnection {
+    /// Returns every op sent to the worker since the previous call, regardless of the timeout.
+    fn get_op_list(&mut self, _timeout_millis: u32) -> OpList {
+        let op_list = {
+            let mut world = self.world();
+            let worker = world.workers.get_mut(&self.handle).unwrap();
+            OpList::with_schema_data(
+                std::mem::take(&mut worker.ops),
+                std::mem::take(&mut worker.schema_data),
+            )
+        };
+        self.authority.process_ops(&op_list);
+        let mut authority_loss_handlers = std::mem::take(&mut self.authority_loss_handlers);
+        authority_loss_handlers.process_ops(&op_list, self);
+        self.authority_loss_handlers = authority_loss_handlers;
+        op_list
+    }
+
+    fn authority_tracker(&self) -> &AuthorityTracker {
+        &self.authority
+    }
+
+    fn authority_loss_handlers(&mut self) -> &mut AuthorityLossHandlers {
+        &mut self.authority_loss_handlers
+    }
+
+    fn is_connected(&self) -> bool {
+        self.world().workers[&self.handle].connected
+    }
+
+    fn worker_id(&self) -> String {
+        self.world().workers[&self.handle].worker_id.clone()
+    }
+
+    fn attributes(&self) -> WorkerAttributes {
+        let attributes = self.world().workers[&self.handle].attributes.clone();
+        WorkerAttributes {
+            attribute_count: attributes.len() as u32,
+            attributes,
+        }
+    }
+
+    fn flag(&self, name: &str) -> Option<String> {
+        self.world().flags.get(name).cloned()
+    }
+
+    fn send_log_message(&self, log_message: LogMessage) {
+        let mut world = self.world();
+        let worker_id = world.workers[&self.handle].worker_id.clone();
+        world.log_messages.push((worker_id, log_message));
+    }
+
+    fn send_metrics(&self, _metrics: Metrics) {}
+
+    fn send_reserve_entity_ids_request(
+        &self,
+        number_of_entity_ids: u32,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let mut world = self.world();
+        let request_id = world.allocate_request_id();
+        let first_entity_id = world.next_entity_id;
+        world.next_entity_id += number_of_entity_ids as EntityId;
+        drop(world);
+        self.respond(WorkerOp::ReserveEntityIdsResponse(
+            ReserveEntityIdsResponseOp {
+                request_id,
+                status_code: StatusCode::Success,
+                message: String::new(),
+                first_entity_id,
+                number_of_entity_ids,
+            },
+        ));
+        request_id
+    }
+
+    fn send_create_entity_request(
+        &self,
+        components: Vec<ComponentData>,
+        entity_id: Option<EntityId>,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let mut world = self.world();
+        let request_id = world.allocate_request_id();
+        let entity_id = entity_id.unwrap_or_else(|| world.allocate_entity_id());
+        let (status_code, message) = if world.entities.contains_key(&entity_id) {
+            drop(components);
+            (
+                StatusCode::ApplicationError,
+                format!("Entity {} already exists.", entity_id),
+            )
+        } else {
+            match world.serialize_components(components) {
+                Some(components) => {
+                    world.insert_entity(entity_id, components);
+                    (StatusCode::Success, String::new())
+                }
+                None => (
+                    StatusCode::ApplicationError,
+                    "A component is backed by a user handle which cannot be serialized.".to_owned(),
+                ),
+            }
+        };
+        drop(world);
+        self.respond(WorkerOp::CreateEntityResponse(CreateEntityResponseOp {
+            request_id,
+            status_code,
+            message,
+            entity_id,
+        }));
+        request_id
+    }
+
+    fn send_delete_entity_request(
+        &self,
+        entity_id: EntityId,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let mut world = self.world();
+        let request_id = world.allocate_request_id();
+        let (status_code, message) = if world.remove_entity(entity_id) {
+            (StatusCode::Success, String::new())
+        } else {
+            (
+                StatusCode::NotFound,
+                format!("Entity {} does not exist.", entity_id),
+            )
+        };
+        drop(world);
+        self.respond(WorkerOp::DeleteEntityResponse(DeleteEntityResponseOp {
+            request_id,
+            entity_id,
+            status_code,
+            message,
+        }));
+        request_id
+    }
+
+    fn send_entity_query_request(
+        &self,
+        entity_query: EntityQuery,
+        _timeout_millis: Option<u32>,
+    ) -> RequestId {
+        let mut world = self.world();
+        let request_id = world.allocate_request_id();
+        let world = &mut *world;
+        let entities = world
+            .entities
+            .iter()
+            .filter(|(&entity_id, entity)| {
+                world.matches(entity_id, entity, &entity_query.constraint)
+            })
+            .collect::<Vec<_>>();
+        let result_count = entities.len() as u32;
+        let worker = world.workers.get_mut(&self.handle).unwrap();
+        let results = match entity_query.result_type {
+            ResultType::Count => Vec::new(),
+            ResultType::Snapshot => entities
+                .into_iter()
+                .map(|(&entity_id, entity)| {
+                    let components = entity
+                        .components
+                        .iter()
+                        .filter(|(component_id, _)| {
+                            entity_query.snapshot_result_type_component_ids.is_empty()
+                                || entity_query
+                                    .snapshot_result_type_component_ids
+                                    .contains(component_id)
+                        })
+                        .map(|(&component_id, &data)| unsafe {
+                            OpComponentData::from_raw(
+                                worker.copy_component_data(component_id, data),
+                            )
+                        })
+                        .collect();
+                    EntitySnapshot {
+                        entity_id,
+                        components,
+                    }
+                })
+                .collect(),
+        };
+        if worker.connected {
+            worker
+                .ops
+                .push(WorkerOp::EntityQueryResponse(EntityQueryResponseOp {
+                    request_id,
+                    status_code: StatusCode::Success,
+                    message: String::new(),
+                    result_count,
+                    results,
+                }));
+        }
+        request_id
+    }
+
+    /// Applies the update and sends it to every worker. Returns false if the worker is not
+    /// authoritative over the component, or if the update is backed by a user handle which cannot
+    /// be serialized.
+    fn send_component_update(&self, entity_id: EntityId, update: ComponentUpdate) -> bool {
+        self.send_component_update_with_parameters(entity_id, update, UpdateParameters::default())
+    }
+
+    /// Applies the update and sends it to every worker, including the sender unless its loopback
+    /// is UpdateLoopback::None. Returns false if the worker is not authoritative over the
+    /// component, or if the update is backed by a user handle which cannot be serialized.
+    fn send_component_update_with_parameters(
+        &self,
+        entity_id: EntityId,
+        update: ComponentUpdate,
+        parameters: UpdateParameters,
+    ) -> bool {
+        self.authority.check_update(entity_id, update.component_id);
+        let mut world = self.world();
+        let update = match update.into_schema_data(&world.vtables) {
+            Some(update) => update,
+            None => return false,
+        };
+        let component_id = update.component_id;
+        let data = match world.entities.get(&entity_id) {
+            Some(entity) if entity.authority.get(&component_id) == Some(&self.handle) => {
+                entity.components[&component_id]
+            }
+            _ => {
+                unsafe { Schema_DestroyComponentUpdate(update.schema_type) }
+                return false;
+            }
+        };
+        unsafe { Schema_ApplyComponentUpdateToData(update.schema_type, data) };
+        for (&handle, worker) in world.workers.iter_mut() {
+            if !worker.connected || !self.loops_back(handle, parameters) {
+                continue;
+            }
+            let schema_type = unsafe { Schema_CopyComponentUpdate(update.schema_type) };
+            worker
+                .schema_data
+                .push(OwnedSchemaData::ComponentUpdate(schema_type));
+            worker
+                .ops
+                .push(WorkerOp::ComponentUpdate(ComponentUpdateOp {
+                    entity_id,
+                    update: unsafe {
+                        OpComponentUpdate::from_raw(Worker_ComponentUpdate {
+                            schema_type,
+                            ..update
+                        })
+                    },
+                }));
+        }
+        unsafe { Schema_DestroyComponentUpdate(update.schema_type) };
+        if component_id == EntityAcl::ID {
+            world.update_authority();
+        }
+        true
+    }
+
+    /// Returns false if the entity does not exist or already has the component, or if the data is
+    /// backed by a user handle which cannot be serialized.
+    fn send_add_component(&self, entity_id: EntityId, component_data: ComponentData) -> bool {
+        self.send_add_component_with_parameters(
+            entity_id,
+            component_data,
+            UpdateParameters::default(),
+        )
+    }
+
+    /// Returns false if the entity does not exist or already has the component, or if the data is
+    /// backed by a user handle which cannot be serialized. The sender does not receive the added
+    /// component if its loopback is UpdateLoopback::None.
+    fn send_add_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_data: ComponentData,
+        parameters: UpdateParameters,
+    ) -> bool {
+        let mut world = self.world();
+        let data = match component_data.into_schema_data(&world.vtables) {
+            Some(data) => data,
+            None => return false,
+        };
+        let entity = match world.entities.get_mut(&entity_id) {
+            Some(entity) if !entity.components.contains_key(&data.component_id) => entity,
+            _ => {
+                unsafe { Schema_DestroyComponentData(data.schema_type) }
+                return false;
+            }
+        };
+        entity
+            .components
+            .insert(data.component_id, data.schema_type);
+        for (&handle, worker) in world.workers.iter_mut() {
+            if worker.connected && self.loops_back(handle, parameters) {
+                worker.add_component(entity_id, data.component_id, data.schema_type);
+            }
+        }
+        world.update_authority();
+        true
+    }
+
+    /// Returns false if the entity does not have the component.
+    fn send_remove_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.send_remove_component_with_parameters(
+            entity_id,
+            component_id,
+            UpdateParameters::default(),
+        )
+    }
+
+    /// Returns false if the entity does not have the component. The sender does not receive the
+    /// removal if its loopback is UpdateLoopback::None.
+    fn send_remove_component_with_parameters(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        parameters: UpdateParameters,
+    ) -> bool {
+        let mut world = self.world();
+        let (data, authority) = match world.entities.get_mut(&entity_id) {
+            Some(entity) => match entity.components.remove(&component_id) {
+                Some(data) => {
+                    entity.handoffs.remove(&component_id);
+                    (data, entity.authority.remove(&component_id))
+                }
+                None => return false,
+            },
+            None => return false,
+        };
+        unsafe { Schema_DestroyComponentData(data) };
+        for (&handle, worker) in world.workers.iter_mut().filter(|(_, w)| w.connected) {
+            if authority == Some(handle) {
+                worker.authority_change(entity_id, component_id, Authority::NotAuthoritative);
+            }
+            if self.loops_back(handle, parameters) {
+                worker
+                    .ops
+                    .push(WorkerOp::RemoveComponent(RemoveComponentOp {
+                        entity_id,
+                        component_id,
+                    }));
+            }
+        }
+        if component_id == EntityAcl::ID {
+            world.update_authority();
+        }
+        true
+    }
+
+    /// Sends the request to the worker authoritative over the component of the command. The
+    /// request fails with NotFound if the entity does not have the component, with AuthorityLost
+    /// if no worker is authoritative over it, or with ApplicationError if it is backed by a user
+    /// handle which cannot be serialized.
+    fn send_command_request(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+    ) -> RequestId {
+        self.send_command_request_with_parameters(
+            entity_id,
+            request,
+            timeout_millis,
+            CommandParameters::default(),
+        )
+    }
+
+    /// Sends the request as send_command_request does. Requests are always routed through the
+    /// simulated runtime, whether or not they allow short-circuiting.
+    fn send_command_request_with_parameters(
+        &self,
+        entity_id: EntityId,
+        request: CommandRequest,
+        timeout_millis: Option<u32>,
+        _parameters: CommandParameters,
+    ) -> RequestId {
+        let mut world = self.world();
+        let world = &mut *world;
+        let request_id = world.allocate_request_id();
+        let command = PendingCommand {
+            caller: self.handle,
+            target: 0,
+            entity_id,
+            component_id: request.component_id,
+            command_index: request.command_index,
+        };
+        let request = match request.into_schema_data(&world.vtables) {
+            Some(request) => request,
+            None => {
+                world.respond_to_command(
+                    request_id,
+                    command,
+                    StatusCode::ApplicationError,
+                    "The request is backed by a user handle which cannot be serialized.",
+                    None,
+                );
+                return request_id;
+            }
+        };
+        let target = match world.entities.get(&entity_id) {
+            Some(entity) if entity.components.contains_key(&request.component_id) => {
+                entity.authority.get(&request.component_id).copied()
+            }
+            _ => {
+                unsafe { Schema_DestroyCommandRequest(request.schema_type) }
+                world.respond_to_command(
+                    request_id,
+                    command,
+                    StatusCode::NotFound,
+                    "The entity does not exist or does not have the component.",
+                    None,
+                );
+                return request_id;
+            }
+        };
+        let target = match target {
+            Some(target) => target,
+            None => {
+                unsafe { Schema_DestroyCommandRequest(request.schema_type) }
+                world.respond_to_command(
+                    request_id,
+                    command,
+                    StatusCode::AuthorityLost,
+                    "No worker is authoritative over the component.",
+                    None,
+                );
+                return request_id;
+            }
+        };
+        let caller = &world.workers[&self.handle];
+        let caller_worker_id = caller.worker_id.clone();
+        let caller_attribute_set = WorkerAttributes {
+            attribute_count: caller.attributes.len() as u32,
+            attributes: caller.attributes.clone(),
+        };
+        let worker = world.workers.get_mut(&target).unwrap();
+        worker
+            .schema_data
+            .push(OwnedSchemaData::CommandRequest(request.schema_type));
+        worker.ops.push(WorkerOp::CommandRequest(CommandRequestOp {
+            request_id,
+            entity_id,
+            timeout_millis: timeout_millis.unwrap_or(0),
+            caller_worker_id,
+            caller_attribute_set,
+            request: unsafe { OpCommandRequest::from_raw(request) },
+        }));
+        world
+            .commands
+            .insert(request_id, PendingCommand { target, ..command });
+        request_id
+    }
+
+    /// Returns false if the worker did not receive the request, or already responded to it, or if
+    /// the response is backed by a user handle which cannot be serialized.
+    fn send_command_response(&self, request_id: RequestId, response: CommandResponse) -> bool {
+        let mut world = self.world();
+        let response = match response.into_schema_data(&world.vtables) {
+            Some(response) => response,
+            None => return false,
+        };
+        match world.commands.remove(&request_id) {
+            Some(command) if command.target == self.handle => {
+                world.respond_to_command(
+                    request_id,
+                    command,
+                    StatusCode::Success,
+                    "",
+                    Some(response),
+                );
+                true
+            }
+            command => {
+                if let Some(command) = command {
+                    world.commands.insert(request_id, command);
+                }
+                unsafe { Schema_DestroyCommandResponse(response.schema_type) }
+                false
+            }
+        }
+    }
+
+    /// Returns false if the worker did not receive the request, or already responded to it.
+    fn send_command_failure(&self, request_id: RequestId, message: &str) -> bool {
+        let mut world = self.world();
+        match world.commands.remove(&request_id) {
+            Some(command) if command.target == self.handle => {
+                world.respond_to_command(
+                    request_id,
+                    command,
+                    StatusCode::ApplicationError,
+                    message,
+                    None,
+                );
+                true
+            }
+            command => {
+                if let Some(command) = command {
+                    world.commands.insert(request_id, command);
+                }
+                false
+            }
+        }
+    }
+
+    /// Completes the handoff of the component to the next eligible worker, if the loss of
+    /// authority of this worker over it is imminent.
+    fn send_authority_loss_imminent_acknowledgement(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) {
+        self.world()
+            .complete_handoff(self.handle, entity_id, component_id);
+    }
+}
+
+/// Reads a component from schema data owned by the simulation.
+fn read_component_data<C: Component>(data: *mut Schema_ComponentData) -> C {
+    C::from_data(&ManuallyDrop::new(schema::ComponentData::from(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::improbable::{
+        Coordinates, EntityAclUpdate, Metadata, MetadataUpdate, WorkerAttributeSet,
+        WorkerRequirementSet,
+    };
+    use crate::schema::Update;
+    use crate::worker::constraint::SphereConstraint;
+    use crate::worker::test_util::{metadata, MetadataHandler};
+
+    /// Describes the ops received by the worker, leaving out their payloads.
+    fn receive(connection: &mut SimulatedConnection) -> Vec<String> {
+        connection
+            .get_op_list(0)
+            .iter()
+            .map(|op| match op {
+                WorkerOp::AddEntity(op) => format!("AddEntity {}", op.entity_id),
+                WorkerOp::RemoveEntity(op) => format!("RemoveEntity {}", op.entity_id),
+                WorkerOp::AddComponent(op) => {
+                    format!("AddComponent {} {}", op.entity_id, op.data.component_id())
+                }
+                WorkerOp::RemoveComponent(op) => {
+                    format!("RemoveComponent {} {}", op.entity_id, op.component_id)
+                }
+                WorkerOp::AuthorityChange(op) => format!(
+                    "AuthorityChange {} {} {:?}",
+                    op.entity_id, op.component_id, op.authority
+                ),
+                WorkerOp::ComponentUpdate(op) => {
+                    format!(
+                        "ComponentUpdate {} {}",
+                        op.entity_id,
+                        op.update.component_id()
+                    )
+                }
+                WorkerOp::CommandRequest(op) => format!("CommandRequest {}", op.request_id),
+                WorkerOp::CommandResponse(op) => {
+                    format!("CommandResponse {} {:?}", op.request_id, op.status_code)
+                }
+                WorkerOp::EntityQueryResponse(op) => {
+                    format!("EntityQueryResponse {}", op.result_count)
+                }
+                WorkerOp::Disconnect(_) => "Disconnect".to_owned(),
+                op => format!("{:?}", op),
+            })
+            .collect()
+    }
+
+    fn position(x: f64) -> ComponentData {
+        Position {
+            coords: Coordinates { x, y: 0.0, z: 0.0 },
+        }
+        .to_component_data()
+    }
+
+    /// Grants write access to Metadata to the workers with the given attribute.
+    fn metadata_write_acl(attribute: &str) -> BTreeMap<ComponentId, WorkerRequirementSet> {
+        let mut write_acl = BTreeMap::new();
+        write_acl.insert(
+            Metadata::ID,
+            WorkerRequirementSet {
+                attribute_set: vec![WorkerAttributeSet {
+                    attribute: vec![attribute.to_owned()],
+                }],
+            },
+        );
+        write_acl
+    }
+
+    fn metadata_acl(attribute: &str) -> ComponentData {
+        EntityAcl {
+            read_acl: WorkerRequirementSet {
+                attribute_set: Vec::new(),
+            },
+            component_write_acl: metadata_write_acl(attribute),
+        }
+        .to_component_data()
+    }
+
+    fn rename(connection: &SimulatedConnection, entity_id: EntityId, entity_type: &str) -> bool {
+        let update = MetadataUpdate {
+            entity_type: Some(entity_type.to_owned()),
+        };
+        connection.send_component_update(
+            entity_id,
+            ComponentUpdate::new(Metadata::ID, update.to_update()),
+        )
+    }
+
+    #[test]
+    fn workers_receive_entities_and_authority() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+
+        assert_eq!(
+            receive(&mut server),
+            vec![
+                format!("AddEntity {}", entity_id),
+                format!("AddComponent {} 53", entity_id),
+                format!("AuthorityChange {} 53 Authoritative", entity_id),
+            ]
+        );
+        assert_eq!(
+            receive(&mut client),
+            vec![
+                format!("AddEntity {}", entity_id),
+                format!("AddComponent {} 53", entity_id),
+            ]
+        );
+        assert_eq!(
+            simulation.authoritative_worker(entity_id, Metadata::ID),
+            Some("server-1".to_owned())
+        );
+    }
+
+    #[test]
+    fn updates_require_authority_and_reach_every_worker() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+        receive(&mut server);
+        receive(&mut client);
+
+        assert!(!rename(&client, entity_id, "rock"));
+        assert!(rename(&server, entity_id, "bush"));
+        assert_eq!(
+            simulation.read_component::<Metadata>(entity_id),
+            Some(Metadata {
+                entity_type: "bush".to_owned()
+            })
+        );
+        let update = format!("ComponentUpdate {} 53", entity_id);
+        assert_eq!(receive(&mut server), vec![update.clone()]);
+        let op_list = client.get_op_list(0);
+        match op_list.iter().next() {
+            Some(WorkerOp::ComponentUpdate(op)) => {
+                let update = op.update.read::<Metadata>().unwrap();
+                assert_eq!(update.entity_type.as_deref(), Some("bush"));
+            }
+            op => panic!("unexpected op {:?}", op),
+        }
+    }
+
+    #[test]
+    fn changes_without_loopback_skip_the_sender() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+        receive(&mut server);
+        receive(&mut client);
+        let parameters = UpdateParameters {
+            loopback: UpdateLoopback::None,
+        };
+
+        let update = MetadataUpdate {
+            entity_type: Some("bush".to_owned()),
+        };
+        assert!(server.send_component_update_with_parameters(
+            entity_id,
+            ComponentUpdate::new(Metadata::ID, update.to_update()),
+            parameters,
+        ));
+        assert!(server.send_add_component_with_parameters(entity_id, position(1.0), parameters));
+        assert!(server.send_remove_component_with_parameters(entity_id, Position::ID, parameters));
+        assert!(receive(&mut server).is_empty());
+        assert_eq!(
+            receive(&mut client),
+            vec![
+                format!("ComponentUpdate {} 53", entity_id),
+                format!("AddComponent {} 54", entity_id),
+                format!("RemoveComponent {} 54", entity_id),
+            ]
+        );
+        assert_eq!(
+            simulation.read_component::<Metadata>(entity_id),
+            Some(Metadata {
+                entity_type: "bush".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn authority_is_handed_over_when_a_worker_disconnects() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut first = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut second = simulation.connect("server-2", vec!["server".to_owned()]);
+        receive(&mut first);
+        assert_eq!(receive(&mut second).len(), 2);
+
+        simulation.disconnect("server-1");
+        assert_eq!(receive(&mut first), vec!["Disconnect".to_owned()]);
+        assert!(!first.is_connected());
+        assert_eq!(
+            receive(&mut second),
+            vec![format!("AuthorityChange {} 53 Authoritative", entity_id)]
+        );
+        assert_eq!(
+            simulation.authoritative_worker(entity_id, Metadata::ID),
+            Some("server-2".to_owned())
+        );
+    }
+
+    #[test]
+    fn entity_acl_overrides_authority_rules() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![
+            metadata("tree").to_component_data(),
+            metadata_acl("client"),
+        ]);
+        let _server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let _client = simulation.connect("client-1", vec!["client".to_owned()]);
+        assert_eq!(
+            simulation.authoritative_worker(entity_id, Metadata::ID),
+            Some("client-1".to_owned())
+        );
+    }
+
+    #[test]
+    fn authority_is_handed_over_once_the_loss_is_acknowledged() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(EntityAcl::ID, "server");
+        let entity_id = simulation.create_entity(vec![
+            metadata("tree").to_component_data(),
+            metadata_acl("client"),
+        ]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", vec!["client".to_owned()]);
+        receive(&mut server);
+        receive(&mut client);
+
+        let update = EntityAclUpdate {
+            read_acl: None,
+            component_write_acl: Some(metadata_write_acl("server")),
+        };
+        assert!(server.send_component_update(
+            entity_id,
+            ComponentUpdate::new(EntityAcl::ID, update.to_update()),
+        ));
+        assert_eq!(
+            receive(&mut client),
+            vec![
+                format!("ComponentUpdate {} 50", entity_id),
+                format!("AuthorityChange {} 53 AuthorityLossImminent", entity_id),
+            ]
+        );
+        assert_eq!(
+            receive(&mut server),
+            vec![format!("ComponentUpdate {} 50", entity_id)]
+        );
+
+        // The client keeps authority, to send its final updates, until it acknowledges the loss.
+        assert!(rename(&client, entity_id, "stump"));
+        assert!(!rename(&server, entity_id, "rock"));
+        assert!(client.acknowledge_authority_loss(entity_id, Metadata::ID));
+        assert_eq!(
+            receive(&mut client),
+            vec![
+                format!("ComponentUpdate {} 53", entity_id),
+                format!("AuthorityChange {} 53 NotAuthoritative", entity_id),
+            ]
+        );
+        assert_eq!(
+            receive(&mut server),
+            vec![
+                format!("ComponentUpdate {} 53", entity_id),
+                format!("AuthorityChange {} 53 Authoritative", entity_id),
+            ]
+        );
+        assert_eq!(
+            simulation.authoritative_worker(entity_id, Metadata::ID),
+            Some("server-1".to_owned())
+        );
+        assert!(!client.acknowledge_authority_loss(entity_id, Metadata::ID));
+    }
+
+    #[test]
+    fn commands_are_routed_to_the_authoritative_worker() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+        receive(&mut server);
+        receive(&mut client);
+
+        let request = CommandRequest::new(Metadata::ID, 1, schema::CommandRequest::new());
+        let request_id = client.send_command_request(entity_id, request, None);
+        assert_eq!(
+            receive(&mut server),
+            vec![format!("CommandRequest {}", request_id)]
+        );
+        assert!(!client.send_command_failure(request_id, "not the target"));
+        let response = CommandResponse::new(Metadata::ID, 1, schema::CommandResponse::new());
+        assert!(server.send_command_response(request_id, response));
+        assert_eq!(
+            receive(&mut client),
+            vec![format!("CommandResponse {} Success", request_id)]
+        );
+
+        let request = CommandRequest::new(Metadata::ID, 1, schema::CommandRequest::new());
+        let request_id = client.send_command_request(entity_id + 1, request, None);
+        assert_eq!(
+            receive(&mut client),
+            vec![format!("CommandResponse {} NotFound", request_id)]
+        );
+    }
+
+    #[test]
+    fn pending_commands_fail_when_the_target_disconnects() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+        receive(&mut client);
+
+        let request = CommandRequest::new(Metadata::ID, 1, schema::CommandRequest::new());
+        let request_id = client.send_command_request(entity_id, request, None);
+        drop(server);
+        assert_eq!(
+            receive(&mut client),
+            vec![format!("CommandResponse {} AuthorityLost", request_id)]
+        );
+    }
+
+    #[test]
+    fn entities_can_be_created_deleted_and_queried() {
+        let simulation = Simulation::new();
+        let near = simulation.create_entity(vec![position(1.0)]);
+        simulation.create_entity(vec![position(10.0)]);
+        let mut worker = simulation.connect("worker-1", Vec::new());
+        receive(&mut worker);
+
+        let query = EntityQuery {
+            constraint: Constraint::Sphere(SphereConstraint {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                radius: 2.0,
+            }),
+            result_type: ResultType::Count,
+            snapshot_result_type_component_ids: Vec::new(),
+        };
+        worker.send_entity_query_request(query, None);
+        assert_eq!(
+            receive(&mut worker),
+            vec!["EntityQueryResponse 1".to_owned()]
+        );
+
+        worker.send_delete_entity_request(near, None);
+        let ops = receive(&mut worker);
+        assert!(ops.contains(&format!("RemoveEntity {}", near)));
+        assert!(!simulation.entity_ids().contains(&near));
+
+        let request_id = worker.send_create_entity_request(
+            vec![metadata("tree").to_component_data()],
+            None,
+            None,
+        );
+        let ops = receive(&mut worker);
+        assert_eq!(ops.len(), 3);
+        assert!(ops[2].contains(&format!("request_id: {}", request_id)));
+        assert_eq!(simulation.entity_ids().len(), 2);
+    }
+
+    #[test]
+    fn flags_and_log_messages() {
+        let simulation = Simulation::new();
+        let mut worker = simulation.connect("worker-1", Vec::new());
+        simulation.set_flag("tick_rate", Some("30".to_owned()));
+        assert_eq!(worker.flag("tick_rate"), Some("30".to_owned()));
+        assert_eq!(receive(&mut worker).len(), 1);
+
+        worker.send_log_message(LogMessage::new(
+            crate::worker::LogLevel::Info,
+            "test",
+            "hello",
+            None,
+        ));
+        let log_messages = simulation.take_log_messages();
+        assert_eq!(log_messages.len(), 1);
+        assert_eq!(log_messages[0].0, "worker-1");
+        assert_eq!(log_messages[0].1.message, "hello");
+    }
+
+    fn handler_registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<Metadata>();
+        registry.register_handler::<MetadataHandler>().unwrap();
+        registry
+    }
+
+    #[test]
+    fn user_handles_are_serialized_through_the_vtables() {
+        let simulation = Simulation::new();
+        simulation.set_component_vtables(&handler_registry());
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id =
+            simulation.create_entity(vec![ComponentData::from_handle::<MetadataHandler>(
+                Metadata {
+                    entity_type: "tree".to_owned(),
+                },
+            )]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        let mut client = simulation.connect("client-1", Vec::new());
+        receive(&mut server);
+        receive(&mut client);
+
+        let update = MetadataUpdate {
+            entity_type: Some("rock".to_owned()),
+        };
+        assert!(server.send_component_update(
+            entity_id,
+            ComponentUpdate::from_handle::<MetadataHandler>(update)
+        ));
+        assert_eq!(
+            simulation.read_component::<Metadata>(entity_id),
+            Some(Metadata {
+                entity_type: "rock".to_owned()
+            })
+        );
+
+        let request_id = client.send_command_request(
+            entity_id,
+            CommandRequest::from_handle::<MetadataHandler>(1, "ping".to_owned()),
+            None,
+        );
+        let op_list = server.get_op_list(0);
+        let request = op_list
+            .iter()
+            .find_map(|op| match op {
+                WorkerOp::CommandRequest(op) => Some(op),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(request.request.get_object().unwrap().get_string(1), "ping");
+        assert!(server.send_command_response(
+            request.request_id,
+            CommandResponse::from_handle::<MetadataHandler>(1, "pong".to_owned())
+        ));
+        let op_list = client.get_op_list(0);
+        let response = op_list
+            .iter()
+            .find_map(|op| match op {
+                WorkerOp::CommandResponse(op) if op.request_id == request_id => Some(op),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            response.response.get_object().unwrap().get_string(1),
+            "pong"
+        );
+    }
+
+    #[test]
+    fn user_handles_without_a_vtable_are_rejected() {
+        let simulation = Simulation::new();
+        simulation.add_authority_rule(Metadata::ID, "server");
+        let entity_id = simulation.create_entity(vec![metadata("tree").to_component_data()]);
+        let mut server = simulation.connect("server-1", vec!["server".to_owned()]);
+        receive(&mut server);
+
+        let update = MetadataUpdate {
+            entity_type: Some("rock".to_owned()),
+        };
+        assert!(!server.send_component_update(
+            entity_id,
+            ComponentUpdate::from_handle::<MetadataHandler>(update)
+        ));
+        let request_id = server.send_create_entity_request(
+            vec![ComponentData::from_handle::<MetadataHandler>(
+                Metadata::default(),
+            )],
+            None,
+            None,
+        );
+        let op_list = server.get_op_list(0);
+        match op_list.iter().collect::<Vec<_>>().as_slice() {
+            [WorkerOp::CreateEntityResponse(response)] => {
+                assert_eq!(response.request_id, request_id);
+                assert!(matches!(response.status_code, StatusCode::ApplicationError));
+            }
+            ops => panic!("unexpected ops {:?}", ops),
+        }
+        assert_eq!(simulation.entity_ids(), vec![entity_id]);
+    }
+}
@@ -0,0 +1,67 @@
+//! Helpers shared by the tests of the worker modules.
+
+use crate::improbable::{Metadata, MetadataUpdate};
+use crate::schema::{self, Component, Update};
+use crate::worker::component_vtable::ComponentHandler;
+use crate::worker::{CommandIndex, ComponentId};
+
+/// Returns the Metadata of an entity of the given type.
+pub fn metadata(entity_type: &str) -> Metadata {
+    Metadata {
+        entity_type: entity_type.to_owned(),
+    }
+}
+
+/// Handles Metadata through user handles. Its commands take and return a string.
+pub struct MetadataHandler;
+
+impl ComponentHandler for MetadataHandler {
+    const ID: ComponentId = Metadata::ID;
+
+    type Data = Metadata;
+    type Update = MetadataUpdate;
+    type CommandRequest = String;
+    type CommandResponse = String;
+
+    fn deserialize_data(data: &schema::ComponentData) -> Option<Metadata> {
+        Some(Metadata::from_data(data))
+    }
+
+    fn serialize_data(data: &Metadata) -> schema::ComponentData {
+        data.to_data()
+    }
+
+    fn deserialize_update(update: &schema::ComponentUpdate) -> Option<MetadataUpdate> {
+        Some(MetadataUpdate::from_update(update))
+    }
+
+    fn serialize_update(update: &MetadataUpdate) -> schema::ComponentUpdate {
+        update.to_update()
+    }
+
+    fn deserialize_command_request(
+        _: CommandIndex,
+        request: &schema::CommandRequest,
+    ) -> Option<String> {
+        Some(request.get_object().get_string(1))
+    }
+
+    fn serialize_command_request(_: CommandIndex, request: &String) -> schema::CommandRequest {
+        let mut serialized = schema::CommandRequest::new();
+        serialized.get_object_mut().add_string(1, request);
+        serialized
+    }
+
+    fn deserialize_command_response(
+        _: CommandIndex,
+        response: &schema::CommandResponse,
+    ) -> Option<String> {
+        Some(response.get_object().get_string(1))
+    }
+
+    fn serialize_command_response(_: CommandIndex, response: &String) -> schema::CommandResponse {
+        let mut serialized = schema::CommandResponse::new();
+        serialized.get_object_mut().add_string(1, response);
+        serialized
+    }
+}